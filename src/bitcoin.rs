@@ -1,3 +1,5 @@
+pub mod fee;
+
 use crate::dai;
 use crate::dai::ATTOS_IN_DAI_EXP;
 use crate::rate::Rate;
@@ -31,6 +33,10 @@ impl Amount {
         self.0.as_btc()
     }
 
+    pub fn checked_sub(self, rhs: Self) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
     /// Allow to know the worth of self in dai using the given conversion rate.
     /// Truncation may be done during the conversion to allow a result in attodai.
     pub fn worth_in(&self, btc_to_dai_rate: Rate) -> dai::Amount {
@@ -46,6 +52,14 @@ impl Amount {
     }
 }
 
+impl std::ops::Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Amount(self.0 + rhs.0)
+    }
+}
+
 impl std::ops::Sub for Amount {
     type Output = Amount;
 
@@ -0,0 +1,261 @@
+use crate::bitcoin::Amount;
+use crate::bitcoind::{self, EstimateMode};
+use crate::config::BitcoinFees;
+use comit::Timestamp;
+use std::cmp::{max, min};
+
+/// Approximate virtual size of a transaction funding an HTLC from the
+/// wallet: one P2WPKH input, the HTLC output and a change output.
+const FUND_TRANSACTION_VSIZE: u64 = 154;
+
+/// Approximate virtual size of a transaction spending an HTLC to a
+/// single output. The redeem witness, which includes the secret, is
+/// the larger one.
+const SPEND_TRANSACTION_VSIZE: u64 = 200;
+
+/// Approximate virtual size of a child transaction spending a single
+/// P2WPKH output back to the wallet.
+const CHILD_TRANSACTION_VSIZE: u64 = 110;
+
+/// How long before its deadline a transaction is rebroadcast with the
+/// maximum fee rate, in seconds.
+const URGENCY_WINDOW: u32 = 60 * 60;
+
+/// How many blocks a refund has to be mined in, counting from when it
+/// is first broadcast. The HTLC has expired already, only from then on
+/// does the fee rate go up to the maximum.
+const REFUND_BLOCKS: u32 = 12;
+
+/// The average time between two blocks, in seconds.
+const BLOCK_INTERVAL: u32 = 10 * 60;
+
+/// Decides which fee rate to pay for Bitcoin transactions, based on
+/// bitcoind's `estimatesmartfee`.
+#[derive(Clone, Debug)]
+pub struct Estimator {
+    client: bitcoind::Client,
+    fees: BitcoinFees,
+}
+
+impl Estimator {
+    pub fn new(client: bitcoind::Client, fees: BitcoinFees) -> Self {
+        Estimator { client, fees }
+    }
+
+    /// The fee rate of transactions funding an HTLC, per vbyte.
+    pub async fn fund_vbyte_rate(&self) -> anyhow::Result<::bitcoin::Amount> {
+        self.vbyte_rate(self.fees.fund_target).await
+    }
+
+    /// The fee rate of transactions redeeming or refunding an HTLC,
+    /// per vbyte.
+    pub async fn spend_vbyte_rate(&self) -> anyhow::Result<::bitcoin::Amount> {
+        self.vbyte_rate(self.fees.spend_target).await
+    }
+
+    /// The most nectar pays in fees when selling bitcoin: it funds the
+    /// HTLC and, if the swap does not go through, refunds it.
+    pub async fn max_fees_per_swap(&self) -> anyhow::Result<Amount> {
+        let fund = self.fund_vbyte_rate().await?.as_sat() * FUND_TRANSACTION_VSIZE;
+        let spend = self.spend_vbyte_rate().await?.as_sat() * SPEND_TRANSACTION_VSIZE;
+
+        Ok(Amount::from_sat(fund + spend))
+    }
+
+    /// The fee rate to raise a transaction to when it was not
+    /// confirmed in time at `vbyte_rate`.
+    ///
+    /// The rate goes up by half at every bump and to the maximum once
+    /// `now` is close to `deadline`.
+    pub fn bumped_vbyte_rate(
+        &self,
+        vbyte_rate: ::bitcoin::Amount,
+        now: Timestamp,
+        deadline: Timestamp,
+    ) -> ::bitcoin::Amount {
+        ::bitcoin::Amount::from_sat(bumped(
+            vbyte_rate.as_sat(),
+            u32::from(now),
+            u32::from(deadline),
+            &self.fees,
+        ))
+    }
+
+    async fn vbyte_rate(&self, conf_target: u32) -> anyhow::Result<::bitcoin::Amount> {
+        let estimate = self
+            .client
+            .estimate_smart_fee(conf_target, Some(EstimateMode::Conservative))
+            .await?;
+
+        let sat_per_vbyte = match estimate.fee_rate {
+            Some(btc_per_kvb) => sat_per_vbyte(btc_per_kvb)?,
+            // Paying too much is better than missing an expiry
+            None => {
+                tracing::warn!(
+                    "bitcoind could not estimate the fee rate for {} blocks, paying the maximum: {:?}",
+                    conf_target,
+                    estimate.errors
+                );
+                self.fees.max_sat_per_vbyte
+            }
+        };
+
+        Ok(::bitcoin::Amount::from_sat(bounded(
+            sat_per_vbyte,
+            &self.fees,
+        )))
+    }
+}
+
+/// bitcoind estimates fee rates in BTC/kvB. The rate is rounded up so
+/// as not to fall below the estimate.
+fn sat_per_vbyte(btc_per_kvb: f64) -> anyhow::Result<u64> {
+    let sat_per_kvb = ::bitcoin::Amount::from_btc(btc_per_kvb)?.as_sat();
+
+    Ok((sat_per_kvb + 999) / 1000)
+}
+
+fn bumped(sat_per_vbyte: u64, now: u32, deadline: u32, fees: &BitcoinFees) -> u64 {
+    if now.saturating_add(URGENCY_WINDOW) >= deadline {
+        return fees.max_sat_per_vbyte;
+    }
+
+    // A replacement must pay at least one more sat per vbyte (BIP 125)
+    let raised = max(sat_per_vbyte + sat_per_vbyte / 2, sat_per_vbyte + 1);
+
+    bounded(raised, fees)
+}
+
+/// The time by which a refund first broadcast at `broadcast` must be
+/// mined, on the same clock.
+pub fn refund_deadline(broadcast: Timestamp) -> Timestamp {
+    Timestamp::from(u32::from(broadcast).saturating_add(REFUND_BLOCKS * BLOCK_INTERVAL))
+}
+
+/// The fee a child transaction has to pay for it and its unconfirmed
+/// parent, paying `parent_vbyte_rate`, to pay `vbyte_rate` together.
+pub fn child_fee(
+    parent: &::bitcoin::Transaction,
+    parent_vbyte_rate: ::bitcoin::Amount,
+    vbyte_rate: ::bitcoin::Amount,
+) -> ::bitcoin::Amount {
+    let parent_vsize = (parent.get_weight() as u64 + 3) / 4;
+    let package = vbyte_rate.as_sat() * (parent_vsize + CHILD_TRANSACTION_VSIZE);
+    let paid = parent_vbyte_rate.as_sat() * parent_vsize;
+
+    ::bitcoin::Amount::from_sat(package.saturating_sub(paid))
+}
+
+fn bounded(sat_per_vbyte: u64, fees: &BitcoinFees) -> u64 {
+    min(
+        max(sat_per_vbyte, fees.min_sat_per_vbyte),
+        fees.max_sat_per_vbyte,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_btc_per_kvb_rounding_up() {
+        assert_eq!(sat_per_vbyte(0.000_12).unwrap(), 12);
+        assert_eq!(sat_per_vbyte(0.000_123_45).unwrap(), 13);
+        assert_eq!(sat_per_vbyte(0.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn fee_rate_is_kept_within_bounds() {
+        let fees = BitcoinFees {
+            min_sat_per_vbyte: 2,
+            max_sat_per_vbyte: 50,
+            ..BitcoinFees::DEFAULT
+        };
+
+        assert_eq!(bounded(1, &fees), 2);
+        assert_eq!(bounded(20, &fees), 20);
+        assert_eq!(bounded(80, &fees), 50);
+    }
+
+    #[test]
+    fn bumped_fee_rate_goes_up_by_half() {
+        let fees = BitcoinFees {
+            min_sat_per_vbyte: 1,
+            max_sat_per_vbyte: 50,
+            ..BitcoinFees::DEFAULT
+        };
+        let deadline = 100_000;
+
+        assert_eq!(bumped(10, 0, deadline, &fees), 15);
+        assert_eq!(bumped(1, 0, deadline, &fees), 2);
+        assert_eq!(bumped(40, 0, deadline, &fees), 50);
+    }
+
+    #[test]
+    fn bumped_fee_rate_is_the_maximum_close_to_the_deadline() {
+        let fees = BitcoinFees {
+            max_sat_per_vbyte: 50,
+            ..BitcoinFees::DEFAULT
+        };
+        let deadline = 100_000;
+
+        assert_eq!(bumped(10, deadline - URGENCY_WINDOW, deadline, &fees), 50);
+        assert_eq!(bumped(10, deadline + 1, deadline, &fees), 50);
+    }
+
+    #[test]
+    fn refund_is_not_urgent_when_first_broadcast() {
+        let fees = BitcoinFees {
+            max_sat_per_vbyte: 50,
+            ..BitcoinFees::DEFAULT
+        };
+        let broadcast = 100_000;
+        let deadline = u32::from(refund_deadline(Timestamp::from(broadcast)));
+
+        assert_eq!(bumped(10, broadcast, deadline, &fees), 15);
+        assert_eq!(bumped(10, deadline - URGENCY_WINDOW, deadline, &fees), 50);
+    }
+
+    #[test]
+    fn child_pays_for_the_whole_package() {
+        let parent = ::bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        let parent_vsize = (parent.get_weight() as u64 + 3) / 4;
+
+        let fee = child_fee(
+            &parent,
+            ::bitcoin::Amount::from_sat(2),
+            ::bitcoin::Amount::from_sat(10),
+        );
+
+        assert_eq!(
+            fee.as_sat(),
+            10 * (parent_vsize + CHILD_TRANSACTION_VSIZE) - 2 * parent_vsize
+        );
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod docker_tests {
+    use super::*;
+    use crate::test_harness::BitcoinBlockchain;
+    use testcontainers::clients;
+
+    #[tokio::test]
+    async fn given_no_fee_data_then_pay_the_maximum() {
+        let tc_client = clients::Cli::default();
+        let blockchain = BitcoinBlockchain::new(&tc_client).unwrap();
+        let estimator = Estimator::new(
+            bitcoind::Client::new(blockchain.node_url.clone()),
+            BitcoinFees::DEFAULT,
+        );
+
+        let rate = estimator.spend_vbyte_rate().await.unwrap();
+
+        assert_eq!(rate.as_sat(), BitcoinFees::DEFAULT.max_sat_per_vbyte);
+    }
+}
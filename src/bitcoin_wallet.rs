@@ -1,33 +1,16 @@
 use crate::bitcoind;
 use crate::bitcoind::WalletInfoResponse;
+use crate::seed::Seed;
 use ::bitcoin::hash_types::PubkeyHash;
 use ::bitcoin::hashes::Hash;
-use ::bitcoin::secp256k1;
-use ::bitcoin::secp256k1::constants::SECRET_KEY_SIZE;
+use ::bitcoin::util::address::Payload;
 use ::bitcoin::Address;
 use ::bitcoin::Network;
-use bitcoin::{Amount, PrivateKey};
-use rand::prelude::*;
-use reqwest::Url;
+use bitcoin::{Amount, OutPoint, PrivateKey, Script, Transaction, TxIn, TxOut, Txid};
+use std::convert::TryFrom;
 
-// TODO: Go in its own module
 #[derive(Debug, Clone)]
-struct Seed([u8; SECRET_KEY_SIZE]);
-
-impl Seed {
-    pub fn new() -> Self {
-        let mut bytes = [0u8; SECRET_KEY_SIZE];
-
-        rand::thread_rng().fill_bytes(&mut bytes);
-        Seed(bytes)
-    }
-
-    pub fn secret_key(&self) -> anyhow::Result<secp256k1::SecretKey> {
-        Ok(secp256k1::SecretKey::from_slice(&self.0)?)
-    }
-}
-
-struct Wallet {
+pub struct Wallet {
     /// The wallet is named `nectar_x` with `x` being the first 4 byte of the public key hash
     name: String,
     bitcoind_client: bitcoind::Client,
@@ -35,15 +18,17 @@ struct Wallet {
 }
 
 impl Wallet {
-    pub fn new(seed: Seed, url: Url, network: Network) -> anyhow::Result<Wallet> {
+    pub fn new(
+        seed: Seed,
+        bitcoind_client: bitcoind::Client,
+        network: Network,
+    ) -> anyhow::Result<Wallet> {
         let private_key = ::bitcoin::PrivateKey {
             compressed: true,
             network,
             key: seed.secret_key()?,
         };
 
-        let bitcoind_client = bitcoind::Client::new(url);
-
         let name = Wallet::gen_name(private_key);
 
         Ok(Wallet {
@@ -53,23 +38,55 @@ impl Wallet {
         })
     }
 
+    /// Make sure the wallet is loaded in bitcoind, loading it from
+    /// disk or creating it if needed.
+    ///
+    /// We assume the wallet present with the same name has the same
+    /// seed, which is fair but could be safer.
     pub async fn init(&self) -> anyhow::Result<()> {
-        let info = self.info().await;
+        match self.info().await {
+            Ok(_) => return Ok(()),
+            Err(e) if bitcoind::Error::of(&e) == Some(bitcoind::Error::WalletNotFound) => {}
+            Err(e) => return Err(e.context(format!("failed to check wallet {}", self.name))),
+        }
 
-        // We assume the wallet present with the same name has the
-        // same seed, which is fair but could be safer.
-        if info.is_err() {
-            // TODO: Probably need to protect the wallet with a passphrase
-            self.bitcoind_client
-                .create_wallet(&self.name, None, Some(true), "".into(), None)
-                .await?;
+        match self.bitcoind_client.load_wallet(&self.name).await {
+            Ok(_) => return Ok(()),
+            Err(e) => match bitcoind::Error::of(&e) {
+                Some(bitcoind::Error::WalletNotFound) => {}
+                // Loaded concurrently
+                Some(bitcoind::Error::WalletAlreadyLoaded) => return Ok(()),
+                _ => return Err(e.context(format!("failed to load wallet {}", self.name))),
+            },
+        }
 
-            let wif = self.private_key.to_wif();
+        // TODO: Probably need to protect the wallet with a passphrase
+        self.bitcoind_client
+            .create_wallet(&self.name, None, Some(true), "".into(), None)
+            .await?;
 
-            self.bitcoind_client
-                .set_hd_seed(&self.name, Some(true), Some(wif))
-                .await?;
-        }
+        let wif = self.private_key.to_wif();
+
+        self.bitcoind_client
+            .set_hd_seed(&self.name, Some(true), Some(wif))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Scan the whole chain for the transactions of the wallet, to
+    /// find the funds of a restored seed.
+    pub async fn rescan(&self) -> anyhow::Result<()> {
+        let response = self
+            .bitcoind_client
+            .rescan_blockchain(&self.name, None)
+            .await?;
+        tracing::info!(
+            "rescanned wallet {} from block {} to {:?}",
+            self.name,
+            response.start_height,
+            response.stop_height
+        );
 
         Ok(())
     }
@@ -90,6 +107,153 @@ impl Wallet {
             .await
     }
 
+    pub async fn send_to_address(&self, address: Address, amount: Amount) -> anyhow::Result<Txid> {
+        self.check_network(&address)?;
+
+        self.bitcoind_client
+            .send_to_address(&self.name, address, amount)
+            .await
+    }
+
+    /// Build and sign a transaction paying `amount` to `address`
+    /// without broadcasting it, e.g. to record it first.
+    pub async fn build_to_address(
+        &self,
+        address: &Address,
+        amount: Amount,
+    ) -> anyhow::Result<Transaction> {
+        self.check_network(address)?;
+
+        let hex = self
+            .bitcoind_client
+            .create_raw_transaction(address, amount)
+            .await?;
+        let transaction = self
+            .bitcoind_client
+            .fund_raw_transaction(&self.name, &hex)
+            .await?;
+
+        self.bitcoind_client
+            .sign_raw_transaction_with_wallet(&self.name, &transaction)
+            .await
+    }
+
+    pub async fn send_raw_transaction(&self, transaction: &Transaction) -> anyhow::Result<Txid> {
+        self.bitcoind_client.send_raw_transaction(transaction).await
+    }
+
+    /// Broadcast a transaction of the wallet which may have been
+    /// broadcast before, e.g. by nectar before a restart. bitcoind
+    /// rejects a transaction that is already in the chain.
+    pub async fn rebroadcast(&self, transaction: &Transaction) -> anyhow::Result<Txid> {
+        let txid = transaction.txid();
+
+        match self.bitcoind_client.send_raw_transaction(transaction).await {
+            Ok(txid) => Ok(txid),
+            Err(e) => match self.bitcoind_client.get_transaction(&self.name, txid).await {
+                Ok(known) if known.confirmations >= 0 => Ok(txid),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// Whether the wallet can sign for an output paying to
+    /// `script_pubkey`.
+    pub async fn owns(&self, script_pubkey: &Script) -> anyhow::Result<bool> {
+        let address = match Address::from_script(script_pubkey, self.private_key.network) {
+            Some(address) => address,
+            None => return Ok(false),
+        };
+        let info = self
+            .bitcoind_client
+            .get_address_info(&self.name, &address)
+            .await?;
+
+        Ok(info.is_mine)
+    }
+
+    /// The first output of `transaction` paying to the wallet.
+    pub async fn owned_output(&self, transaction: &Transaction) -> anyhow::Result<Option<u32>> {
+        for (vout, output) in transaction.output.iter().enumerate() {
+            if self.owns(&output.script_pubkey).await? {
+                return Ok(Some(u32::try_from(vout)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Spend an output of an unconfirmed transaction back to the
+    /// wallet, paying `fee` so that miners are incentivised to include
+    /// both transactions (CPFP). The output must pay to the wallet.
+    ///
+    /// The child signals replaceability so that it can in turn be
+    /// replaced by a child paying a higher fee.
+    pub async fn spend_child(
+        &self,
+        parent: &Transaction,
+        vout: u32,
+        fee: Amount,
+    ) -> anyhow::Result<Txid> {
+        let parent_txid = parent.txid();
+        let output = parent
+            .output
+            .get(vout as usize)
+            .ok_or_else(|| anyhow::anyhow!("transaction {} has no output {}", parent_txid, vout))?;
+        let value = output.value.checked_sub(fee.as_sat()).ok_or_else(|| {
+            anyhow::anyhow!(
+                "output {} of transaction {} cannot pay a fee of {}",
+                vout,
+                parent_txid,
+                fee
+            )
+        })?;
+        if !self.owns(&output.script_pubkey).await? {
+            anyhow::bail!(
+                "output {} of transaction {} does not pay to the wallet",
+                vout,
+                parent_txid
+            );
+        }
+        let address = self.new_address().await?;
+
+        let child = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint {
+                    txid: parent_txid,
+                    vout,
+                },
+                script_sig: Script::new(),
+                sequence: 0xFFFF_FFFD,
+                witness: vec![],
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let child = self
+            .bitcoind_client
+            .sign_raw_transaction_with_wallet(&self.name, &child)
+            .await?;
+
+        self.bitcoind_client.send_raw_transaction(&child).await
+    }
+
+    fn check_network(&self, address: &Address) -> anyhow::Result<()> {
+        if !is_valid_on(address, self.private_key.network) {
+            anyhow::bail!(
+                "address {} is not valid on network {}",
+                address,
+                self.private_key.network
+            );
+        }
+
+        Ok(())
+    }
+
     fn gen_name(private_key: PrivateKey) -> String {
         let mut hash_engine = PubkeyHash::engine();
         private_key
@@ -105,13 +269,51 @@ impl Wallet {
     }
 }
 
+/// Whether `address` can receive funds on `network`.
+///
+/// Base58 addresses have the same prefixes on testnet and regtest, so
+/// a regtest address of this kind parses as a testnet address.
+fn is_valid_on(address: &Address, network: Network) -> bool {
+    match (&address.payload, address.network, network) {
+        (Payload::PubkeyHash(_), Network::Testnet, Network::Regtest)
+        | (Payload::ScriptHash(_), Network::Testnet, Network::Regtest) => true,
+        _ => address.network == network,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
+
+    fn public_key() -> ::bitcoin::PublicKey {
+        let private_key = PrivateKey {
+            compressed: true,
+            network: Network::Regtest,
+            key: ::bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        };
+
+        private_key.public_key(&crate::SECP)
+    }
 
     #[test]
-    fn generate_random_seed() {
-        let _seed = Seed::new();
+    fn base58_regtest_address_is_valid_on_regtest() {
+        let address = Address::p2pkh(&public_key(), Network::Regtest).to_string();
+        let address = Address::from_str(&address).unwrap();
+
+        assert!(is_valid_on(&address, Network::Regtest));
+        assert!(is_valid_on(&address, Network::Testnet));
+        assert!(!is_valid_on(&address, Network::Bitcoin));
+    }
+
+    #[test]
+    fn bech32_address_is_only_valid_on_its_network() {
+        let regtest = Address::p2wpkh(&public_key(), Network::Regtest);
+        let testnet = Address::p2wpkh(&public_key(), Network::Testnet);
+
+        assert!(is_valid_on(&regtest, Network::Regtest));
+        assert!(!is_valid_on(&regtest, Network::Testnet));
+        assert!(!is_valid_on(&testnet, Network::Regtest));
     }
 }
 
@@ -129,7 +331,12 @@ mod docker_tests {
         blockchain.init().await.unwrap();
 
         let seed = Seed::new();
-        let wallet = Wallet::new(seed, blockchain.node_url.clone(), Network::Regtest).unwrap();
+        let wallet = Wallet::new(
+            seed,
+            bitcoind::Client::new(blockchain.node_url.clone()),
+            Network::Regtest,
+        )
+        .unwrap();
         wallet.init().await.unwrap();
 
         let _address = wallet.new_address().await.unwrap();
@@ -143,7 +350,12 @@ mod docker_tests {
         blockchain.init().await.unwrap();
 
         let seed = Seed::new();
-        let wallet = Wallet::new(seed, blockchain.node_url.clone(), Network::Regtest).unwrap();
+        let wallet = Wallet::new(
+            seed,
+            bitcoind::Client::new(blockchain.node_url.clone()),
+            Network::Regtest,
+        )
+        .unwrap();
         wallet.init().await.unwrap();
 
         let _balance = wallet.balance().await.unwrap();
@@ -158,18 +370,85 @@ mod docker_tests {
 
         let seed = Seed::new();
         {
-            let wallet =
-                Wallet::new(seed.clone(), blockchain.node_url.clone(), Network::Regtest).unwrap();
+            let wallet = Wallet::new(
+                seed.clone(),
+                bitcoind::Client::new(blockchain.node_url.clone()),
+                Network::Regtest,
+            )
+            .unwrap();
             wallet.init().await.unwrap();
 
             let _address = wallet.new_address().await.unwrap();
         }
 
         {
-            let wallet = Wallet::new(seed, blockchain.node_url.clone(), Network::Regtest).unwrap();
+            let wallet = Wallet::new(
+                seed,
+                bitcoind::Client::new(blockchain.node_url.clone()),
+                Network::Regtest,
+            )
+            .unwrap();
             wallet.init().await.unwrap();
 
             let _address = wallet.new_address().await.unwrap();
         }
     }
+
+    #[tokio::test]
+    async fn funds_are_found_again_by_a_rescan() {
+        let tc_client = clients::Cli::default();
+        let blockchain = BitcoinBlockchain::new(&tc_client).unwrap();
+
+        blockchain.init().await.unwrap();
+
+        let bitcoind_client = bitcoind::Client::new(blockchain.node_url.clone());
+        let wallet = Wallet::new(Seed::new(), bitcoind_client.clone(), Network::Regtest).unwrap();
+        wallet.init().await.unwrap();
+        let address = wallet.new_address().await.unwrap();
+        bitcoind_client
+            .send_to_address("testwallet", address.clone(), Amount::from_sat(100_000))
+            .await
+            .unwrap();
+        bitcoind_client
+            .generate_to_address(1, address, None)
+            .await
+            .unwrap();
+
+        wallet.rescan().await.unwrap();
+
+        assert!(wallet.balance().await.unwrap() >= Amount::from_sat(100_000));
+    }
+
+    #[tokio::test]
+    async fn unloaded_bitcoin_wallet_is_loaded_again() {
+        let tc_client = clients::Cli::default();
+        let blockchain = BitcoinBlockchain::new(&tc_client).unwrap();
+
+        blockchain.init().await.unwrap();
+
+        let seed = Seed::new();
+        let wallet = Wallet::new(
+            seed,
+            bitcoind::Client::new(blockchain.node_url.clone()),
+            Network::Regtest,
+        )
+        .unwrap();
+        wallet.init().await.unwrap();
+
+        wallet
+            .bitcoind_client
+            .unload_wallet(&wallet.name)
+            .await
+            .unwrap();
+        let error = wallet.info().await.unwrap_err();
+        assert_eq!(
+            bitcoind::Error::of(&error),
+            Some(bitcoind::Error::WalletNotFound)
+        );
+
+        // Creating the wallet again would fail as its file exists
+        wallet.init().await.unwrap();
+
+        let _info = wallet.info().await.unwrap();
+    }
 }
@@ -1,6 +1,18 @@
+mod connector;
+
+pub use connector::Connector;
+
 use crate::jsonrpc;
-use bitcoin::{Address, Amount};
-use serde::Deserialize;
+use bitcoin::{
+    consensus::encode::{deserialize, serialize_hex},
+    hashes::hex::FromHex,
+    Address, Amount, Network, Transaction, Txid,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+/// How long to wait for a rescan of the whole chain.
+const RESCAN_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Debug, Clone)]
 pub struct Client {
@@ -9,18 +21,28 @@ pub struct Client {
 
 impl Client {
     pub fn new(url: reqwest::Url) -> Self {
+        Self::with_auth(url, jsonrpc::Auth::None)
+    }
+
+    pub fn with_auth(url: reqwest::Url, auth: jsonrpc::Auth) -> Self {
         Client {
-            rpc_client: jsonrpc::Client::new(url),
+            rpc_client: jsonrpc::Client::with_auth(url, auth),
         }
     }
 
-    pub async fn network(&self) -> anyhow::Result<String> {
+    pub async fn blockchain_info(&self) -> anyhow::Result<BlockchainInfo> {
         let blockchain_info = self
             .rpc_client
             .send::<Vec<()>, BlockchainInfo>(jsonrpc::Request::new("getblockchaininfo", vec![]))
             .await?;
 
-        Ok(blockchain_info.chain)
+        Ok(blockchain_info)
+    }
+
+    pub async fn network(&self) -> anyhow::Result<Network> {
+        let network = self.blockchain_info().await?.network()?;
+
+        Ok(network)
     }
 
     pub async fn create_wallet(
@@ -31,22 +53,49 @@ impl Client {
         passphrase: String,
         avoid_reuse: Option<bool>,
     ) -> anyhow::Result<CreateWalletResponse> {
+        let response = self
+            .rpc_client
+            .send(
+                jsonrpc::Request::new(
+                    "createwallet",
+                    vec![
+                        jsonrpc::serialize(wallet_name)?,
+                        jsonrpc::serialize(disable_private_keys)?,
+                        jsonrpc::serialize(blank)?,
+                        jsonrpc::serialize(passphrase)?,
+                        jsonrpc::serialize(avoid_reuse)?,
+                    ],
+                )
+                // A retry would fail as the wallet already exists
+                .not_idempotent()
+                .with_secrets(),
+            )
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn load_wallet(&self, wallet_name: &str) -> anyhow::Result<LoadWalletResponse> {
         let response = self
             .rpc_client
             .send(jsonrpc::Request::new(
-                "createwallet",
-                vec![
-                    jsonrpc::serialize(wallet_name)?,
-                    jsonrpc::serialize(disable_private_keys)?,
-                    jsonrpc::serialize(blank)?,
-                    jsonrpc::serialize(passphrase)?,
-                    jsonrpc::serialize(avoid_reuse)?,
-                ],
+                "loadwallet",
+                vec![jsonrpc::serialize(wallet_name)?],
             ))
             .await?;
         Ok(response)
     }
 
+    #[cfg(test)]
+    pub async fn unload_wallet(&self, wallet_name: &str) -> anyhow::Result<()> {
+        self.rpc_client
+            .send::<_, ()>(jsonrpc::Request::new(
+                "unloadwallet",
+                vec![jsonrpc::serialize(wallet_name)?],
+            ))
+            .await?;
+        Ok(())
+    }
+
     pub async fn get_balance(
         &self,
         wallet_name: &str,
@@ -88,13 +137,36 @@ impl Client {
                         jsonrpc::serialize(new_key_pool)?,
                         jsonrpc::serialize(wif_private_key)?,
                     ],
-                ),
+                )
+                .with_secrets(),
             )
             .await?;
 
         Ok(())
     }
 
+    /// Scan the chain from `start_height`, or the genesis block, for
+    /// the transactions of the wallet. It can take hours on mainnet.
+    pub async fn rescan_blockchain(
+        &self,
+        wallet_name: &str,
+        start_height: Option<u32>,
+    ) -> anyhow::Result<RescanResponse> {
+        let response = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new("rescanblockchain", vec![jsonrpc::serialize(start_height)?])
+                    .with_timeout(RESCAN_TIMEOUT)
+                    // bitcoind refuses a second rescan while the first
+                    // one is still running
+                    .not_idempotent(),
+            )
+            .await?;
+
+        Ok(response)
+    }
+
     pub async fn get_new_address(
         &self,
         wallet_name: &str,
@@ -117,6 +189,269 @@ impl Client {
         Ok(address)
     }
 
+    pub async fn send_to_address(
+        &self,
+        wallet_name: &str,
+        address: Address,
+        amount: Amount,
+    ) -> anyhow::Result<Txid> {
+        let txid = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "sendtoaddress",
+                    vec![
+                        jsonrpc::serialize(address)?,
+                        jsonrpc::serialize(amount.as_btc())?,
+                    ],
+                )
+                .not_idempotent(),
+            )
+            .await?;
+        Ok(txid)
+    }
+
+    /// Create an unsigned transaction paying `amount` to `address`,
+    /// without inputs. Its hex encoding is returned as is: with no
+    /// inputs, it cannot be told apart from a segwit transaction.
+    pub async fn create_raw_transaction(
+        &self,
+        address: &Address,
+        amount: Amount,
+    ) -> anyhow::Result<String> {
+        let mut outputs = serde_json::Map::new();
+        outputs.insert(address.to_string(), jsonrpc::serialize(amount.as_btc())?);
+
+        let hex = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                "createrawtransaction",
+                vec![
+                    jsonrpc::serialize(Vec::<()>::new())?,
+                    jsonrpc::serialize(outputs)?,
+                ],
+            ))
+            .await?;
+        Ok(hex)
+    }
+
+    /// Add inputs of the wallet to the transaction encoded in `hex`,
+    /// and a change output if needed. The inputs are locked so that
+    /// the wallet does not spend them again before the transaction is
+    /// broadcast.
+    pub async fn fund_raw_transaction(
+        &self,
+        wallet_name: &str,
+        hex: &str,
+    ) -> anyhow::Result<Transaction> {
+        let response: FundRawTransactionResponse = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "fundrawtransaction",
+                    vec![
+                        jsonrpc::serialize(hex)?,
+                        jsonrpc::serialize(FundRawTransactionOptions {
+                            lock_unspents: true,
+                        })?,
+                    ],
+                )
+                .not_idempotent(),
+            )
+            .await?;
+
+        decode_transaction(&response.hex)
+    }
+
+    pub async fn send_raw_transaction(&self, transaction: &Transaction) -> anyhow::Result<Txid> {
+        let txid = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                "sendrawtransaction",
+                vec![jsonrpc::serialize(serialize_hex(transaction))?],
+            ))
+            .await?;
+        Ok(txid)
+    }
+
+    pub async fn get_raw_transaction(&self, txid: Txid) -> anyhow::Result<Transaction> {
+        let hex: String = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                "getrawtransaction",
+                vec![jsonrpc::serialize(txid)?],
+            ))
+            .await?;
+
+        decode_transaction(&hex)
+    }
+
+    pub async fn get_block_count(&self) -> anyhow::Result<u32> {
+        let count = self
+            .rpc_client
+            .send::<Vec<()>, _>(jsonrpc::Request::new("getblockcount", vec![]))
+            .await?;
+        Ok(count)
+    }
+
+    pub async fn list_unspent(
+        &self,
+        wallet_name: &str,
+        minimum_confirmation: Option<u32>,
+        maximum_confirmation: Option<u32>,
+        addresses: Option<Vec<Address>>,
+    ) -> anyhow::Result<Vec<Unspent>> {
+        let unspent = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "listunspent",
+                    vec![
+                        jsonrpc::serialize(minimum_confirmation)?,
+                        jsonrpc::serialize(maximum_confirmation)?,
+                        jsonrpc::serialize(addresses)?,
+                    ],
+                ),
+            )
+            .await?;
+        Ok(unspent)
+    }
+
+    pub async fn get_transaction(
+        &self,
+        wallet_name: &str,
+        txid: Txid,
+    ) -> anyhow::Result<GetTransactionResponse> {
+        let response = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new("gettransaction", vec![jsonrpc::serialize(txid)?]),
+            )
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn get_address_info(
+        &self,
+        wallet_name: &str,
+        address: &Address,
+    ) -> anyhow::Result<AddressInfoResponse> {
+        let response = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new("getaddressinfo", vec![jsonrpc::serialize(address)?]),
+            )
+            .await?;
+        Ok(response)
+    }
+
+    /// Lock outputs so that the wallet does not spend them, or unlock
+    /// them. Locks only last until bitcoind restarts.
+    pub async fn lock_unspent(
+        &self,
+        wallet_name: &str,
+        unlock: bool,
+        outpoints: Vec<Outpoint>,
+    ) -> anyhow::Result<bool> {
+        let success = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "lockunspent",
+                    vec![jsonrpc::serialize(unlock)?, jsonrpc::serialize(outpoints)?],
+                ),
+            )
+            .await?;
+        Ok(success)
+    }
+
+    /// Unlock an encrypted wallet for `timeout` seconds.
+    pub async fn wallet_passphrase(
+        &self,
+        wallet_name: &str,
+        passphrase: &str,
+        timeout: u32,
+    ) -> anyhow::Result<()> {
+        self.rpc_client
+            .send_with_path::<_, ()>(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "walletpassphrase",
+                    vec![
+                        jsonrpc::serialize(passphrase)?,
+                        jsonrpc::serialize(timeout)?,
+                    ],
+                )
+                .with_secrets(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Copy the wallet file to `destination`, on the machine bitcoind
+    /// runs on.
+    pub async fn backup_wallet(&self, wallet_name: &str, destination: &Path) -> anyhow::Result<()> {
+        self.rpc_client
+            .send_with_path::<_, ()>(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new("backupwallet", vec![jsonrpc::serialize(destination)?]),
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn sign_raw_transaction_with_wallet(
+        &self,
+        wallet_name: &str,
+        transaction: &Transaction,
+    ) -> anyhow::Result<Transaction> {
+        let response: SignRawTransactionResponse = self
+            .rpc_client
+            .send_with_path(
+                format!("/wallet/{}", wallet_name),
+                jsonrpc::Request::new(
+                    "signrawtransactionwithwallet",
+                    vec![jsonrpc::serialize(serialize_hex(transaction))?],
+                ),
+            )
+            .await?;
+
+        if !response.complete {
+            anyhow::bail!(
+                "wallet {} could not sign transaction {}: {:?}",
+                wallet_name,
+                transaction.txid(),
+                response.errors
+            );
+        }
+
+        decode_transaction(&response.hex)
+    }
+
+    pub async fn estimate_smart_fee(
+        &self,
+        conf_target: u32,
+        estimate_mode: Option<EstimateMode>,
+    ) -> anyhow::Result<EstimateSmartFeeResponse> {
+        let response = self
+            .rpc_client
+            .send(jsonrpc::Request::new(
+                "estimatesmartfee",
+                vec![
+                    jsonrpc::serialize(conf_target)?,
+                    jsonrpc::serialize(estimate_mode)?,
+                ],
+            ))
+            .await?;
+        Ok(response)
+    }
+
     pub async fn get_wallet_info(&self, wallet_name: &str) -> anyhow::Result<WalletInfoResponse> {
         let response = self
             .rpc_client
@@ -137,33 +472,304 @@ impl Client {
     ) -> anyhow::Result<Vec<BlockHash>> {
         let response = self
             .rpc_client
-            .send(jsonrpc::Request::new(
-                "generatetoaddress",
-                vec![
-                    jsonrpc::serialize(nblocks)?,
-                    jsonrpc::serialize(address)?,
-                    jsonrpc::serialize(max_tries)?,
-                ],
-            ))
+            .send(
+                jsonrpc::Request::new(
+                    "generatetoaddress",
+                    vec![
+                        jsonrpc::serialize(nblocks)?,
+                        jsonrpc::serialize(address)?,
+                        jsonrpc::serialize(max_tries)?,
+                    ],
+                )
+                .not_idempotent(),
+            )
             .await?;
         Ok(response)
     }
+
+    #[cfg(test)]
+    pub async fn get_best_block_hash(&self) -> anyhow::Result<BlockHash> {
+        let hash = self
+            .rpc_client
+            .send::<Vec<()>, _>(jsonrpc::Request::new("getbestblockhash", vec![]))
+            .await?;
+        Ok(hash)
+    }
+
+    /// Mark a block as invalid, reorganising the chain as if it had
+    /// never been mined.
+    #[cfg(test)]
+    pub async fn invalidate_block(&self, hash: &BlockHash) -> anyhow::Result<()> {
+        self.rpc_client
+            .send::<_, ()>(jsonrpc::Request::new(
+                "invalidateblock",
+                vec![jsonrpc::serialize(hash)?],
+            ))
+            .await?;
+        Ok(())
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct BlockchainInfo {
-    chain: String,
+fn decode_transaction(hex: &str) -> anyhow::Result<Transaction> {
+    let bytes = Vec::<u8>::from_hex(hex)?;
+    let transaction = deserialize(&bytes)?;
+
+    Ok(transaction)
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct RescanResponse {
+    pub start_height: u32,
+    /// `None` if a reorganisation left no block to scan
+    pub stop_height: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct BlockchainInfo {
+    pub chain: String,
+    #[serde(rename = "initialblockdownload")]
+    pub initial_block_download: bool,
+}
+
+impl BlockchainInfo {
+    pub fn network(&self) -> Result<Network, UnsupportedChain> {
+        network_of_chain(&self.chain)
+    }
+
+    /// Make sure the node follows `network` and is synced enough to
+    /// watch the swaps' transactions.
+    pub fn ensure_ready_for(&self, network: Network) -> anyhow::Result<()> {
+        let node = self.network()?;
+        if node != network {
+            return Err(NetworkMismatch {
+                node,
+                configured: network,
+            }
+            .into());
+        }
+        if self.initial_block_download {
+            return Err(InitialBlockDownload.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Map the name bitcoind gives to its chain, as in `-chain=<chain>`.
+fn network_of_chain(chain: &str) -> Result<Network, UnsupportedChain> {
+    match chain {
+        "main" => Ok(Network::Bitcoin),
+        "test" => Ok(Network::Testnet),
+        "regtest" => Ok(Network::Regtest),
+        // The bitcoin crate does not know signet yet
+        _ => Err(UnsupportedChain(chain.to_owned())),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("bitcoind chain {0} is not supported")]
+pub struct UnsupportedChain(pub String);
+
+#[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
+#[error("bitcoind follows {node} but {configured} is configured")]
+pub struct NetworkMismatch {
+    pub node: Network,
+    pub configured: Network,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
+#[error("bitcoind is still in initial block download")]
+pub struct InitialBlockDownload;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct BlockHash(String);
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum EstimateMode {
+    Economical,
+    Conservative,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct EstimateSmartFeeResponse {
+    /// Fee rate in BTC/kvB, absent if bitcoind has too little data to
+    /// estimate it
+    #[serde(rename = "feerate")]
+    pub fee_rate: Option<f64>,
+    pub errors: Option<Vec<String>>,
+    /// Number of blocks for which the estimate is valid
+    pub blocks: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Unspent {
+    pub txid: Txid,
+    pub vout: u32,
+    pub address: Option<Address>,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: String,
+    /// Amount in BTC
+    amount: f64,
+    pub confirmations: u32,
+    pub spendable: bool,
+    pub solvable: bool,
+    /// Whether the output is considered safe to spend, unconfirmed
+    /// outputs from outside the wallet are not
+    pub safe: bool,
+}
+
+impl Unspent {
+    pub fn amount(&self) -> anyhow::Result<Amount> {
+        Ok(Amount::from_btc(self.amount)?)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct GetTransactionResponse {
+    pub txid: Txid,
+    /// Net amount for the wallet in BTC
+    amount: f64,
+    /// Fee in BTC, negative, only present for transactions sent by the
+    /// wallet
+    fee: Option<f64>,
+    /// Negative if the transaction conflicts with one in the chain
+    pub confirmations: i64,
+    #[serde(rename = "blockhash")]
+    pub block_hash: Option<BlockHash>,
+    hex: String,
+}
+
+impl GetTransactionResponse {
+    pub fn amount(&self) -> anyhow::Result<::bitcoin::SignedAmount> {
+        Ok(::bitcoin::SignedAmount::from_btc(self.amount)?)
+    }
+
+    pub fn fee(&self) -> anyhow::Result<Option<::bitcoin::SignedAmount>> {
+        self.fee
+            .map(::bitcoin::SignedAmount::from_btc)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    pub fn transaction(&self) -> anyhow::Result<Transaction> {
+        decode_transaction(&self.hex)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AddressInfoResponse {
+    pub address: Address,
+    /// Whether the wallet can sign for the address
+    #[serde(rename = "ismine")]
+    pub is_mine: bool,
+}
+
+/// An output of a transaction, as expected by `lockunspent`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Outpoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl From<::bitcoin::OutPoint> for Outpoint {
+    fn from(outpoint: ::bitcoin::OutPoint) -> Self {
+        Outpoint {
+            txid: outpoint.txid,
+            vout: outpoint.vout,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+struct FundRawTransactionOptions {
+    #[serde(rename = "lockUnspents")]
+    lock_unspents: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct FundRawTransactionResponse {
+    pub hex: String,
+    /// Fee in BTC
+    pub fee: f64,
+    /// Position of the change output, -1 if there is none
+    #[serde(rename = "changepos")]
+    pub change_position: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct SignRawTransactionResponse {
+    pub hex: String,
+    /// Whether all inputs are signed
+    pub complete: bool,
+    pub errors: Option<Vec<SigningError>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct SigningError {
+    pub txid: Txid,
+    pub vout: u32,
+    pub error: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateWalletResponse {
     name: String,
     warning: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LoadWalletResponse {
+    name: String,
+    warning: String,
+}
+
+/// The errors bitcoind reports that nectar handles, identified by
+/// their RPC error code.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("wallet does not exist or is not loaded")]
+    WalletNotFound,
+    #[error("wallet is already loaded")]
+    WalletAlreadyLoaded,
+    #[error("insufficient funds")]
+    InsufficientFunds,
+    #[error("wallet is encrypted and must be unlocked with walletpassphrase first")]
+    UnlockNeeded,
+    #[error("wallet passphrase is incorrect")]
+    IncorrectPassphrase,
+    #[error("bitcoind error {code}: {message}")]
+    Other { code: i64, message: String },
+}
+
+impl Error {
+    /// The error bitcoind answered a failed request with, `None` if
+    /// the request failed for another reason, e.g. bitcoind being
+    /// unreachable.
+    pub fn of(error: &anyhow::Error) -> Option<Error> {
+        error
+            .downcast_ref::<jsonrpc::JsonRpcError>()
+            .map(Error::from)
+    }
+}
+
+impl From<&jsonrpc::JsonRpcError> for Error {
+    fn from(error: &jsonrpc::JsonRpcError) -> Self {
+        // See `RPCErrorCode` in bitcoind's `rpc/protocol.h`
+        match error.code() {
+            -18 => Error::WalletNotFound,
+            -35 => Error::WalletAlreadyLoaded,
+            -6 => Error::InsufficientFunds,
+            -13 => Error::UnlockNeeded,
+            -14 => Error::IncorrectPassphrase,
+            code => Error::Other {
+                code,
+                message: error.message().to_owned(),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct WalletInfoResponse {
     #[serde(rename = "walletname")]
@@ -208,7 +814,48 @@ mod test {
 
         let network = client.network().await.unwrap();
 
-        assert_eq!(network.as_str(), "regtest")
+        assert_eq!(network, Network::Regtest)
+    }
+
+    #[test]
+    fn map_chain_to_network() {
+        assert_eq!(network_of_chain("main"), Ok(Network::Bitcoin));
+        assert_eq!(network_of_chain("test"), Ok(Network::Testnet));
+        assert_eq!(network_of_chain("regtest"), Ok(Network::Regtest));
+        assert_eq!(
+            network_of_chain("signet"),
+            Err(UnsupportedChain("signet".to_owned()))
+        );
+    }
+
+    #[test]
+    fn given_node_on_other_network_then_not_ready() {
+        let info = BlockchainInfo {
+            chain: "test".to_owned(),
+            initial_block_download: false,
+        };
+
+        let error = info.ensure_ready_for(Network::Bitcoin).unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<NetworkMismatch>(),
+            Some(&NetworkMismatch {
+                node: Network::Testnet,
+                configured: Network::Bitcoin,
+            })
+        );
+    }
+
+    #[test]
+    fn given_node_in_initial_block_download_then_not_ready() {
+        let info = BlockchainInfo {
+            chain: "main".to_owned(),
+            initial_block_download: true,
+        };
+
+        let error = info.ensure_ready_for(Network::Bitcoin).unwrap_err();
+
+        assert!(error.is::<InitialBlockDownload>());
     }
 
     #[test]
@@ -249,4 +896,262 @@ mod test {
             }
         )
     }
+
+    #[test]
+    fn decode_estimate_smart_fee() {
+        let json = r#"{
+        "feerate":0.00012345,
+        "blocks":2
+        }"#;
+
+        let response: EstimateSmartFeeResponse = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            response,
+            EstimateSmartFeeResponse {
+                fee_rate: Some(0.000_123_45),
+                errors: None,
+                blocks: 2
+            }
+        )
+    }
+
+    #[test]
+    fn decode_estimate_smart_fee_without_enough_data() {
+        let json = r#"{
+        "errors":["Insufficient data or no feerate found"],
+        "blocks":0
+        }"#;
+
+        let response: EstimateSmartFeeResponse = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(response.fee_rate, None);
+        assert_eq!(
+            response.errors,
+            Some(vec!["Insufficient data or no feerate found".to_owned()])
+        );
+    }
+
+    #[test]
+    fn given_wallet_not_found_code_then_typed_error() {
+        let json = r#"{
+        "code":-18,
+        "message":"Requested wallet does not exist or is not loaded"
+        }"#;
+        let rpc_error: jsonrpc::JsonRpcError = serde_json::from_str(&json).unwrap();
+
+        let error = anyhow::Error::from(rpc_error).context("JSON-RPC request failed");
+
+        assert_eq!(Error::of(&error), Some(Error::WalletNotFound));
+    }
+
+    #[test]
+    fn given_unknown_code_then_other_error() {
+        let json = r#"{
+        "code":-8,
+        "message":"Invalid parameter"
+        }"#;
+        let rpc_error: jsonrpc::JsonRpcError = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            Error::from(&rpc_error),
+            Error::Other {
+                code: -8,
+                message: "Invalid parameter".into()
+            }
+        );
+    }
+
+    #[test]
+    fn given_connection_failure_then_no_bitcoind_error() {
+        let error = anyhow::anyhow!("connection refused");
+
+        assert_eq!(Error::of(&error), None);
+    }
+
+    /// The coinbase transaction of the genesis block.
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+    const GENESIS_COINBASE_TXID: &str =
+        "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    #[test]
+    fn decode_txid_returned_when_sending() {
+        // `sendtoaddress` and `sendrawtransaction` return the txid
+        let json = format!(r#""{}""#, GENESIS_COINBASE_TXID);
+
+        let txid: Txid = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(txid, Txid::from_hex(GENESIS_COINBASE_TXID).unwrap());
+    }
+
+    #[test]
+    fn decode_raw_transaction() {
+        let transaction = decode_transaction(GENESIS_COINBASE).unwrap();
+
+        assert_eq!(
+            transaction.txid(),
+            Txid::from_hex(GENESIS_COINBASE_TXID).unwrap()
+        );
+        assert_eq!(transaction.output[0].value, 5_000_000_000);
+    }
+
+    #[test]
+    fn decode_block_count() {
+        let count: u32 = serde_json::from_str("652847").unwrap();
+
+        assert_eq!(count, 652_847);
+    }
+
+    #[test]
+    fn decode_list_unspent() {
+        let json = r#"[{
+        "txid":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+        "vout":1,
+        "address":"bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl",
+        "label":"",
+        "scriptPubKey":"001454d26dddb59c7073c6a197946ea1841951fa7a74",
+        "amount":0.50000000,
+        "confirmations":3,
+        "spendable":true,
+        "solvable":true,
+        "desc":"wpkh([d34db33f/84'/1'/0'/0/0]02...)#abcdefgh",
+        "safe":true
+        }]"#;
+
+        let unspent: Vec<Unspent> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            unspent,
+            vec![Unspent {
+                txid: Txid::from_hex(GENESIS_COINBASE_TXID).unwrap(),
+                vout: 1,
+                address: Some(
+                    "bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl"
+                        .parse()
+                        .unwrap()
+                ),
+                script_pub_key: "001454d26dddb59c7073c6a197946ea1841951fa7a74".into(),
+                amount: 0.5,
+                confirmations: 3,
+                spendable: true,
+                solvable: true,
+                safe: true,
+            }]
+        );
+        assert_eq!(unspent[0].amount().unwrap(), Amount::from_sat(50_000_000));
+    }
+
+    #[test]
+    fn decode_get_transaction() {
+        let json = format!(
+            r#"{{
+        "amount":-0.10000000,
+        "fee":-0.00000141,
+        "confirmations":-2,
+        "trusted":false,
+        "txid":"{}",
+        "walletconflicts":[],
+        "time":1592792998,
+        "timereceived":1592792998,
+        "bip125-replaceable":"no",
+        "details":[],
+        "hex":"{}"
+        }}"#,
+            GENESIS_COINBASE_TXID, GENESIS_COINBASE
+        );
+
+        let response: GetTransactionResponse = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(response.confirmations, -2);
+        assert_eq!(response.block_hash, None);
+        assert_eq!(
+            response.amount().unwrap(),
+            ::bitcoin::SignedAmount::from_sat(-10_000_000)
+        );
+        assert_eq!(
+            response.fee().unwrap(),
+            Some(::bitcoin::SignedAmount::from_sat(-141))
+        );
+        assert_eq!(response.transaction().unwrap().txid(), response.txid);
+    }
+
+    #[test]
+    fn decode_get_address_info() {
+        let json = r#"{
+        "address":"bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl",
+        "scriptPubKey":"001454d26dddb59c7073c6a197946ea1841951fa7a74",
+        "ismine":false,
+        "solvable":false,
+        "iswatchonly":false,
+        "isscript":false,
+        "iswitness":true,
+        "witness_version":0,
+        "witness_program":"54d26dddb59c7073c6a197946ea1841951fa7a74",
+        "ischange":false,
+        "labels":[]
+        }"#;
+
+        let response: AddressInfoResponse = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            response.address,
+            "bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl"
+                .parse()
+                .unwrap()
+        );
+        assert!(!response.is_mine);
+    }
+
+    #[test]
+    fn serialize_lock_unspent_outpoints() {
+        let outpoint = Outpoint {
+            txid: Txid::from_hex(GENESIS_COINBASE_TXID).unwrap(),
+            vout: 0,
+        };
+
+        let json = serde_json::to_string(&vec![outpoint]).unwrap();
+
+        assert_eq!(
+            json,
+            format!(r#"[{{"txid":"{}","vout":0}}]"#, GENESIS_COINBASE_TXID)
+        );
+    }
+
+    #[test]
+    fn decode_incomplete_signed_transaction() {
+        let json = r#"{
+        "hex":"0200000001",
+        "complete":false,
+        "errors":[{
+            "txid":"a8e3ad3a4da2bfb6ab7c3c4a0a5aed3d88e3a3a2a1f0b4cc0f6c4b0c2e8e2f71",
+            "vout":0,
+            "witness":[],
+            "scriptSig":"",
+            "sequence":4294967293,
+            "error":"Input not found or already spent"
+        }]
+        }"#;
+
+        let response: SignRawTransactionResponse = serde_json::from_str(&json).unwrap();
+
+        assert!(!response.complete);
+        assert_eq!(
+            response.errors,
+            Some(vec![SigningError {
+                txid: Txid::from_hex(
+                    "a8e3ad3a4da2bfb6ab7c3c4a0a5aed3d88e3a3a2a1f0b4cc0f6c4b0c2e8e2f71"
+                )
+                .unwrap(),
+                vout: 0,
+                error: "Input not found or already spent".into(),
+            }])
+        );
+    }
+
+    #[test]
+    fn serialize_estimate_mode() {
+        let mode = serde_json::to_string(&EstimateMode::Conservative).unwrap();
+
+        assert_eq!(mode, r#""CONSERVATIVE""#);
+    }
 }
@@ -0,0 +1,147 @@
+use crate::jsonrpc;
+use bitcoin::{Block, BlockHash, Network};
+use comit::btsieve::{bitcoin::BitcoindConnector, BlockByHash, LatestBlock};
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Watches the Bitcoin blockchain through bitcoind with the
+/// credentials of `auth`.
+///
+/// `BitcoindConnector` only authenticates with credentials in its
+/// URL. When a request fails, the cookie is read again and, if
+/// bitcoind restarted with a new one, the request is sent again with
+/// a connector built for it.
+pub struct Connector {
+    node_url: reqwest::Url,
+    network: Network,
+    auth: jsonrpc::Auth,
+    current: Mutex<Current>,
+}
+
+struct Current {
+    url: reqwest::Url,
+    connector: Arc<BitcoindConnector>,
+}
+
+impl Connector {
+    pub fn new(
+        node_url: reqwest::Url,
+        network: Network,
+        auth: jsonrpc::Auth,
+    ) -> anyhow::Result<Self> {
+        let url = auth.url_with_credentials(&node_url)?;
+        let connector = Arc::new(BitcoindConnector::new(url.clone(), network)?);
+
+        Ok(Connector {
+            node_url,
+            network,
+            auth,
+            current: Mutex::new(Current { url, connector }),
+        })
+    }
+
+    async fn with_connector<F, Fut, T>(&self, request: F) -> anyhow::Result<T>
+    where
+        F: Fn(Arc<BitcoindConnector>) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let connector = Arc::clone(&self.current().connector);
+
+        match request(connector).await {
+            Ok(response) => Ok(response),
+            Err(e) => match self.reconnect() {
+                Ok(Some(connector)) => request(connector).await,
+                Ok(None) => Err(e),
+                Err(reconnect) => {
+                    tracing::warn!("could not reconnect to bitcoind: {:#}", reconnect);
+                    Err(e)
+                }
+            },
+        }
+    }
+
+    /// A connector with the credentials of the cookie as it is now,
+    /// `None` if they did not change.
+    fn reconnect(&self) -> anyhow::Result<Option<Arc<BitcoindConnector>>> {
+        self.auth.reload()?;
+        let url = self.auth.url_with_credentials(&self.node_url)?;
+
+        let mut current = self.current();
+        if current.url == url {
+            return Ok(None);
+        }
+
+        tracing::info!("bitcoind cookie changed, reconnecting");
+        let connector = Arc::new(BitcoindConnector::new(url.clone(), self.network)?);
+        *current = Current {
+            url,
+            connector: Arc::clone(&connector),
+        };
+
+        Ok(Some(connector))
+    }
+
+    fn current(&self) -> MutexGuard<'_, Current> {
+        self.current.lock().expect("bitcoind connector poisoned")
+    }
+}
+
+#[async_trait::async_trait]
+impl LatestBlock for Connector {
+    type Block = Block;
+
+    async fn latest_block(&self) -> anyhow::Result<Self::Block> {
+        self.with_connector(|connector| async move { connector.latest_block().await })
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl BlockByHash for Connector {
+    type Block = Block;
+    type BlockHash = BlockHash;
+
+    async fn block_by_hash(&self, hash: Self::BlockHash) -> anyhow::Result<Self::Block> {
+        self.with_connector(|connector| async move { connector.block_by_hash(hash).await })
+            .await
+    }
+}
+
+impl fmt::Debug for Connector {
+    /// The URL in use holds the credentials, only show the configured
+    /// one.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connector")
+            .field("node_url", &self.node_url)
+            .field("network", &self.network)
+            .field("auth", &self.auth)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconnect_with_the_new_cookie_once_it_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".cookie");
+        std::fs::write(&path, "__cookie__:first").unwrap();
+        let connector = Connector::new(
+            "http://localhost:18443".parse().unwrap(),
+            Network::Regtest,
+            jsonrpc::Auth::Cookie(jsonrpc::CookieFile::new(path.clone())),
+        )
+        .unwrap();
+
+        assert!(connector.reconnect().unwrap().is_none());
+
+        std::fs::write(&path, "__cookie__:second").unwrap();
+        assert!(connector.reconnect().unwrap().is_some());
+        assert_eq!(connector.current().url.password(), Some("second"));
+    }
+}
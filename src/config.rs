@@ -0,0 +1,900 @@
+mod file;
+
+pub use file::File;
+
+use crate::bitcoin;
+use crate::dai;
+use crate::jsonrpc;
+use crate::markets::OhlcInterval;
+use crate::rate::Spread;
+use comit::ethereum::Address;
+use num::{BigUint, ToPrimitive};
+use reqwest::Url;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The validated configuration of nectar.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub data_dir: PathBuf,
+    pub maker: Maker,
+    pub bitcoin: Bitcoin,
+    pub ethereum: Ethereum,
+}
+
+#[derive(Clone, Debug)]
+pub struct Maker {
+    pub spread: Spread,
+    pub max_sell_amount: bitcoin::Amount,
+    /// `None` if nectar does not sell dai
+    pub max_sell_dai_amount: Option<dai::Amount>,
+    pub kraken_ohlc_interval: OhlcInterval,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bitcoin {
+    pub network: ::bitcoin::Network,
+    pub bitcoind: Bitcoind,
+    pub safety: Safety,
+    pub confirmations: u32,
+    pub fees: BitcoinFees,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bitcoind {
+    pub node_url: Url,
+    pub auth: jsonrpc::Auth,
+}
+
+#[derive(Clone, Debug)]
+pub struct Ethereum {
+    pub node_url: Url,
+    pub safety: Safety,
+    pub confirmations: u32,
+    pub gas_price: GasPrice,
+    pub dai_contract: Address,
+}
+
+/// The time needed to get a transaction mined and confirmed on a
+/// chain, derived from its expected block time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Safety {
+    pub block_time: Duration,
+    pub blocks: u32,
+}
+
+/// The confirmation targets given to bitcoind's fee estimation and
+/// the bounds within which its estimate is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BitcoinFees {
+    pub fund_target: u32,
+    pub spend_target: u32,
+    pub min_sat_per_vbyte: u64,
+    pub max_sat_per_vbyte: u64,
+}
+
+impl BitcoinFees {
+    /// Redeem and refund transactions are time-sensitive, hence the
+    /// shorter target.
+    pub const DEFAULT: BitcoinFees = BitcoinFees {
+        fund_target: 6,
+        spend_target: 2,
+        min_sat_per_vbyte: 1,
+        max_sat_per_vbyte: 200,
+    };
+}
+
+/// The gas price of Ethereum transactions is the one suggested by
+/// the node unless a fixed one is configured, and never more than the
+/// maximum. Both are in wei.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GasPrice {
+    pub fixed: Option<u64>,
+    pub max: u64,
+}
+
+impl GasPrice {
+    pub const DEFAULT: GasPrice = GasPrice {
+        fixed: None,
+        max: 200 * WEI_IN_GWEI,
+    };
+}
+
+const WEI_IN_GWEI: u64 = 1_000_000_000;
+
+impl Safety {
+    pub const BITCOIN: Safety = Safety {
+        block_time: Duration::from_secs(600),
+        blocks: 6,
+    };
+    pub const ETHEREUM: Safety = Safety {
+        block_time: Duration::from_secs(15),
+        blocks: 40,
+    };
+
+    /// How long before an expiry nectar must stop acting on an HTLC,
+    /// `None` if it is too long to be represented, which validation
+    /// rules out.
+    pub fn margin(&self) -> Option<Duration> {
+        self.block_time.checked_mul(self.blocks)
+    }
+}
+
+impl Bitcoin {
+    pub const DEFAULT_CONFIRMATIONS: u32 = 3;
+}
+
+impl Ethereum {
+    pub const DEFAULT_CONFIRMATIONS: u32 = 12;
+    pub const MAINNET_DAI_CONTRACT: &'static str = "6b175474e89094c44da98b954eedeac495271d0f";
+}
+
+impl Settings {
+    pub fn read(config_file: &Path) -> anyhow::Result<Settings> {
+        let file = File::read(config_file).map_err(|e| {
+            anyhow::anyhow!(
+                "failed to read configuration file {}: {:#}",
+                config_file.display(),
+                e
+            )
+        })?;
+        let settings = Settings::from_file(file)?;
+
+        Ok(settings)
+    }
+
+    /// Validate every value of the configuration file, collecting all
+    /// the problems found instead of stopping at the first one.
+    pub fn from_file(file: File) -> Result<Settings, InvalidSettings> {
+        let mut errors = Vec::new();
+
+        let data_dir = file.data_dir.or_else(default_data_dir);
+        let data_dir = required(data_dir, "data_dir", &mut errors);
+
+        let maker = file.maker.unwrap_or_default();
+        let spread = validate(maker.spread, "maker.spread", &mut errors, Spread::new);
+        let max_sell_amount = validate(
+            maker.max_sell,
+            "maker.max_sell",
+            &mut errors,
+            bitcoin::Amount::from_btc,
+        );
+        let max_sell_dai_amount = maker
+            .max_sell_dai
+            .map(|dai| dai::Amount::from_atto(BigUint::from(dai) * &*dai::DAI_DEC));
+        let kraken_ohlc_interval = match maker.kraken.and_then(|kraken| kraken.ohlc_interval) {
+            Some(minutes) => validate(
+                Some(minutes),
+                "maker.kraken.ohlc_interval",
+                &mut errors,
+                OhlcInterval::new,
+            ),
+            None => Some(OhlcInterval::default()),
+        };
+
+        let bitcoin = file.bitcoin.unwrap_or_default();
+        let network = validate(
+            bitcoin.network,
+            "bitcoin.network",
+            &mut errors,
+            parse_network,
+        );
+        let bitcoind = bitcoin.bitcoind.unwrap_or_default();
+        let bitcoind_url = validate(
+            bitcoind.node_url.clone(),
+            "bitcoin.bitcoind.node_url",
+            &mut errors,
+            parse_url,
+        );
+        let bitcoind_auth = bitcoind_auth(bitcoind, &mut errors);
+        let bitcoin_safety = safety(bitcoin.safety, Safety::BITCOIN, "bitcoin", &mut errors);
+        let bitcoin_confirmations = confirmations(
+            bitcoin.confirmations,
+            Bitcoin::DEFAULT_CONFIRMATIONS,
+            "bitcoin",
+            &mut errors,
+        );
+        let bitcoin_fees = bitcoin_fees(bitcoin.fees, &mut errors);
+
+        let ethereum = file.ethereum.unwrap_or_default();
+        let ethereum_url = validate(
+            ethereum.node_url,
+            "ethereum.node_url",
+            &mut errors,
+            parse_url,
+        );
+        let ethereum_safety = safety(ethereum.safety, Safety::ETHEREUM, "ethereum", &mut errors);
+        let ethereum_confirmations = confirmations(
+            ethereum.confirmations,
+            Ethereum::DEFAULT_CONFIRMATIONS,
+            "ethereum",
+            &mut errors,
+        );
+        let gas_price = gas_price(ethereum.gas_price, &mut errors);
+        let dai_contract = validate(
+            Some(
+                ethereum
+                    .dai_contract
+                    .unwrap_or_else(|| Ethereum::MAINNET_DAI_CONTRACT.to_owned()),
+            ),
+            "ethereum.dai_contract",
+            &mut errors,
+            parse_address,
+        );
+
+        match (
+            data_dir,
+            spread,
+            max_sell_amount,
+            kraken_ohlc_interval,
+            network,
+            bitcoind_url,
+            ethereum_url,
+            dai_contract,
+        ) {
+            (
+                Some(data_dir),
+                Some(spread),
+                Some(max_sell_amount),
+                Some(kraken_ohlc_interval),
+                Some(network),
+                Some(bitcoind_url),
+                Some(ethereum_url),
+                Some(dai_contract),
+            ) if errors.is_empty() => Ok(Settings {
+                data_dir,
+                maker: Maker {
+                    spread,
+                    max_sell_amount,
+                    max_sell_dai_amount,
+                    kraken_ohlc_interval,
+                },
+                bitcoin: Bitcoin {
+                    network,
+                    bitcoind: Bitcoind {
+                        node_url: bitcoind_url,
+                        auth: bitcoind_auth,
+                    },
+                    safety: bitcoin_safety,
+                    confirmations: bitcoin_confirmations,
+                    fees: bitcoin_fees,
+                },
+                ethereum: Ethereum {
+                    node_url: ethereum_url,
+                    safety: ethereum_safety,
+                    confirmations: ethereum_confirmations,
+                    gas_price,
+                    dai_contract,
+                },
+            }),
+            _ => Err(InvalidSettings(errors)),
+        }
+    }
+}
+
+impl From<Settings> for File {
+    fn from(settings: Settings) -> Self {
+        File {
+            data_dir: Some(settings.data_dir),
+            maker: Some(file::Maker {
+                spread: Some(settings.maker.spread.as_permyriad()),
+                max_sell: Some(settings.maker.max_sell_amount.as_btc()),
+                max_sell_dai: settings
+                    .maker
+                    .max_sell_dai_amount
+                    .and_then(|amount| (amount.as_atto() / &*dai::DAI_DEC).to_u64()),
+                kraken: Some(file::Kraken {
+                    ohlc_interval: Some(settings.maker.kraken_ohlc_interval.as_minutes()),
+                }),
+            }),
+            bitcoin: Some(file::Bitcoin {
+                network: Some(network_name(settings.bitcoin.network).to_owned()),
+                bitcoind: Some(settings.bitcoin.bitcoind.into()),
+                safety: Some(settings.bitcoin.safety.into()),
+                confirmations: Some(settings.bitcoin.confirmations),
+                fees: Some(settings.bitcoin.fees.into()),
+            }),
+            ethereum: Some(file::Ethereum {
+                node_url: Some(settings.ethereum.node_url.into_string()),
+                safety: Some(settings.ethereum.safety.into()),
+                confirmations: Some(settings.ethereum.confirmations),
+                gas_price: Some(settings.ethereum.gas_price.into()),
+                dai_contract: Some(format!(
+                    "0x{}",
+                    hex::encode(settings.ethereum.dai_contract.as_bytes())
+                )),
+            }),
+        }
+    }
+}
+
+/// The password is left out so that dumping the configuration does
+/// not print it, it has to be set again by hand.
+impl From<Bitcoind> for file::Bitcoind {
+    fn from(bitcoind: Bitcoind) -> Self {
+        let mut file = file::Bitcoind {
+            node_url: Some(bitcoind.node_url.into_string()),
+            ..file::Bitcoind::default()
+        };
+        match bitcoind.auth {
+            jsonrpc::Auth::None => {}
+            jsonrpc::Auth::UserPass(credentials) => file.rpc_user = Some(credentials.user),
+            jsonrpc::Auth::Cookie(cookie) => file.cookie_file = Some(cookie.path().to_path_buf()),
+        }
+
+        file
+    }
+}
+
+impl From<Safety> for file::Safety {
+    fn from(safety: Safety) -> Self {
+        file::Safety {
+            block_time: Some(safety.block_time.as_secs()),
+            blocks: Some(safety.blocks),
+        }
+    }
+}
+
+impl From<BitcoinFees> for file::BitcoinFees {
+    fn from(fees: BitcoinFees) -> Self {
+        file::BitcoinFees {
+            fund_target: Some(fees.fund_target),
+            spend_target: Some(fees.spend_target),
+            min_sat_per_vbyte: Some(fees.min_sat_per_vbyte),
+            max_sat_per_vbyte: Some(fees.max_sat_per_vbyte),
+        }
+    }
+}
+
+impl From<GasPrice> for file::GasPrice {
+    fn from(gas_price: GasPrice) -> Self {
+        file::GasPrice {
+            fixed_gwei: gas_price.fixed.map(|wei| wei / WEI_IN_GWEI),
+            max_gwei: Some(gas_price.max / WEI_IN_GWEI),
+        }
+    }
+}
+
+/// The default location of the configuration file, in the
+/// platform-specific configuration directory.
+pub fn default_config_file() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "nectar")
+        .map(|dirs| dirs.config_dir().join("config.toml"))
+}
+
+fn default_data_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "nectar").map(|dirs| dirs.data_dir().to_path_buf())
+}
+
+fn parse_network(network: String) -> anyhow::Result<::bitcoin::Network> {
+    match network.as_str() {
+        "mainnet" | "bitcoin" => Ok(::bitcoin::Network::Bitcoin),
+        "testnet" => Ok(::bitcoin::Network::Testnet),
+        "regtest" => Ok(::bitcoin::Network::Regtest),
+        _ => anyhow::bail!(
+            "unknown network {}, expected one of mainnet, testnet or regtest",
+            network
+        ),
+    }
+}
+
+fn network_name(network: ::bitcoin::Network) -> &'static str {
+    match network {
+        ::bitcoin::Network::Bitcoin => "mainnet",
+        ::bitcoin::Network::Testnet => "testnet",
+        ::bitcoin::Network::Regtest => "regtest",
+    }
+}
+
+fn parse_url(url: String) -> anyhow::Result<Url> {
+    Ok(Url::parse(&url)?)
+}
+
+fn parse_address(address: String) -> anyhow::Result<Address> {
+    Address::from_str(address.trim_start_matches("0x"))
+        .map_err(|_| anyhow::anyhow!("{} is not an Ethereum address", address))
+}
+
+/// Without a cookie file or user, credentials can still be part of
+/// the node URL.
+fn bitcoind_auth(file: file::Bitcoind, errors: &mut Vec<String>) -> jsonrpc::Auth {
+    match (file.cookie_file, file.rpc_user, file.rpc_password) {
+        (None, None, None) => jsonrpc::Auth::None,
+        (Some(path), None, None) => jsonrpc::Auth::Cookie(jsonrpc::CookieFile::new(path)),
+        (None, Some(user), Some(password)) => {
+            jsonrpc::Auth::UserPass(jsonrpc::Credentials::new(user, password))
+        }
+        (None, _, _) => {
+            errors.push(
+                "`bitcoin.bitcoind.rpc_user` and `bitcoin.bitcoind.rpc_password` must be set together"
+                    .to_owned(),
+            );
+            jsonrpc::Auth::None
+        }
+        (Some(_), _, _) => {
+            errors.push(
+                "`bitcoin.bitcoind.cookie_file` cannot be combined with `bitcoin.bitcoind.rpc_user` and `bitcoin.bitcoind.rpc_password`"
+                    .to_owned(),
+            );
+            jsonrpc::Auth::None
+        }
+    }
+}
+
+/// Missing values fall back on the defaults of the chain.
+fn safety(
+    file: Option<file::Safety>,
+    default: Safety,
+    chain: &str,
+    errors: &mut Vec<String>,
+) -> Safety {
+    let file = file.unwrap_or_default();
+
+    let block_time = match file.block_time {
+        Some(0) => {
+            errors.push(format!("`{}.safety.block_time` must not be zero", chain));
+            default.block_time
+        }
+        Some(seconds) => Duration::from_secs(seconds),
+        None => default.block_time,
+    };
+
+    let safety = Safety {
+        block_time,
+        blocks: file.blocks.unwrap_or(default.blocks),
+    };
+    if safety.margin().is_none() {
+        errors.push(format!(
+            "`{}.safety.block_time` times `{}.safety.blocks` is too long",
+            chain, chain
+        ));
+        return default;
+    }
+
+    safety
+}
+
+/// A transaction is only seen once mined, hence at least one
+/// confirmation is always required.
+fn confirmations(file: Option<u32>, default: u32, chain: &str, errors: &mut Vec<String>) -> u32 {
+    match file {
+        Some(0) => {
+            errors.push(format!("`{}.confirmations` must not be zero", chain));
+            default
+        }
+        Some(confirmations) => confirmations,
+        None => default,
+    }
+}
+
+/// bitcoind only estimates fees for targets up to 1008 blocks.
+fn bitcoin_fees(file: Option<file::BitcoinFees>, errors: &mut Vec<String>) -> BitcoinFees {
+    let file = file.unwrap_or_default();
+    let default = BitcoinFees::DEFAULT;
+
+    let mut target = |target: Option<u32>, default: u32, key: &str| match target {
+        Some(blocks) if blocks == 0 || blocks > 1008 => {
+            errors.push(format!(
+                "`bitcoin.fees.{}` must be between 1 and 1008 blocks",
+                key
+            ));
+            default
+        }
+        Some(blocks) => blocks,
+        None => default,
+    };
+    let fund_target = target(file.fund_target, default.fund_target, "fund_target");
+    let spend_target = target(file.spend_target, default.spend_target, "spend_target");
+
+    let min_sat_per_vbyte = file.min_sat_per_vbyte.unwrap_or(default.min_sat_per_vbyte);
+    let max_sat_per_vbyte = file.max_sat_per_vbyte.unwrap_or(default.max_sat_per_vbyte);
+    if min_sat_per_vbyte > max_sat_per_vbyte {
+        errors.push(
+            "`bitcoin.fees.min_sat_per_vbyte` must not exceed `bitcoin.fees.max_sat_per_vbyte`"
+                .to_owned(),
+        );
+    }
+
+    BitcoinFees {
+        fund_target,
+        spend_target,
+        min_sat_per_vbyte,
+        max_sat_per_vbyte,
+    }
+}
+
+/// The configuration is in gwei, a more readable unit than wei.
+fn gas_price(file: Option<file::GasPrice>, errors: &mut Vec<String>) -> GasPrice {
+    let file = file.unwrap_or_default();
+    let default = GasPrice::DEFAULT;
+
+    let max = match file.max_gwei {
+        Some(0) => {
+            errors.push("`ethereum.gas_price.max_gwei` must not be zero".to_owned());
+            default.max
+        }
+        Some(gwei) => gwei.saturating_mul(WEI_IN_GWEI),
+        None => default.max,
+    };
+    let fixed = file.fixed_gwei.map(|gwei| gwei.saturating_mul(WEI_IN_GWEI));
+    if fixed.map_or(false, |fixed| fixed > max) {
+        errors.push(
+            "`ethereum.gas_price.fixed_gwei` must not exceed `ethereum.gas_price.max_gwei`"
+                .to_owned(),
+        );
+    }
+
+    GasPrice { fixed, max }
+}
+
+fn required<T>(value: Option<T>, key: &str, errors: &mut Vec<String>) -> Option<T> {
+    if value.is_none() {
+        errors.push(format!("`{}` is missing", key));
+    }
+
+    value
+}
+
+fn validate<T, U, F>(value: Option<T>, key: &str, errors: &mut Vec<String>, f: F) -> Option<U>
+where
+    F: FnOnce(T) -> anyhow::Result<U>,
+{
+    let value = required(value, key, errors)?;
+
+    match f(value) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            errors.push(format!("`{}` is invalid: {:#}", key, e));
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub struct InvalidSettings(Vec<String>);
+
+impl fmt::Display for InvalidSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for error in self.0.iter() {
+            writeln!(f, "  - {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_file() -> File {
+        File {
+            data_dir: Some(PathBuf::from("/tmp/nectar")),
+            maker: Some(file::Maker {
+                spread: Some(500),
+                max_sell: Some(0.5),
+                max_sell_dai: None,
+                kraken: None,
+            }),
+            bitcoin: Some(file::Bitcoin {
+                network: Some("regtest".into()),
+                bitcoind: Some(file::Bitcoind {
+                    node_url: Some("http://localhost:18443".into()),
+                    ..file::Bitcoind::default()
+                }),
+                safety: None,
+                confirmations: None,
+                fees: None,
+            }),
+            ethereum: Some(file::Ethereum {
+                node_url: Some("http://localhost:8545".into()),
+                safety: None,
+                confirmations: None,
+                gas_price: None,
+                dai_contract: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn given_valid_file_then_settings() {
+        let settings = Settings::from_file(valid_file()).unwrap();
+
+        assert_eq!(settings.bitcoin.network, ::bitcoin::Network::Regtest);
+        assert_eq!(
+            settings.maker.max_sell_amount,
+            bitcoin::Amount::from_sat(50_000_000)
+        );
+        assert_eq!(settings.maker.kraken_ohlc_interval, OhlcInterval::default());
+        assert_eq!(settings.bitcoin.safety, Safety::BITCOIN);
+        assert_eq!(settings.ethereum.safety, Safety::ETHEREUM);
+        assert_eq!(
+            settings.bitcoin.confirmations,
+            Bitcoin::DEFAULT_CONFIRMATIONS
+        );
+        assert_eq!(
+            settings.ethereum.confirmations,
+            Ethereum::DEFAULT_CONFIRMATIONS
+        );
+        assert_eq!(settings.bitcoin.fees, BitcoinFees::DEFAULT);
+        assert_eq!(settings.ethereum.gas_price, GasPrice::DEFAULT);
+        assert_eq!(settings.maker.max_sell_dai_amount, None);
+        assert_eq!(
+            settings.ethereum.dai_contract,
+            Address::from_str(Ethereum::MAINNET_DAI_CONTRACT).unwrap()
+        );
+    }
+
+    #[test]
+    fn max_sell_dai_is_configured_in_dai() {
+        let mut file = valid_file();
+        file.maker.as_mut().unwrap().max_sell_dai = Some(1_000);
+
+        let settings = Settings::from_file(file).unwrap();
+
+        assert_eq!(
+            settings.maker.max_sell_dai_amount,
+            Some(dai::Amount::from_atto(
+                BigUint::from(1_000u32) * &*dai::DAI_DEC
+            ))
+        );
+        assert_eq!(
+            File::from(settings).maker.unwrap().max_sell_dai,
+            Some(1_000)
+        );
+    }
+
+    #[test]
+    fn given_partial_safety_then_defaults_fill_the_gaps() {
+        let mut file = valid_file();
+        file.ethereum.as_mut().unwrap().safety = Some(file::Safety {
+            block_time: None,
+            blocks: Some(10),
+        });
+
+        let settings = Settings::from_file(file).unwrap();
+
+        assert_eq!(
+            settings.ethereum.safety.margin(),
+            Some(Duration::from_secs(150))
+        );
+    }
+
+    #[test]
+    fn given_overflowing_safety_margin_then_error() {
+        let mut file = valid_file();
+        file.bitcoin.as_mut().unwrap().safety = Some(file::Safety {
+            block_time: Some(u64::MAX),
+            blocks: Some(2),
+        });
+
+        let InvalidSettings(errors) = Settings::from_file(file).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "`bitcoin.safety.block_time` times `bitcoin.safety.blocks` is too long".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn given_zero_block_time_then_error() {
+        let mut file = valid_file();
+        file.bitcoin.as_mut().unwrap().safety = Some(file::Safety {
+            block_time: Some(0),
+            blocks: None,
+        });
+
+        let result = Settings::from_file(file);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn given_zero_confirmations_then_error() {
+        let mut file = valid_file();
+        file.ethereum.as_mut().unwrap().confirmations = Some(0);
+
+        let InvalidSettings(errors) = Settings::from_file(file).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec!["`ethereum.confirmations` must not be zero".to_owned()]
+        );
+    }
+
+    #[test]
+    fn given_invalid_bitcoin_fees_then_all_are_reported() {
+        let mut file = valid_file();
+        file.bitcoin.as_mut().unwrap().fees = Some(file::BitcoinFees {
+            fund_target: Some(0),
+            spend_target: Some(2_000),
+            min_sat_per_vbyte: Some(50),
+            max_sat_per_vbyte: Some(10),
+        });
+
+        let InvalidSettings(errors) = Settings::from_file(file).unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn given_fixed_gas_price_above_maximum_then_error() {
+        let mut file = valid_file();
+        file.ethereum.as_mut().unwrap().gas_price = Some(file::GasPrice {
+            fixed_gwei: Some(300),
+            max_gwei: Some(100),
+        });
+
+        let InvalidSettings(errors) = Settings::from_file(file).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "`ethereum.gas_price.fixed_gwei` must not exceed `ethereum.gas_price.max_gwei`"
+                    .to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn gas_price_is_configured_in_gwei() {
+        let mut file = valid_file();
+        file.ethereum.as_mut().unwrap().gas_price = Some(file::GasPrice {
+            fixed_gwei: Some(20),
+            max_gwei: None,
+        });
+
+        let settings = Settings::from_file(file).unwrap();
+
+        assert_eq!(
+            settings.ethereum.gas_price,
+            GasPrice {
+                fixed: Some(20_000_000_000),
+                max: GasPrice::DEFAULT.max,
+            }
+        );
+    }
+
+    #[test]
+    fn given_cookie_file_then_cookie_auth() {
+        let mut file = valid_file();
+        file.bitcoin.as_mut().unwrap().bitcoind = Some(file::Bitcoind {
+            node_url: Some("http://localhost:18443".into()),
+            cookie_file: Some(PathBuf::from("/tmp/.cookie")),
+            ..file::Bitcoind::default()
+        });
+
+        let settings = Settings::from_file(file).unwrap();
+
+        match settings.bitcoin.bitcoind.auth {
+            jsonrpc::Auth::Cookie(cookie) => assert_eq!(cookie.path(), Path::new("/tmp/.cookie")),
+            auth => panic!("expected cookie authentication, got {:?}", auth),
+        }
+    }
+
+    #[test]
+    fn given_rpc_user_without_password_then_error() {
+        let mut file = valid_file();
+        file.bitcoin.as_mut().unwrap().bitcoind = Some(file::Bitcoind {
+            node_url: Some("http://localhost:18443".into()),
+            rpc_user: Some("nectar".into()),
+            ..file::Bitcoind::default()
+        });
+
+        let InvalidSettings(errors) = Settings::from_file(file).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "`bitcoin.bitcoind.rpc_user` and `bitcoin.bitcoind.rpc_password` must be set together"
+                    .to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn given_rpc_password_then_it_is_not_dumped() {
+        let mut file = valid_file();
+        file.bitcoin.as_mut().unwrap().bitcoind = Some(file::Bitcoind {
+            node_url: Some("http://localhost:18443".into()),
+            rpc_user: Some("nectar".into()),
+            rpc_password: Some("secret".into()),
+            ..file::Bitcoind::default()
+        });
+        let settings = Settings::from_file(file).unwrap();
+
+        let bitcoind = File::from(settings).bitcoin.unwrap().bitcoind.unwrap();
+
+        assert_eq!(bitcoind.rpc_user, Some("nectar".to_owned()));
+        assert_eq!(bitcoind.rpc_password, None);
+    }
+
+    #[test]
+    fn settings_roundtrip_through_file() {
+        let settings = Settings::from_file(valid_file()).unwrap();
+
+        let file = File::from(settings);
+
+        let mut expected = valid_file();
+        expected.maker.as_mut().unwrap().kraken = Some(file::Kraken {
+            ohlc_interval: Some(30),
+        });
+        expected.bitcoin.as_mut().unwrap().bitcoind = Some(file::Bitcoind {
+            node_url: Some("http://localhost:18443/".into()),
+            ..file::Bitcoind::default()
+        });
+        expected.bitcoin.as_mut().unwrap().safety = Some(file::Safety {
+            block_time: Some(600),
+            blocks: Some(6),
+        });
+        expected.bitcoin.as_mut().unwrap().confirmations = Some(3);
+        expected.bitcoin.as_mut().unwrap().fees = Some(file::BitcoinFees {
+            fund_target: Some(6),
+            spend_target: Some(2),
+            min_sat_per_vbyte: Some(1),
+            max_sat_per_vbyte: Some(200),
+        });
+        expected.ethereum = Some(file::Ethereum {
+            node_url: Some("http://localhost:8545/".into()),
+            safety: Some(file::Safety {
+                block_time: Some(15),
+                blocks: Some(40),
+            }),
+            confirmations: Some(12),
+            gas_price: Some(file::GasPrice {
+                fixed_gwei: None,
+                max_gwei: Some(200),
+            }),
+            dai_contract: Some("0x6b175474e89094c44da98b954eedeac495271d0f".into()),
+        });
+        assert_eq!(file, expected);
+    }
+
+    #[test]
+    fn given_spread_above_hundred_percent_then_error() {
+        let mut file = valid_file();
+        file.maker.as_mut().unwrap().spread = Some(10_001);
+
+        let result = Settings::from_file(file);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn given_several_invalid_values_then_all_are_reported() {
+        let file = File {
+            data_dir: Some(PathBuf::from("/tmp/nectar")),
+            maker: Some(file::Maker {
+                spread: Some(10_001),
+                max_sell: Some(-1.0),
+                max_sell_dai: None,
+                kraken: Some(file::Kraken {
+                    ohlc_interval: Some(2),
+                }),
+            }),
+            bitcoin: Some(file::Bitcoin {
+                network: Some("simnet".into()),
+                bitcoind: None,
+                safety: None,
+                confirmations: None,
+                fees: None,
+            }),
+            ethereum: Some(file::Ethereum {
+                node_url: Some("not a url".into()),
+                safety: None,
+                confirmations: None,
+                gas_price: None,
+                dai_contract: Some("0x1234".into()),
+            }),
+        };
+
+        let InvalidSettings(errors) = Settings::from_file(file).unwrap_err();
+
+        assert_eq!(errors.len(), 7);
+    }
+}
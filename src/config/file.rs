@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The content of the configuration file as written by the operator.
+///
+/// Every field is optional so that all missing or invalid values can be
+/// reported at once when validating it into `Settings`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct File {
+    pub data_dir: Option<PathBuf>,
+    pub maker: Option<Maker>,
+    pub bitcoin: Option<Bitcoin>,
+    pub ethereum: Option<Ethereum>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Maker {
+    /// Spread in permyriad (per ten thousand)
+    pub spread: Option<u16>,
+    /// Maximum amount of bitcoin to sell in a single order, in BTC
+    pub max_sell: Option<f64>,
+    /// Maximum amount of dai to sell in a single order, in DAI. No dai
+    /// is sold if it is not set
+    pub max_sell_dai: Option<u64>,
+    pub kraken: Option<Kraken>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Kraken {
+    /// OHLC interval in minutes
+    pub ohlc_interval: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bitcoin {
+    pub network: Option<String>,
+    pub bitcoind: Option<Bitcoind>,
+    pub safety: Option<Safety>,
+    /// Number of confirmations required on the counterparty's transactions
+    pub confirmations: Option<u32>,
+    pub fees: Option<BitcoinFees>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bitcoind {
+    pub node_url: Option<String>,
+    /// Path of bitcoind's `.cookie` file to authenticate with
+    pub cookie_file: Option<PathBuf>,
+    pub rpc_user: Option<String>,
+    /// Never written by `dump-config`, it has to be set by hand
+    pub rpc_password: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ethereum {
+    pub node_url: Option<String>,
+    pub safety: Option<Safety>,
+    /// Number of confirmations required on the counterparty's transactions
+    pub confirmations: Option<u32>,
+    pub gas_price: Option<GasPrice>,
+    /// Address of the DAI token contract, mainnet's by default
+    pub dai_contract: Option<String>,
+}
+
+/// How long before an HTLC expiry nectar stops acting on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Safety {
+    /// Expected time between two blocks, in seconds
+    pub block_time: Option<u64>,
+    /// Number of blocks needed to get a transaction mined and confirmed
+    pub blocks: Option<u32>,
+}
+
+/// How much to pay for Bitcoin transactions, based on bitcoind's fee
+/// estimation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BitcoinFees {
+    /// Confirmation target in blocks for funding transactions
+    pub fund_target: Option<u32>,
+    /// Confirmation target in blocks for redeem and refund transactions
+    pub spend_target: Option<u32>,
+    /// Lowest fee rate to pay, in sat/vbyte
+    pub min_sat_per_vbyte: Option<u64>,
+    /// Highest fee rate to pay, in sat/vbyte
+    pub max_sat_per_vbyte: Option<u64>,
+}
+
+/// How much to pay for Ethereum transactions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GasPrice {
+    /// Gas price to pay, in gwei, instead of the one suggested by the node
+    pub fixed_gwei: Option<u64>,
+    /// Highest gas price to pay, in gwei
+    pub max_gwei: Option<u64>,
+}
+
+impl File {
+    pub fn read(path: &std::path::Path) -> anyhow::Result<File> {
+        let content = std::fs::read_to_string(path)?;
+        let file = toml::from_str(&content)?;
+
+        Ok(file)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let content = toml::to_string(self)?;
+
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_full_config_file() {
+        let content = r#"
+data_dir = "/tmp/nectar"
+
+[maker]
+spread = 500
+max_sell = 0.5
+max_sell_dai = 10000
+
+[maker.kraken]
+ohlc_interval = 30
+
+[bitcoin]
+network = "regtest"
+confirmations = 3
+
+[bitcoin.bitcoind]
+node_url = "http://localhost:18443"
+cookie_file = "/home/nectar/.bitcoin/regtest/.cookie"
+
+[bitcoin.safety]
+block_time = 600
+blocks = 6
+
+[bitcoin.fees]
+spend_target = 2
+max_sat_per_vbyte = 100
+
+[ethereum]
+node_url = "http://localhost:8545"
+confirmations = 12
+dai_contract = "0x6b175474e89094c44da98b954eedeac495271d0f"
+
+[ethereum.safety]
+blocks = 40
+
+[ethereum.gas_price]
+max_gwei = 150
+"#;
+
+        let file: File = toml::from_str(content).unwrap();
+
+        assert_eq!(
+            file,
+            File {
+                data_dir: Some(PathBuf::from("/tmp/nectar")),
+                maker: Some(Maker {
+                    spread: Some(500),
+                    max_sell: Some(0.5),
+                    max_sell_dai: Some(10_000),
+                    kraken: Some(Kraken {
+                        ohlc_interval: Some(30)
+                    }),
+                }),
+                bitcoin: Some(Bitcoin {
+                    network: Some("regtest".into()),
+                    bitcoind: Some(Bitcoind {
+                        node_url: Some("http://localhost:18443".into()),
+                        cookie_file: Some(PathBuf::from("/home/nectar/.bitcoin/regtest/.cookie")),
+                        rpc_user: None,
+                        rpc_password: None,
+                    }),
+                    safety: Some(Safety {
+                        block_time: Some(600),
+                        blocks: Some(6),
+                    }),
+                    confirmations: Some(3),
+                    fees: Some(BitcoinFees {
+                        fund_target: None,
+                        spend_target: Some(2),
+                        min_sat_per_vbyte: None,
+                        max_sat_per_vbyte: Some(100),
+                    }),
+                }),
+                ethereum: Some(Ethereum {
+                    node_url: Some("http://localhost:8545".into()),
+                    safety: Some(Safety {
+                        block_time: None,
+                        blocks: Some(40),
+                    }),
+                    confirmations: Some(12),
+                    gas_price: Some(GasPrice {
+                        fixed_gwei: None,
+                        max_gwei: Some(150),
+                    }),
+                    dai_contract: Some("0x6b175474e89094c44da98b954eedeac495271d0f".into()),
+                }),
+            }
+        )
+    }
+
+    #[test]
+    fn deserialize_empty_config_file() {
+        let file: File = toml::from_str("").unwrap();
+
+        assert_eq!(file, File::default())
+    }
+
+    #[test]
+    fn serialize_then_deserialize_config_file() {
+        let file = File {
+            data_dir: Some(PathBuf::from("/tmp/nectar")),
+            maker: Some(Maker {
+                spread: Some(500),
+                max_sell: Some(0.5),
+                max_sell_dai: None,
+                kraken: Some(Kraken {
+                    ohlc_interval: Some(30),
+                }),
+            }),
+            bitcoin: None,
+            ethereum: Some(Ethereum {
+                node_url: Some("http://localhost:8545".into()),
+                safety: Some(Safety {
+                    block_time: Some(15),
+                    blocks: Some(40),
+                }),
+                confirmations: None,
+                gas_price: Some(GasPrice {
+                    fixed_gwei: Some(20),
+                    max_gwei: None,
+                }),
+                dai_contract: None,
+            }),
+        };
+
+        let content = file.to_toml().unwrap();
+
+        assert_eq!(toml::from_str::<File>(&content).unwrap(), file)
+    }
+
+    #[test]
+    fn reject_unknown_fields() {
+        let result = toml::from_str::<File>("[maker]\nspred = 500\n");
+
+        assert!(result.is_err())
+    }
+}
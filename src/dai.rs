@@ -2,7 +2,7 @@ use crate::bitcoin::{self, SATS_IN_BITCOIN_EXP};
 use crate::float_maths::{divide_pow_ten_trunc, multiply_pow_ten, truncate};
 use crate::rate::Rate;
 use conquer_once::Lazy;
-use num::{pow::Pow, BigUint, ToPrimitive};
+use num::{pow::Pow, BigUint, CheckedSub, ToPrimitive};
 
 pub const ATTOS_IN_DAI_EXP: u16 = 18;
 pub static DAI_DEC: Lazy<BigUint> = Lazy::new(|| BigUint::from(10u16).pow(ATTOS_IN_DAI_EXP));
@@ -44,6 +44,10 @@ impl Amount {
         self.0.clone()
     }
 
+    pub fn checked_sub(self, rhs: Self) -> Option<Amount> {
+        self.0.checked_sub(&rhs.0).map(Amount)
+    }
+
     /// Allow to know the worth of self in bitcoin asset using the given conversion rate.
     /// Truncation may be done during the conversion to allow a result in satoshi
     pub fn worth_in(&self, dai_to_btc_rate: Rate) -> anyhow::Result<bitcoin::Amount> {
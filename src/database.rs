@@ -0,0 +1,566 @@
+use crate::ethereum_wallet::Sent;
+use crate::maker::TakenOrder;
+use crate::ongoing_swaps::Peer;
+use crate::swap::{hbit, herc20, Direction};
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use comit::{
+    asset,
+    ethereum::{self, ChainId},
+    identity, Secret, SecretHash, Timestamp,
+};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fs, path::Path};
+
+/// Stores every swap in progress together with the steps already
+/// completed, so that swaps can be resumed after a restart.
+///
+/// The secrets of the swaps are stored in plaintext, anyone able to
+/// read the database can take the funds locked in their HTLCs. Only
+/// the user running nectar has access to it.
+#[derive(Debug, Clone)]
+pub struct Database {
+    db: sled::Db,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(path)
+            .with_context(|| format!("failed to create database at {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o700)).with_context(|| {
+                format!("failed to restrict access to database {}", path.display())
+            })?;
+        }
+
+        let db = sled::open(path)
+            .with_context(|| format!("failed to open database at {}", path.display()))?;
+
+        Ok(Database { db })
+    }
+
+    pub fn insert_swap(&self, id: &SwapId, swap: &Swap) -> anyhow::Result<()> {
+        self.save(id, swap)
+    }
+
+    /// Load all the swaps that have not been removed, i.e. that were
+    /// still in progress when nectar stopped.
+    pub fn all_swaps(&self) -> anyhow::Result<Vec<(SwapId, Swap)>> {
+        self.db
+            .iter()
+            .map(|item| {
+                let (key, value) = item?;
+                let id = SwapId::from_bytes(&key)?;
+                let swap = serde_json::from_slice(&value)
+                    .with_context(|| format!("failed to deserialize swap {}", id))?;
+
+                Ok((id, swap))
+            })
+            .collect()
+    }
+
+    pub fn remove_swap(&self, id: &SwapId) -> anyhow::Result<()> {
+        self.db.remove(id.as_bytes())?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+
+    pub fn record_step(&self, id: &SwapId, step: Step) -> anyhow::Result<()> {
+        let mut swap = self.get(id)?;
+
+        if !swap.steps.contains(&step) || swap.in_flight_of(step).is_some() {
+            swap.complete(step);
+            self.save(id, &swap)?;
+        }
+
+        Ok(())
+    }
+
+    /// Record the transaction of `step` before it is broadcast,
+    /// replacing the one recorded before, if any.
+    pub fn record_in_flight(
+        &self,
+        id: &SwapId,
+        step: Step,
+        in_flight: InFlight,
+    ) -> anyhow::Result<()> {
+        let mut swap = self.get(id)?;
+
+        swap.in_flight.retain(|(other, _)| *other != step);
+        swap.in_flight.push((step, in_flight));
+
+        self.save(id, &swap)
+    }
+
+    /// Forget the transaction of `step`, which was not broadcast.
+    pub fn forget_in_flight(&self, id: &SwapId, step: Step) -> anyhow::Result<()> {
+        let mut swap = self.get(id)?;
+
+        swap.in_flight.retain(|(other, _)| *other != step);
+
+        self.save(id, &swap)
+    }
+
+    pub fn in_flight(&self, id: &SwapId, step: Step) -> anyhow::Result<Option<InFlight>> {
+        let swap = self.get(id)?;
+
+        Ok(swap.in_flight_of(step).cloned())
+    }
+
+    /// The nonces of the Ethereum transactions sent by all the swaps for
+    /// steps which are not recorded yet.
+    pub fn ethereum_nonces_in_flight(&self) -> anyhow::Result<Vec<u64>> {
+        let nonces = self
+            .all_swaps()?
+            .into_iter()
+            .flat_map(|(_, swap)| swap.in_flight)
+            .filter_map(|(_, in_flight)| match in_flight {
+                InFlight::Ethereum(sent) => Some(sent.nonce),
+                InFlight::Bitcoin(_) => None,
+            })
+            .collect();
+
+        Ok(nonces)
+    }
+
+    pub fn start_of_swap(&self, id: &SwapId) -> anyhow::Result<NaiveDateTime> {
+        let swap = self.get(id)?;
+
+        Ok(swap.start_of_swap)
+    }
+
+    pub fn has_step(&self, id: &SwapId, step: Step) -> anyhow::Result<bool> {
+        let swap = self.get(id)?;
+
+        Ok(swap.steps.contains(&step))
+    }
+
+    /// Record the refund of the Bitcoin HTLC together with its step, so
+    /// that it is reported again when the swap is resumed.
+    pub fn record_hbit_refund(&self, id: &SwapId, event: &hbit::Refunded) -> anyhow::Result<()> {
+        let mut swap = self.get(id)?;
+
+        swap.refunds.hbit = Some(BitcoinTransaction(event.transaction.clone()));
+        swap.complete(Step::HbitRefunded);
+
+        self.save(id, &swap)
+    }
+
+    pub fn hbit_refund(&self, id: &SwapId) -> anyhow::Result<Option<hbit::Refunded>> {
+        let swap = self.get(id)?;
+
+        Ok(swap
+            .refunds
+            .hbit
+            .map(|BitcoinTransaction(transaction)| hbit::Refunded { transaction }))
+    }
+
+    /// Record the refund of the ERC20 HTLC together with its step, see
+    /// `record_hbit_refund`.
+    pub fn record_herc20_refund(
+        &self,
+        id: &SwapId,
+        event: &herc20::Refunded,
+    ) -> anyhow::Result<()> {
+        let mut swap = self.get(id)?;
+
+        swap.refunds.herc20 = Some(event.transaction.clone());
+        swap.complete(Step::Herc20Refunded);
+
+        self.save(id, &swap)
+    }
+
+    pub fn herc20_refund(&self, id: &SwapId) -> anyhow::Result<Option<herc20::Refunded>> {
+        let swap = self.get(id)?;
+
+        Ok(swap
+            .refunds
+            .herc20
+            .map(|transaction| herc20::Refunded { transaction }))
+    }
+
+    fn get(&self, id: &SwapId) -> anyhow::Result<Swap> {
+        let value = self
+            .db
+            .get(id.as_bytes())?
+            .ok_or_else(|| anyhow::anyhow!("swap {} not found in database", id))?;
+        let swap = serde_json::from_slice(&value)
+            .with_context(|| format!("failed to deserialize swap {}", id))?;
+
+        Ok(swap)
+    }
+
+    fn save(&self, id: &SwapId, swap: &Swap) -> anyhow::Result<()> {
+        let value = serde_json::to_vec(swap)?;
+        self.db.insert(id.as_bytes(), value)?;
+        // Losing track of a step can mean losing funds, make sure it
+        // hits the disk before moving on.
+        self.db.flush()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SwapId(uuid::Uuid);
+
+impl SwapId {
+    pub fn random() -> Self {
+        SwapId(uuid::Uuid::new_v4())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(SwapId(uuid::Uuid::from_slice(bytes)?))
+    }
+}
+
+impl std::fmt::Display for SwapId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A step of a swap that has been completed, either by us or by the
+/// counterparty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Step {
+    HbitFunded,
+    Herc20Deployed,
+    Herc20Funded,
+    Herc20Redeemed,
+    HbitRedeemed,
+    HbitRefunded,
+    Herc20Refunded,
+}
+
+/// A swap as stored in the database.
+///
+/// `secret` and `hbit_transient_sk` are enough to redeem and refund
+/// the HTLCs of the swap, see `Database` for how they are protected.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Swap {
+    peer: Peer,
+    direction: Direction,
+    hbit_params: HbitParams,
+    herc20_params: Herc20Params,
+    hbit_transient_sk: ::bitcoin::secp256k1::SecretKey,
+    hbit_final_identity: ::bitcoin::Address,
+    secret: Secret,
+    start_of_swap: NaiveDateTime,
+    pub steps: Vec<Step>,
+    in_flight: Vec<(Step, InFlight)>,
+    refunds: Refunds,
+}
+
+impl Swap {
+    fn complete(&mut self, step: Step) {
+        if !self.steps.contains(&step) {
+            self.steps.push(step);
+        }
+        self.in_flight.retain(|(other, _)| *other != step);
+    }
+
+    fn in_flight_of(&self, step: Step) -> Option<&InFlight> {
+        self.in_flight
+            .iter()
+            .find(|(other, _)| *other == step)
+            .map(|(_, in_flight)| in_flight)
+    }
+}
+
+/// The transactions sent for a step which is not recorded yet.
+///
+/// They are recorded before being broadcast so that a swap resumed
+/// after a restart does not send new ones: funding an HTLC twice
+/// would lose the second amount.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum InFlight {
+    /// The signed transaction, which is broadcast again as is
+    Bitcoin(BitcoinTransaction),
+    Ethereum(Sent),
+}
+
+/// The refunds made by nectar. Unlike the other events, they cannot
+/// be found again on the blockchain once the swap is resumed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Refunds {
+    hbit: Option<BitcoinTransaction>,
+    herc20: Option<ethereum::Transaction>,
+}
+
+/// A Bitcoin transaction, stored consensus-encoded in hex.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitcoinTransaction(pub ::bitcoin::Transaction);
+
+impl Serialize for BitcoinTransaction {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&::bitcoin::consensus::encode::serialize_hex(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for BitcoinTransaction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        let bytes = hex::decode(hex).map_err(D::Error::custom)?;
+        let transaction =
+            ::bitcoin::consensus::encode::deserialize(&bytes).map_err(D::Error::custom)?;
+
+        Ok(BitcoinTransaction(transaction))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct HbitParams {
+    network: ::bitcoin::Network,
+    asset: u64,
+    redeem_identity: identity::Bitcoin,
+    refund_identity: identity::Bitcoin,
+    expiry: Timestamp,
+    secret_hash: SecretHash,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Herc20Params {
+    asset: asset::Erc20,
+    redeem_identity: identity::Ethereum,
+    refund_identity: identity::Ethereum,
+    expiry: Timestamp,
+    chain_id: ChainId,
+    secret_hash: SecretHash,
+}
+
+impl From<&TakenOrder> for Swap {
+    fn from(order: &TakenOrder) -> Self {
+        let hbit_params = order.hbit_params;
+        let herc20_params = order.herc20_params.clone();
+
+        Swap {
+            peer: order.peer,
+            direction: order.direction,
+            hbit_params: HbitParams {
+                network: hbit_params.network,
+                asset: hbit_params.asset.as_sat(),
+                redeem_identity: hbit_params.redeem_identity,
+                refund_identity: hbit_params.refund_identity,
+                expiry: hbit_params.expiry,
+                secret_hash: hbit_params.secret_hash,
+            },
+            herc20_params: Herc20Params {
+                asset: herc20_params.asset,
+                redeem_identity: herc20_params.redeem_identity,
+                refund_identity: herc20_params.refund_identity,
+                expiry: herc20_params.expiry,
+                chain_id: herc20_params.chain_id,
+                secret_hash: herc20_params.secret_hash,
+            },
+            hbit_transient_sk: order.hbit_transient_sk,
+            hbit_final_identity: order.hbit_final_identity.clone(),
+            secret: order.secret,
+            start_of_swap: order.start_of_swap,
+            steps: Vec::new(),
+            in_flight: Vec::new(),
+            refunds: Refunds::default(),
+        }
+    }
+}
+
+impl From<Swap> for TakenOrder {
+    fn from(swap: Swap) -> Self {
+        TakenOrder {
+            peer: swap.peer,
+            direction: swap.direction,
+            hbit_params: hbit::Params {
+                network: swap.hbit_params.network,
+                asset: asset::Bitcoin::from_sat(swap.hbit_params.asset),
+                redeem_identity: swap.hbit_params.redeem_identity,
+                refund_identity: swap.hbit_params.refund_identity,
+                expiry: swap.hbit_params.expiry,
+                secret_hash: swap.hbit_params.secret_hash,
+            },
+            herc20_params: herc20::Params {
+                asset: swap.herc20_params.asset,
+                redeem_identity: swap.herc20_params.redeem_identity,
+                refund_identity: swap.herc20_params.refund_identity,
+                expiry: swap.herc20_params.expiry,
+                chain_id: swap.herc20_params.chain_id,
+                secret_hash: swap.herc20_params.secret_hash,
+            },
+            hbit_transient_sk: swap.hbit_transient_sk,
+            hbit_final_identity: swap.hbit_final_identity,
+            secret: swap.secret,
+            start_of_swap: swap.start_of_swap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maker::tests::taken_order;
+
+    #[test]
+    fn given_inserted_swap_then_it_is_loaded_with_its_steps() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let id = SwapId::random();
+        let swap = Swap::from(&taken_order(
+            Peer::new(0),
+            crate::bitcoin::Amount::from_sat(100_000),
+        ));
+
+        db.insert_swap(&id, &swap).unwrap();
+        db.record_step(&id, Step::HbitFunded).unwrap();
+
+        let swaps = db.all_swaps().unwrap();
+
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].0, id);
+        assert_eq!(swaps[0].1.steps, vec![Step::HbitFunded]);
+        assert!(db.has_step(&id, Step::HbitFunded).unwrap());
+        assert!(!db.has_step(&id, Step::Herc20Deployed).unwrap());
+    }
+
+    #[test]
+    fn given_swaps_recorded_when_reopening_database_then_they_are_still_there() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = SwapId::random();
+        let swap = Swap::from(&taken_order(
+            Peer::new(0),
+            crate::bitcoin::Amount::from_sat(100_000),
+        ));
+
+        {
+            let db = Database::open(dir.path()).unwrap();
+            db.insert_swap(&id, &swap).unwrap();
+            db.record_step(&id, Step::HbitFunded).unwrap();
+            db.record_step(&id, Step::Herc20Deployed).unwrap();
+        }
+
+        let db = Database::open(dir.path()).unwrap();
+        let swaps = db.all_swaps().unwrap();
+
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(
+            swaps[0].1.steps,
+            vec![Step::HbitFunded, Step::Herc20Deployed]
+        );
+    }
+
+    #[test]
+    fn given_removed_swap_then_it_is_not_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let id = SwapId::random();
+        let swap = Swap::from(&taken_order(
+            Peer::new(0),
+            crate::bitcoin::Amount::from_sat(100_000),
+        ));
+
+        db.insert_swap(&id, &swap).unwrap();
+        db.remove_swap(&id).unwrap();
+
+        assert!(db.all_swaps().unwrap().is_empty());
+    }
+
+    #[test]
+    fn given_recorded_refund_then_it_is_returned_with_its_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let id = SwapId::random();
+        let swap = Swap::from(&taken_order(
+            Peer::new(0),
+            crate::bitcoin::Amount::from_sat(100_000),
+        ));
+        let transaction = ::bitcoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![::bitcoin::TxIn::default()],
+            output: vec![::bitcoin::TxOut::default()],
+        };
+
+        db.insert_swap(&id, &swap).unwrap();
+        assert!(db.hbit_refund(&id).unwrap().is_none());
+
+        db.record_hbit_refund(
+            &id,
+            &hbit::Refunded {
+                transaction: transaction.clone(),
+            },
+        )
+        .unwrap();
+
+        let refund = db.hbit_refund(&id).unwrap().unwrap();
+        assert_eq!(refund.transaction, transaction);
+        assert!(db.has_step(&id, Step::HbitRefunded).unwrap());
+    }
+
+    #[test]
+    fn given_in_flight_transaction_when_step_is_recorded_then_it_is_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let id = SwapId::random();
+        let swap = Swap::from(&taken_order(
+            Peer::new(0),
+            crate::bitcoin::Amount::from_sat(100_000),
+        ));
+        let sent = Sent {
+            nonce: 7,
+            hashes: Vec::new(),
+        };
+
+        db.insert_swap(&id, &swap).unwrap();
+        db.record_in_flight(&id, Step::Herc20Deployed, InFlight::Ethereum(sent.clone()))
+            .unwrap();
+
+        assert_eq!(
+            db.in_flight(&id, Step::Herc20Deployed).unwrap(),
+            Some(InFlight::Ethereum(sent))
+        );
+        assert_eq!(db.in_flight(&id, Step::Herc20Funded).unwrap(), None);
+        assert_eq!(db.ethereum_nonces_in_flight().unwrap(), vec![7]);
+
+        db.record_step(&id, Step::Herc20Deployed).unwrap();
+
+        assert_eq!(db.in_flight(&id, Step::Herc20Deployed).unwrap(), None);
+        assert!(db.ethereum_nonces_in_flight().unwrap().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_can_access_the_database() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("database");
+
+        let _db = Database::open(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn swap_roundtrips_through_taken_order() {
+        let swap = Swap::from(&taken_order(
+            Peer::new(0),
+            crate::bitcoin::Amount::from_sat(100_000),
+        ));
+
+        let order = TakenOrder::from(swap.clone());
+
+        assert_eq!(Swap::from(&order), swap);
+    }
+}
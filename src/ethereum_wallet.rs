@@ -0,0 +1,629 @@
+mod gas;
+mod nonce;
+
+use crate::config::GasPrice;
+use crate::geth::{self, BlockTag, TransactionReceipt};
+use crate::redacted::Redacted;
+use crate::seed::Seed;
+use comit::{
+    actions::ethereum::{CallContract, DeployContract},
+    ethereum::{Address, ChainId, Hash, Transaction},
+    Timestamp,
+};
+pub use gas::refund_deadline;
+use nonce::{InUse, Nonces};
+use num::BigUint;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How often to check whether a broadcast transaction has been mined.
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a transaction can wait to be mined before it is replaced
+/// by one paying a higher gas price, about eight blocks.
+const REPLACEMENT_INTERVAL: Duration = Duration::from_secs(2 * 60);
+
+/// The first four bytes of the hash of `balanceOf(address)`.
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// Signs Ethereum transactions locally and broadcasts them through a
+/// web3 endpoint.
+///
+/// Clones share the nonces of the account so that each swap can use
+/// its own clone.
+#[derive(Clone, Debug)]
+pub struct Wallet {
+    private_key: Redacted<clarity::PrivateKey>,
+    account: Address,
+    geth_client: geth::Client,
+    gas_price: GasPrice,
+    nonces: Arc<Nonces>,
+}
+
+impl Wallet {
+    /// The Ethereum key is derived from the seed so that it differs
+    /// from the key of the Bitcoin wallet.
+    pub fn new(seed: Seed, url: Url, gas_price: GasPrice) -> anyhow::Result<Wallet> {
+        let secret_key = seed.derive(b"ETHEREUM").secret_key()?;
+        let private_key = clarity::PrivateKey::from_slice(&secret_key[..])
+            .map_err(|_| anyhow::anyhow!("failed to derive the Ethereum private key"))?;
+        let account = private_key
+            .to_public_key()
+            .map_err(|_| anyhow::anyhow!("failed to derive the Ethereum account"))?;
+        let account = Address::from_slice(account.as_bytes());
+
+        Ok(Wallet {
+            private_key: Redacted(private_key),
+            account,
+            geth_client: geth::Client::new(url),
+            gas_price,
+            nonces: Arc::new(Nonces::default()),
+        })
+    }
+
+    pub fn account(&self) -> Address {
+        self.account
+    }
+
+    /// A transaction using `nonce` may have been sent before a
+    /// restart, it must not be handed out to another transaction
+    /// before it is sent again, unless the node dropped it.
+    pub fn reserve_nonce(&self, nonce: u64) {
+        self.nonces.reserve(nonce)
+    }
+
+    /// Deploy a contract, replacing the transaction until it is mined
+    /// before `deadline`, see `send_until_mined`.
+    pub async fn deploy_contract(
+        &self,
+        action: DeployContract,
+        deadline: Timestamp,
+        journal: &dyn Journal,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let value = parse_wei(&action.amount.to_wei_dec())?;
+        // Clarity encodes the zero address as an empty `to` field,
+        // which is what makes the transaction a contract creation.
+        self.send_until_mined(
+            UnsignedTransaction {
+                to: clarity::Address::default(),
+                value,
+                data: action.data,
+                gas_limit: action.gas_limit,
+                chain_id: action.chain_id,
+            },
+            deadline,
+            journal,
+        )
+        .await
+    }
+
+    /// Call a contract, replacing the transaction until it is mined
+    /// before `deadline`, see `send_until_mined`.
+    pub async fn call_contract(
+        &self,
+        action: CallContract,
+        deadline: Timestamp,
+        journal: &dyn Journal,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let to = clarity::Address::from_slice(action.to.as_bytes())
+            .map_err(|_| anyhow::anyhow!("invalid contract address {}", action.to))?;
+
+        self.send_until_mined(
+            UnsignedTransaction {
+                to,
+                value: clarity::Uint256::from(0u64),
+                data: action.data.unwrap_or_default(),
+                gas_limit: action.gas_limit,
+                chain_id: action.chain_id,
+            },
+            deadline,
+            journal,
+        )
+        .await
+    }
+
+    /// The ether of the account, in wei, which pays for the gas.
+    pub async fn ether_balance(&self) -> anyhow::Result<BigUint> {
+        self.geth_client
+            .get_balance(self.account, BlockTag::Latest)
+            .await
+    }
+
+    /// The balance of the account in an ERC20 token, in its smallest
+    /// unit, e.g. attodai.
+    pub async fn erc20_balance(&self, token_contract: Address) -> anyhow::Result<BigUint> {
+        let mut data = BALANCE_OF_SELECTOR.to_vec();
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(self.account.as_bytes());
+
+        let balance = self
+            .geth_client
+            .call(
+                &geth::CallRequest {
+                    to: Some(token_contract),
+                    data,
+                    ..geth::CallRequest::default()
+                },
+                BlockTag::Latest,
+            )
+            .await?;
+
+        Ok(BigUint::from_bytes_be(&balance))
+    }
+
+    /// The chain id of the node the wallet sends transactions to.
+    pub async fn chain_id(&self) -> anyhow::Result<ChainId> {
+        self.geth_client.chain_id().await
+    }
+
+    pub async fn get_transaction(&self, hash: Hash) -> anyhow::Result<Transaction> {
+        self.geth_client.get_transaction_by_hash(hash).await
+    }
+
+    /// Send a transaction and wait until it, or one of its
+    /// replacements, is mined.
+    ///
+    /// Every time the transaction is not mined within
+    /// `REPLACEMENT_INTERVAL`, it is sent again with the same nonce
+    /// and a higher gas price. Close to `deadline`, in the time of the
+    /// latest block, the maximum gas price is paid.
+    ///
+    /// Resending the transaction also recovers from the node dropping
+    /// it, which would otherwise hold back the transactions using the
+    /// nonces above.
+    ///
+    /// The nonce is recorded in `journal` before the transaction is
+    /// first sent, and every transaction sent after it. If the journal
+    /// already holds transactions, e.g. sent before a restart, they
+    /// are waited for and replaced instead of sending a new one.
+    ///
+    /// If sending fails half way, the nonce is abandoned: it is kept
+    /// for the transaction to be sent again, unless the node has no
+    /// transaction using it by the time another nonce is allocated.
+    async fn send_until_mined(
+        &self,
+        transaction: UnsignedTransaction,
+        deadline: Timestamp,
+        journal: &dyn Journal,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let mut gas_price = gas::initial(self.geth_client.gas_price().await?, &self.gas_price);
+        let resumed = match journal.sent()? {
+            Some(sent) if !self.nonces.claim(sent.nonce) => {
+                // Only one of the transactions using the nonce can be
+                // mined, replacing the other one would take it over
+                tracing::warn!(
+                    "nonce {} of Ethereum transactions {:?} was handed out again, waiting for it to be mined",
+                    sent.nonce,
+                    sent.hashes
+                );
+                if let Some(receipt) = self.wait_for_nonce(&sent).await? {
+                    return successful(receipt);
+                }
+                journal.forget()?;
+                None
+            }
+            resumed => resumed,
+        };
+        let is_resumed = resumed.is_some();
+        let (mut sent, nonce) = match resumed {
+            Some(sent) => {
+                let nonce = InUse::new(&self.nonces, sent.nonce);
+                (sent, nonce)
+            }
+            None => {
+                let nonce = InUse::new(&self.nonces, self.allocate_nonce().await?);
+                let sent = Sent {
+                    nonce: nonce.get(),
+                    hashes: Vec::new(),
+                };
+                if let Err(e) = journal.record(&sent) {
+                    nonce.release();
+                    return Err(e);
+                }
+                (sent, nonce)
+            }
+        };
+
+        if sent.hashes.is_empty() {
+            match self
+                .sign_and_send(&transaction, nonce.get(), gas_price)
+                .await
+            {
+                Ok(hash) => {
+                    sent.hashes.push(hash);
+                    journal.record(&sent)?;
+                }
+                // A resumed transaction may have been sent before the
+                // restart, its nonce is kept
+                Err(e) if !is_resumed => {
+                    journal.forget()?;
+                    nonce.release();
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        loop {
+            if let Some(receipt) = self.wait_for_receipt(&sent.hashes).await? {
+                nonce.mined();
+                return successful(receipt);
+            }
+
+            // A transaction sent right before a restart may not have
+            // made it to the journal
+            let mined = self
+                .geth_client
+                .get_transaction_count(self.account, BlockTag::Latest)
+                .await?;
+            if mined > nonce.get() {
+                let receipts = self
+                    .geth_client
+                    .get_transaction_receipts(&sent.hashes)
+                    .await?;
+                match receipts.into_iter().flatten().next() {
+                    Some(receipt) => {
+                        nonce.mined();
+                        return successful(receipt);
+                    }
+                    None => anyhow::bail!(
+                        "Ethereum transaction with nonce {} was mined but is none of {:?}",
+                        nonce.get(),
+                        sent.hashes
+                    ),
+                }
+            }
+
+            let now = self.geth_client.latest_block_timestamp().await?;
+            let bumped = gas::bumped(
+                gas_price,
+                u32::from(now),
+                u32::from(deadline),
+                &self.gas_price,
+            );
+
+            // Once the gas price is at its maximum, the same transaction
+            // is sent again in case the node dropped it. The transaction
+            // may also have been mined in the meantime, in which case the
+            // node rejects it.
+            match self.sign_and_send(&transaction, nonce.get(), bumped).await {
+                Ok(hash) if sent.hashes.contains(&hash) => {
+                    tracing::debug!("rebroadcast Ethereum transaction {:?}", hash)
+                }
+                Ok(hash) => {
+                    tracing::info!(
+                        "replaced Ethereum transaction {:?} with {:?}, paying {} wei per gas",
+                        sent.hashes.last(),
+                        hash,
+                        bumped
+                    );
+                    sent.hashes.push(hash);
+                    journal.record(&sent)?;
+                    gas_price = bumped;
+                }
+                // The next replacement must outbid the last accepted one
+                Err(e) => tracing::warn!(
+                    "could not resend Ethereum transaction {:?}: {:#}",
+                    sent.hashes.last(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Wait until a transaction using the nonce of `sent` is mined,
+    /// returning its receipt if it is one of `sent`.
+    async fn wait_for_nonce(&self, sent: &Sent) -> anyhow::Result<Option<TransactionReceipt>> {
+        loop {
+            let mined = self
+                .geth_client
+                .get_transaction_count(self.account, BlockTag::Latest)
+                .await?;
+            if mined > sent.nonce {
+                let receipts = self
+                    .geth_client
+                    .get_transaction_receipts(&sent.hashes)
+                    .await?;
+
+                return Ok(receipts.into_iter().flatten().next());
+            }
+
+            tokio::time::delay_for(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn allocate_nonce(&self) -> anyhow::Result<u64> {
+        let mined = self
+            .geth_client
+            .get_transaction_count(self.account, BlockTag::Latest)
+            .await?;
+        let pending = self
+            .geth_client
+            .get_transaction_count(self.account, BlockTag::Pending)
+            .await?;
+
+        Ok(self.nonces.allocate(mined, pending))
+    }
+
+    async fn sign_and_send(
+        &self,
+        transaction: &UnsignedTransaction,
+        nonce: u64,
+        gas_price: u64,
+    ) -> anyhow::Result<Hash> {
+        let unsigned = clarity::Transaction {
+            nonce: nonce.into(),
+            gas_price: gas_price.into(),
+            gas_limit: transaction.gas_limit.into(),
+            to: transaction.to,
+            value: transaction.value.clone(),
+            data: transaction.data.clone(),
+            signature: None,
+        };
+        // Including the chain id in the signature (EIP-155) prevents
+        // the transaction from being replayed on another chain.
+        let chain_id = u64::from(u32::from(transaction.chain_id));
+        let signed = unsigned.sign(&self.private_key.0, Some(chain_id));
+        let bytes = signed
+            .to_bytes()
+            .map_err(|_| anyhow::anyhow!("failed to encode signed transaction"))?;
+
+        let hash = self.geth_client.send_raw_transaction(&bytes).await?;
+        tracing::debug!("sent Ethereum transaction {:?}", hash);
+
+        Ok(hash)
+    }
+
+    /// Wait up to `REPLACEMENT_INTERVAL` for one of the `sent`
+    /// transactions to be mined.
+    async fn wait_for_receipt(&self, sent: &[Hash]) -> anyhow::Result<Option<TransactionReceipt>> {
+        let started = Instant::now();
+
+        while started.elapsed() < REPLACEMENT_INTERVAL {
+            let receipts = self.geth_client.get_transaction_receipts(sent).await?;
+            if let Some(receipt) = receipts.into_iter().flatten().next() {
+                return Ok(Some(receipt));
+            }
+
+            tokio::time::delay_for(RECEIPT_POLL_INTERVAL).await;
+        }
+
+        Ok(None)
+    }
+}
+
+/// The transactions sent for an action, which all use the same nonce
+/// so that at most one of them is mined.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sent {
+    pub nonce: u64,
+    pub hashes: Vec<Hash>,
+}
+
+/// Keeps the transactions sent for an action across restarts, so that
+/// the action is not sent again with another nonce, e.g. funding an
+/// HTLC twice.
+pub trait Journal: Send + Sync {
+    /// The transactions recorded for the action, if any.
+    fn sent(&self) -> anyhow::Result<Option<Sent>>;
+
+    fn record(&self, sent: &Sent) -> anyhow::Result<()>;
+
+    /// No transaction was sent for the action, its nonce is handed
+    /// out again.
+    fn forget(&self) -> anyhow::Result<()>;
+}
+
+/// Everything about a transaction but its nonce and gas price, which
+/// change when it is replaced.
+#[derive(Clone, Debug)]
+struct UnsignedTransaction {
+    to: clarity::Address,
+    value: clarity::Uint256,
+    data: Vec<u8>,
+    gas_limit: u64,
+    chain_id: ChainId,
+}
+
+/// A mined transaction uses its nonce even if it was reverted.
+fn successful(receipt: TransactionReceipt) -> anyhow::Result<TransactionReceipt> {
+    if !receipt.is_successful()? {
+        anyhow::bail!(
+            "Ethereum transaction {:?} was reverted",
+            receipt.transaction_hash
+        )
+    }
+
+    Ok(receipt)
+}
+
+fn parse_wei(wei: &str) -> anyhow::Result<clarity::Uint256> {
+    wei.parse()
+        .map_err(|_| anyhow::anyhow!("invalid amount of wei {}", wei))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ethereum_account_is_deterministic() {
+        let seed = Seed::new();
+        let url = Url::parse("http://localhost:8545").unwrap();
+
+        let wallet = Wallet::new(seed.clone(), url.clone(), GasPrice::DEFAULT).unwrap();
+        let same_wallet = Wallet::new(seed, url.clone(), GasPrice::DEFAULT).unwrap();
+        let other_wallet = Wallet::new(Seed::new(), url, GasPrice::DEFAULT).unwrap();
+
+        assert_eq!(wallet.account(), same_wallet.account());
+        assert_ne!(wallet.account(), other_wallet.account());
+    }
+
+    #[test]
+    fn signature_is_protected_by_chain_id() {
+        let seed = Seed::new();
+        let wallet = Wallet::new(
+            seed,
+            Url::parse("http://localhost:8545").unwrap(),
+            GasPrice::DEFAULT,
+        )
+        .unwrap();
+        let transaction = clarity::Transaction {
+            nonce: 0u64.into(),
+            gas_price: 1u64.into(),
+            gas_limit: 21_000u64.into(),
+            to: clarity::Address::default(),
+            value: 0u64.into(),
+            data: Vec::new(),
+            signature: None,
+        };
+
+        let signed = transaction.sign(&wallet.private_key.0, Some(1337));
+
+        // EIP-155: v = chain_id * 2 + 35 or chain_id * 2 + 36
+        let v = signed.signature.unwrap().v;
+        assert!(v == 2709u64.into() || v == 2710u64.into());
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod docker_tests {
+    use super::*;
+    use crate::test_harness::EthereumBlockchain;
+    use comit::{
+        asset::{
+            self,
+            ethereum::{Erc20Quantity, FromWei},
+        },
+        herc20, identity, SecretHash, Timestamp,
+    };
+    use std::{str::FromStr, sync::Mutex};
+    use testcontainers::clients;
+
+    #[derive(Debug, Default)]
+    struct MemoryJournal(Mutex<Option<Sent>>);
+
+    impl Journal for MemoryJournal {
+        fn sent(&self) -> anyhow::Result<Option<Sent>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn record(&self, sent: &Sent) -> anyhow::Result<()> {
+            *self.0.lock().unwrap() = Some(sent.clone());
+            Ok(())
+        }
+
+        fn forget(&self) -> anyhow::Result<()> {
+            *self.0.lock().unwrap() = None;
+            Ok(())
+        }
+    }
+
+    fn deploy_params(wallet: &Wallet) -> herc20::Params {
+        let token_contract = Address::from_str("6b175474e89094c44da98b954eedeac495271d0f").unwrap();
+
+        herc20::Params {
+            asset: asset::Erc20::new(token_contract, Erc20Quantity::from_wei(1_000u32)),
+            redeem_identity: identity::Ethereum::from(wallet.account()),
+            refund_identity: identity::Ethereum::from(wallet.account()),
+            expiry: Timestamp::from(0),
+            chain_id: ChainId::regtest(),
+            secret_hash: SecretHash::new([1u8; 32].into()),
+        }
+    }
+
+    #[tokio::test]
+    async fn minted_ether_is_in_the_balance() {
+        let tc_client = clients::Cli::default();
+        let blockchain = EthereumBlockchain::new(&tc_client).unwrap();
+
+        let wallet =
+            Wallet::new(Seed::new(), blockchain.node_url.clone(), GasPrice::DEFAULT).unwrap();
+        blockchain
+            .mint_ether(wallet.account(), 1_000_000_000_000_000_000)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            wallet.ether_balance().await.unwrap(),
+            BigUint::from(1_000_000_000_000_000_000u64)
+        );
+    }
+
+    #[tokio::test]
+    async fn dev_chain_has_regtest_chain_id() {
+        let tc_client = clients::Cli::default();
+        let blockchain = EthereumBlockchain::new(&tc_client).unwrap();
+
+        let wallet =
+            Wallet::new(Seed::new(), blockchain.node_url.clone(), GasPrice::DEFAULT).unwrap();
+
+        assert_eq!(wallet.chain_id().await.unwrap(), ChainId::regtest());
+    }
+
+    #[tokio::test]
+    async fn deploy_herc20_htlc_on_dev_chain() {
+        let tc_client = clients::Cli::default();
+        let blockchain = EthereumBlockchain::new(&tc_client).unwrap();
+
+        let wallet =
+            Wallet::new(Seed::new(), blockchain.node_url.clone(), GasPrice::DEFAULT).unwrap();
+        blockchain
+            .mint_ether(wallet.account(), 1_000_000_000_000_000_000)
+            .await
+            .unwrap();
+
+        let params = deploy_params(&wallet);
+
+        let receipt = wallet
+            .deploy_contract(
+                params.build_deploy_action(),
+                params.expiry,
+                &MemoryJournal::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(receipt.contract_address.is_some());
+    }
+
+    #[tokio::test]
+    async fn resumed_deploy_does_not_send_another_transaction() {
+        let tc_client = clients::Cli::default();
+        let blockchain = EthereumBlockchain::new(&tc_client).unwrap();
+
+        let seed = Seed::new();
+        let wallet =
+            Wallet::new(seed.clone(), blockchain.node_url.clone(), GasPrice::DEFAULT).unwrap();
+        blockchain
+            .mint_ether(wallet.account(), 1_000_000_000_000_000_000)
+            .await
+            .unwrap();
+        let params = deploy_params(&wallet);
+        let journal = MemoryJournal::default();
+
+        let receipt = wallet
+            .deploy_contract(params.build_deploy_action(), params.expiry, &journal)
+            .await
+            .unwrap();
+
+        // A wallet started after a restart does not know the nonce
+        let restarted = Wallet::new(seed, blockchain.node_url.clone(), GasPrice::DEFAULT).unwrap();
+        let resumed = restarted
+            .deploy_contract(params.build_deploy_action(), params.expiry, &journal)
+            .await
+            .unwrap();
+
+        assert_eq!(resumed.transaction_hash, receipt.transaction_hash);
+        let count = wallet
+            .geth_client
+            .get_transaction_count(wallet.account, BlockTag::Latest)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::config::GasPrice;
+use comit::Timestamp;
+use std::cmp::min;
+
+/// How long before its deadline a transaction is resent with the
+/// maximum gas price, in seconds.
+const URGENCY_WINDOW: u32 = 10 * 60;
+
+/// How long a refund has to be mined, counting from when it is first
+/// sent, in seconds. The HTLC has expired already, only from then on
+/// does the gas price go up to the maximum.
+const REFUND_WINDOW: u32 = 60 * 60;
+
+/// The time by which a refund first sent at `sent` must be mined, on
+/// the same clock.
+pub fn refund_deadline(sent: Timestamp) -> Timestamp {
+    Timestamp::from(u32::from(sent).saturating_add(REFUND_WINDOW))
+}
+
+/// The gas price of a new transaction, in wei.
+pub fn initial(node_gas_price: u64, config: &GasPrice) -> u64 {
+    min(config.fixed.unwrap_or(node_gas_price), config.max)
+}
+
+/// The gas price to resend a transaction with, using the same nonce,
+/// when it was not mined in time at `gas_price`.
+///
+/// The price goes up by a quarter at every replacement and to the
+/// maximum once `now`, the time of the latest block, is close to
+/// `deadline`.
+///
+/// Nodes only accept a replacement paying at least 10% more, geth's
+/// default price bump. If the maximum is not that much higher, the
+/// price stays the same and the transaction is only sent again.
+pub fn bumped(gas_price: u64, now: u32, deadline: u32, config: &GasPrice) -> u64 {
+    let raised = if now.saturating_add(URGENCY_WINDOW) >= deadline {
+        config.max
+    } else {
+        // Rounding up keeps small prices above the price bump
+        min(gas_price.saturating_add((gas_price + 3) / 4), config.max)
+    };
+
+    if raised < min_replacement(gas_price) {
+        return gas_price;
+    }
+
+    raised
+}
+
+/// The lowest gas price a node accepts to replace a transaction paying
+/// `gas_price` with.
+fn min_replacement(gas_price: u64) -> u64 {
+    gas_price.saturating_add((gas_price + 9) / 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(fixed: Option<u64>, max: u64) -> GasPrice {
+        GasPrice { fixed, max }
+    }
+
+    #[test]
+    fn initial_gas_price_is_capped() {
+        assert_eq!(initial(20, &config(None, 100)), 20);
+        assert_eq!(initial(500, &config(None, 100)), 100);
+        assert_eq!(initial(20, &config(Some(40), 100)), 40);
+    }
+
+    #[test]
+    fn replacement_pays_at_least_ten_percent_more() {
+        let deadline = 100_000;
+
+        assert_eq!(bumped(100, 0, deadline, &config(None, 1_000)), 125);
+        assert_eq!(bumped(1, 0, deadline, &config(None, 1_000)), 2);
+        assert_eq!(bumped(900, 0, deadline, &config(None, 1_000)), 1_000);
+    }
+
+    #[test]
+    fn replacement_under_the_price_bump_is_not_sent() {
+        let deadline = 100_000;
+
+        assert_eq!(bumped(950, 0, deadline, &config(None, 1_000)), 950);
+        assert_eq!(
+            bumped(
+                950,
+                deadline - URGENCY_WINDOW,
+                deadline,
+                &config(None, 1_000)
+            ),
+            950
+        );
+        assert_eq!(bumped(1_000, 0, deadline, &config(None, 1_000)), 1_000);
+    }
+
+    #[test]
+    fn refund_is_not_urgent_when_first_sent() {
+        let sent = 100_000;
+        let deadline = u32::from(refund_deadline(Timestamp::from(sent)));
+
+        assert_eq!(bumped(100, sent, deadline, &config(None, 1_000)), 125);
+        assert_eq!(
+            bumped(
+                100,
+                deadline - URGENCY_WINDOW,
+                deadline,
+                &config(None, 1_000)
+            ),
+            1_000
+        );
+    }
+
+    #[test]
+    fn replacement_pays_the_maximum_close_to_the_deadline() {
+        let deadline = 100_000;
+
+        assert_eq!(
+            bumped(
+                100,
+                deadline - URGENCY_WINDOW,
+                deadline,
+                &config(None, 1_000)
+            ),
+            1_000
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::BTreeSet,
+    sync::{Mutex, MutexGuard},
+};
+
+/// Hands out the nonces of an account to transactions sent
+/// concurrently, e.g. by several swaps.
+///
+/// The node only knows about the transactions it has seen, asking it
+/// for the next nonce is racy as soon as two transactions are built
+/// at the same time.
+#[derive(Debug, Default)]
+pub struct Nonces(Mutex<State>);
+
+#[derive(Debug, Default)]
+struct State {
+    /// The nonce to hand out once all released ones are reused
+    next: u64,
+    /// Nonces handed out whose transaction is not known to be mined
+    pending: BTreeSet<u64>,
+    /// Nonces handed out which no transaction ended up using
+    released: BTreeSet<u64>,
+    /// Nonces of transactions which may have been sent but nobody
+    /// waits for, until they are claimed again or the node turns out
+    /// to have dropped them
+    abandoned: BTreeSet<u64>,
+}
+
+impl Nonces {
+    /// Hand out a nonce no other transaction uses.
+    ///
+    /// `mined` and `pending` are the transaction counts of the account
+    /// in the latest block and including the node's mempool, as
+    /// returned by `eth_getTransactionCount`. They account for the
+    /// transactions that were mined in the meantime and the ones sent
+    /// from outside of nectar.
+    ///
+    /// Abandoned nonces the node knows no transaction for, from
+    /// `pending` on, were dropped and leave a gap, they are handed out
+    /// again.
+    pub fn allocate(&self, mined: u64, pending: u64) -> u64 {
+        let mut state = self.state();
+
+        state.pending = state.pending.split_off(&mined);
+        state.released = state.released.split_off(&mined);
+        state.abandoned = state.abandoned.split_off(&mined);
+        let mut dropped = state.abandoned.split_off(&pending);
+        state.released.append(&mut dropped);
+
+        // Released nonces come first, a gap holds back the
+        // transactions of all the nonces above it
+        let nonce = match state.released.iter().next().copied() {
+            Some(nonce) => {
+                state.released.remove(&nonce);
+                nonce
+            }
+            None => {
+                let nonce = state.next.max(pending);
+                state.next = nonce + 1;
+                nonce
+            }
+        };
+        state.pending.insert(nonce);
+
+        nonce
+    }
+
+    /// The transaction using `nonce` was mined.
+    pub fn mined(&self, nonce: u64) {
+        let mut state = self.state();
+
+        state.pending.remove(&nonce);
+    }
+
+    /// A transaction using `nonce` may have been sent before a
+    /// restart, it is not handed out to another one until it is
+    /// claimed or the node turns out to have dropped it.
+    pub fn reserve(&self, nonce: u64) {
+        let mut state = self.state();
+
+        state.next = state.next.max(nonce + 1);
+        if !state.pending.contains(&nonce) {
+            state.released.remove(&nonce);
+            state.abandoned.insert(nonce);
+        }
+    }
+
+    /// Take back `nonce` to send a transaction which may have been
+    /// sent before. Returns `false` if it was handed out to another
+    /// transaction in the meantime.
+    pub fn claim(&self, nonce: u64) -> bool {
+        let mut state = self.state();
+
+        if state.pending.contains(&nonce) {
+            return false;
+        }
+
+        state.abandoned.remove(&nonce);
+        state.released.remove(&nonce);
+        state.pending.insert(nonce);
+        state.next = state.next.max(nonce + 1);
+
+        true
+    }
+
+    /// The transaction using `nonce` may have been sent but is no
+    /// longer waited for, e.g. because sending it failed half way.
+    pub fn abandon(&self, nonce: u64) {
+        let mut state = self.state();
+
+        if state.pending.remove(&nonce) {
+            state.abandoned.insert(nonce);
+        }
+    }
+
+    /// No transaction using `nonce` reached the node, it is handed out
+    /// again.
+    pub fn release(&self, nonce: u64) {
+        let mut state = self.state();
+
+        if state.pending.remove(&nonce) {
+            state.released.insert(nonce);
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().expect("nonce state poisoned")
+    }
+}
+
+/// A nonce used by a transaction being sent.
+///
+/// Unless the transaction is known to be mined or not to have been
+/// sent, the nonce is abandoned when this is dropped, e.g. on an error
+/// while waiting for the transaction.
+#[derive(Debug)]
+pub struct InUse<'a> {
+    nonces: &'a Nonces,
+    nonce: u64,
+    settled: bool,
+}
+
+impl<'a> InUse<'a> {
+    /// `nonce` must have been allocated or claimed from `nonces`.
+    pub fn new(nonces: &'a Nonces, nonce: u64) -> Self {
+        InUse {
+            nonces,
+            nonce,
+            settled: false,
+        }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn mined(mut self) {
+        self.nonces.mined(self.nonce);
+        self.settled = true;
+    }
+
+    pub fn release(mut self) {
+        self.nonces.release(self.nonce);
+        self.settled = true;
+    }
+}
+
+impl Drop for InUse<'_> {
+    fn drop(&mut self) {
+        if !self.settled {
+            self.nonces.abandon(self.nonce);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn concurrent_transactions_get_distinct_nonces() {
+        let nonces = Arc::new(Nonces::default());
+
+        let handles = (0..10)
+            .map(|_| {
+                let nonces = Arc::clone(&nonces);
+                thread::spawn(move || nonces.allocate(5, 5))
+            })
+            .collect::<Vec<_>>();
+        let mut allocated = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>();
+        allocated.sort();
+
+        assert_eq!(allocated, (5..15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn released_nonce_is_reused_first() {
+        let nonces = Nonces::default();
+        let first = nonces.allocate(0, 0);
+        let _second = nonces.allocate(0, 0);
+
+        nonces.release(first);
+
+        assert_eq!(nonces.allocate(0, 0), first);
+        assert_eq!(nonces.allocate(0, 0), 2);
+    }
+
+    #[test]
+    fn resync_with_transactions_sent_from_outside() {
+        let nonces = Nonces::default();
+        assert_eq!(nonces.allocate(0, 0), 0);
+
+        // Three transactions were sent from the same account by another
+        // wallet in the meantime
+        assert_eq!(nonces.allocate(0, 4), 4);
+    }
+
+    #[test]
+    fn reserved_nonce_is_not_handed_out() {
+        let nonces = Nonces::default();
+
+        nonces.reserve(3);
+
+        assert_eq!(nonces.allocate(0, 4), 4);
+        assert!(nonces.claim(3));
+    }
+
+    #[test]
+    fn reserved_nonce_is_claimed_once() {
+        let nonces = Nonces::default();
+
+        nonces.reserve(3);
+
+        assert!(nonces.claim(3));
+        assert!(!nonces.claim(3));
+    }
+
+    #[test]
+    fn nonce_abandoned_on_error_is_handed_out_again_once_dropped() {
+        let nonces = Nonces::default();
+        let first = InUse::new(&nonces, nonces.allocate(0, 0));
+        let nonce = first.get();
+
+        // The node still knows the transaction
+        drop(first);
+        assert_eq!(nonces.allocate(0, 1), 1);
+
+        // The node dropped it, the gap holds back the second transaction
+        assert_eq!(nonces.allocate(0, 0), nonce);
+        assert!(!nonces.claim(nonce));
+    }
+
+    #[test]
+    fn abandoned_nonce_is_claimed_back_by_its_transaction() {
+        let nonces = Nonces::default();
+        let nonce = nonces.allocate(0, 0);
+        drop(InUse::new(&nonces, nonce));
+
+        assert!(nonces.claim(nonce));
+        assert_eq!(nonces.allocate(0, 0), 1);
+    }
+
+    #[test]
+    fn settled_nonce_is_not_abandoned() {
+        let nonces = Nonces::default();
+        InUse::new(&nonces, nonces.allocate(0, 0)).release();
+        InUse::new(&nonces, nonces.allocate(0, 0)).mined();
+
+        assert_eq!(nonces.allocate(0, 0), 1);
+    }
+
+    #[test]
+    fn released_nonce_taken_by_a_mined_transaction_is_forgotten() {
+        let nonces = Nonces::default();
+        let first = nonces.allocate(0, 0);
+        let second = nonces.allocate(0, 0);
+        nonces.release(first);
+        nonces.mined(second);
+
+        // Another transaction used the released nonce
+        assert_eq!(nonces.allocate(2, 2), 2);
+    }
+}
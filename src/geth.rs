@@ -0,0 +1,447 @@
+use crate::jsonrpc;
+use comit::{
+    ethereum::{Address, ChainId, Hash, Transaction},
+    Timestamp,
+};
+use num::BigUint;
+use serde::{Deserialize, Serialize, Serializer};
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    rpc_client: jsonrpc::Client,
+}
+
+impl Client {
+    pub fn new(url: reqwest::Url) -> Self {
+        Client {
+            rpc_client: jsonrpc::Client::new(url),
+        }
+    }
+
+    /// The number of transactions sent from `account` as of `block`.
+    /// Including the ones still in the mempool, it is the nonce of the
+    /// next transaction.
+    pub async fn get_transaction_count(
+        &self,
+        account: Address,
+        block: BlockTag,
+    ) -> anyhow::Result<u64> {
+        let count: String = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_getTransactionCount",
+                vec![jsonrpc::serialize(account)?, jsonrpc::serialize(block)?],
+            ))
+            .await?;
+
+        parse_quantity(&count)
+    }
+
+    /// The balance of `account` as of `block`, in wei.
+    pub async fn get_balance(&self, account: Address, block: BlockTag) -> anyhow::Result<BigUint> {
+        let balance: String = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_getBalance",
+                vec![jsonrpc::serialize(account)?, jsonrpc::serialize(block)?],
+            ))
+            .await?;
+
+        parse_big_quantity(&balance)
+    }
+
+    /// Execute `call` against the state as of `block` without sending
+    /// a transaction, e.g. to read from a contract. Returns the data
+    /// returned by the call.
+    pub async fn call(&self, call: &CallRequest, block: BlockTag) -> anyhow::Result<Vec<u8>> {
+        let data: String = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_call",
+                vec![jsonrpc::serialize(call)?, jsonrpc::serialize(block)?],
+            ))
+            .await?;
+
+        parse_data(&data)
+    }
+
+    /// The gas `call` would use if it were sent as a transaction. The
+    /// node returns an error if the transaction would be reverted.
+    pub async fn estimate_gas(&self, call: &CallRequest) -> anyhow::Result<u64> {
+        let gas: String = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_estimateGas",
+                vec![jsonrpc::serialize(call)?],
+            ))
+            .await?;
+
+        parse_quantity(&gas)
+    }
+
+    /// The chain id transactions are signed for (EIP-155).
+    pub async fn chain_id(&self) -> anyhow::Result<ChainId> {
+        let chain_id: String = self
+            .rpc_client
+            .send::<Vec<()>, _>(jsonrpc::Request::v2("eth_chainId", vec![]))
+            .await?;
+        let chain_id = u32::try_from(parse_quantity(&chain_id)?)?;
+
+        Ok(ChainId::from(chain_id))
+    }
+
+    /// The gas price in wei.
+    pub async fn gas_price(&self) -> anyhow::Result<u64> {
+        let gas_price: String = self
+            .rpc_client
+            .send::<Vec<()>, _>(jsonrpc::Request::v2("eth_gasPrice", vec![]))
+            .await?;
+
+        parse_quantity(&gas_price)
+    }
+
+    /// The timestamp of the latest block, the time expiries are
+    /// checked against.
+    pub async fn latest_block_timestamp(&self) -> anyhow::Result<Timestamp> {
+        let block: BlockHeader = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_getBlockByNumber",
+                vec![
+                    jsonrpc::serialize(BlockTag::Latest)?,
+                    jsonrpc::serialize(false)?,
+                ],
+            ))
+            .await?;
+
+        block.timestamp()
+    }
+
+    pub async fn send_raw_transaction(&self, transaction: &[u8]) -> anyhow::Result<Hash> {
+        let hash = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_sendRawTransaction",
+                vec![jsonrpc::serialize(format!(
+                    "0x{}",
+                    hex::encode(transaction)
+                ))?],
+            ))
+            .await?;
+
+        Ok(hash)
+    }
+
+    pub async fn get_transaction_by_hash(&self, hash: Hash) -> anyhow::Result<Transaction> {
+        let transaction: Option<Transaction> = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_getTransactionByHash",
+                vec![jsonrpc::serialize(hash)?],
+            ))
+            .await?;
+
+        transaction.ok_or_else(|| anyhow::anyhow!("transaction {:?} not found", hash))
+    }
+
+    /// Returns `None` as long as the transaction is not mined.
+    pub async fn get_transaction_receipt(
+        &self,
+        hash: Hash,
+    ) -> anyhow::Result<Option<TransactionReceipt>> {
+        let receipt = self
+            .rpc_client
+            .send(jsonrpc::Request::v2(
+                "eth_getTransactionReceipt",
+                vec![jsonrpc::serialize(hash)?],
+            ))
+            .await?;
+
+        Ok(receipt)
+    }
+
+    /// The receipts of all the transactions in a single round-trip, in
+    /// the same order.
+    pub async fn get_transaction_receipts(
+        &self,
+        hashes: &[Hash],
+    ) -> anyhow::Result<Vec<Option<TransactionReceipt>>> {
+        let requests = hashes
+            .iter()
+            .map(|hash| -> anyhow::Result<_> {
+                Ok(jsonrpc::Request::v2(
+                    "eth_getTransactionReceipt",
+                    vec![jsonrpc::serialize(hash)?],
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        self.rpc_client.send_batch(requests).await
+    }
+
+    #[cfg(test)]
+    pub async fn accounts(&self) -> anyhow::Result<Vec<Address>> {
+        let accounts = self
+            .rpc_client
+            .send::<Vec<()>, _>(jsonrpc::Request::v2("eth_accounts", vec![]))
+            .await?;
+
+        Ok(accounts)
+    }
+
+    /// Send a transaction from an account unlocked in the node, such
+    /// as the developer account of `geth --dev`. The node estimates
+    /// its gas.
+    #[cfg(test)]
+    pub async fn send_transaction(&self, transaction: &CallRequest) -> anyhow::Result<Hash> {
+        let hash = self
+            .rpc_client
+            .send(
+                jsonrpc::Request::v2(
+                    "eth_sendTransaction",
+                    vec![jsonrpc::serialize(transaction)?],
+                )
+                .not_idempotent(),
+            )
+            .await?;
+
+        Ok(hash)
+    }
+}
+
+/// The state of the chain a call is made against.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockTag {
+    Latest,
+    /// The latest block and the transactions in the node's mempool
+    Pending,
+}
+
+/// A transaction which is executed by the node but not broadcast, see
+/// `eth_call` and `eth_estimateGas`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CallRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Address>,
+    /// `None` to create a contract
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Address>,
+    /// In wei
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_big_quantity"
+    )]
+    pub value: Option<BigUint>,
+    #[serde(serialize_with = "serialize_data")]
+    pub data: Vec<u8>,
+}
+
+/// The fields of a block nectar uses, as returned by
+/// `eth_getBlockByNumber` without transactions.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct BlockHeader {
+    timestamp: String,
+}
+
+impl BlockHeader {
+    fn timestamp(&self) -> anyhow::Result<Timestamp> {
+        let timestamp = u32::try_from(parse_quantity(&self.timestamp)?)?;
+
+        Ok(Timestamp::from(timestamp))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TransactionReceipt {
+    #[serde(rename = "transactionHash")]
+    pub transaction_hash: Hash,
+    #[serde(rename = "blockNumber")]
+    block_number: String,
+    #[serde(rename = "contractAddress")]
+    pub contract_address: Option<Address>,
+    #[serde(rename = "gasUsed")]
+    gas_used: String,
+    status: String,
+}
+
+impl TransactionReceipt {
+    pub fn block_number(&self) -> anyhow::Result<u64> {
+        parse_quantity(&self.block_number)
+    }
+
+    pub fn gas_used(&self) -> anyhow::Result<u64> {
+        parse_quantity(&self.gas_used)
+    }
+
+    /// Whether the transaction was executed without being reverted.
+    pub fn is_successful(&self) -> anyhow::Result<bool> {
+        Ok(parse_quantity(&self.status)? == 1)
+    }
+}
+
+/// Parse a hex-encoded quantity as returned by the Ethereum JSON-RPC
+/// API, e.g. `0x1a`.
+fn parse_quantity(quantity: &str) -> anyhow::Result<u64> {
+    if !quantity.starts_with("0x") {
+        anyhow::bail!("quantity {} is not 0x-prefixed", quantity)
+    }
+    let quantity = u64::from_str_radix(&quantity[2..], 16)?;
+
+    Ok(quantity)
+}
+
+/// Parse a quantity which may not fit in a `u64`, such as an amount
+/// of wei.
+fn parse_big_quantity(quantity: &str) -> anyhow::Result<BigUint> {
+    if !quantity.starts_with("0x") {
+        anyhow::bail!("quantity {} is not 0x-prefixed", quantity)
+    }
+
+    BigUint::parse_bytes(quantity[2..].as_bytes(), 16)
+        .ok_or_else(|| anyhow::anyhow!("invalid quantity {}", quantity))
+}
+
+/// Parse hex-encoded unformatted data, e.g. `0x00ff`.
+fn parse_data(data: &str) -> anyhow::Result<Vec<u8>> {
+    if !data.starts_with("0x") {
+        anyhow::bail!("data {} is not 0x-prefixed", data)
+    }
+    let data = hex::decode(&data[2..])?;
+
+    Ok(data)
+}
+
+fn serialize_big_quantity<S>(quantity: &Option<BigUint>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match quantity {
+        Some(quantity) => serializer.serialize_str(&format!("0x{:x}", quantity)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_data<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("0x{}", hex::encode(data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn decode_transaction_receipt() {
+        let json = r#"{
+        "blockHash":"0x2b1d8ab6b3a1e5cbd4a3d4ee5c5a5d9ebc7a7cd0a4e1f4f3c0c1b2a3d4e5f6a7",
+        "blockNumber":"0x1b",
+        "contractAddress":"0x8f8d4bd3c6b6f4c6bbb5a5b0e9ea5ea0fd62d0f1",
+        "cumulativeGasUsed":"0x2a5e8",
+        "from":"0xc5549e335b2786520f4c5d706c76c9ee69d0a028",
+        "gasUsed":"0x2a5e8",
+        "logs":[],
+        "logsBloom":"0x00",
+        "status":"0x1",
+        "to":null,
+        "transactionHash":"0x5b8b5e8b3b7e6b4f1a1d3a8e6f3f0b3c2e1d0c9b8a7f6e5d4c3b2a1f0e9d8c7b",
+        "transactionIndex":"0x0"
+        }"#;
+
+        let receipt: TransactionReceipt = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(receipt.block_number().unwrap(), 27);
+        assert_eq!(receipt.gas_used().unwrap(), 173_544);
+        assert!(receipt.contract_address.is_some());
+        assert!(receipt.is_successful().unwrap());
+    }
+
+    #[test]
+    fn decode_block_header() {
+        let json = r#"{
+        "difficulty":"0x2",
+        "extraData":"0x",
+        "gasLimit":"0x6691b7",
+        "gasUsed":"0x0",
+        "hash":"0x2b1d8ab6b3a1e5cbd4a3d4ee5c5a5d9ebc7a7cd0a4e1f4f3c0c1b2a3d4e5f6a7",
+        "number":"0x1b",
+        "parentHash":"0x5b8b5e8b3b7e6b4f1a1d3a8e6f3f0b3c2e1d0c9b8a7f6e5d4c3b2a1f0e9d8c7b",
+        "timestamp":"0x5f5e1000",
+        "transactions":[],
+        "uncles":[]
+        }"#;
+
+        let block: BlockHeader = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(u32::from(block.timestamp().unwrap()), 1_600_000_000);
+    }
+
+    #[test]
+    fn decode_reverted_transaction_receipt() {
+        let json = r#"{
+        "blockNumber":"0x1c",
+        "contractAddress":null,
+        "gasUsed":"0x5208",
+        "status":"0x0",
+        "transactionHash":"0x5b8b5e8b3b7e6b4f1a1d3a8e6f3f0b3c2e1d0c9b8a7f6e5d4c3b2a1f0e9d8c7b"
+        }"#;
+
+        let receipt: TransactionReceipt = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(receipt.contract_address, None);
+        assert!(!receipt.is_successful().unwrap());
+    }
+
+    #[test]
+    fn serialize_block_tag() {
+        assert_eq!(
+            serde_json::to_string(&BlockTag::Pending).unwrap(),
+            r#""pending""#
+        );
+    }
+
+    #[test]
+    fn given_quantity_without_prefix_then_error() {
+        assert!(parse_quantity("1a").is_err());
+        assert_eq!(parse_quantity("0x1a").unwrap(), 26);
+    }
+
+    #[test]
+    fn given_balance_above_u64_then_it_is_parsed() {
+        // 100 ether
+        assert_eq!(
+            parse_big_quantity("0x56bc75e2d63100000").unwrap(),
+            BigUint::from(100_000_000_000_000_000_000u128)
+        );
+        assert!(parse_big_quantity("56bc75e2d63100000").is_err());
+    }
+
+    #[test]
+    fn parse_call_data() {
+        assert_eq!(parse_data("0x").unwrap(), Vec::<u8>::new());
+        assert_eq!(parse_data("0x00ff").unwrap(), vec![0x00, 0xff]);
+    }
+
+    #[test]
+    fn serialize_call_request() {
+        let call = CallRequest {
+            to: Some(Address::from_str("6b175474e89094c44da98b954eedeac495271d0f").unwrap()),
+            value: Some(BigUint::from(26u32)),
+            data: vec![0x70, 0xa0, 0x82, 0x31],
+            ..CallRequest::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(&call).unwrap(),
+            serde_json::json!({
+                "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+                "value": "0x1a",
+                "data": "0x70a08231"
+            })
+        );
+    }
+}
@@ -18,12 +18,21 @@ use conquer_once::Lazy;
 pub mod bitcoin;
 pub mod bitcoin_wallet;
 pub mod bitcoind;
+pub mod config;
 pub mod dai;
+pub mod database;
+pub mod ethereum_wallet;
 pub mod float_maths;
+pub mod geth;
 pub mod jsonrpc;
+pub mod maker;
+pub mod markets;
 pub mod ongoing_swaps;
+pub mod options;
 pub mod publish;
 pub mod rate;
+pub mod redacted;
+pub mod seed;
 pub mod swap;
 
 pub static SECP: Lazy<::bitcoin::secp256k1::Secp256k1<::bitcoin::secp256k1::All>> =
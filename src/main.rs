@@ -10,30 +10,549 @@
     clippy::cast_possible_wrap,
     clippy::dbg_macro
 )]
-#![allow(dead_code)] // To be removed further down the line
 #![forbid(unsafe_code)]
 // TODO: Add no unwrap policy
 
-use conquer_once::Lazy;
+use anyhow::Context;
+use comit::{btsieve::ethereum::Web3Connector, SecretHash};
+use nectar::{
+    bitcoin, bitcoin_wallet, bitcoind,
+    config::{self, Settings},
+    dai,
+    database::{Database, SwapId},
+    ethereum_wallet,
+    maker::{Maker, TakenOrder},
+    markets::{self, OhlcInterval, Position, TradingPair},
+    options::{Command, Options},
+    rate,
+    seed::Seed,
+    swap::{
+        self, BitcoinWallet, Confirmations, Direction, EthereumWallet, Journal, Persisted, Role,
+        SafetyMargins, SwapOutcome, WalletAlice, WatchOnlyAlice, WatchOnlyBob,
+    },
+};
+use std::{cmp::min, convert::TryFrom, path::PathBuf, sync::Arc, time::Duration};
+use structopt::StructOpt;
+use tokio::sync::mpsc;
 
-mod bitcoin;
-mod bitcoin_wallet;
-mod bitcoind;
-mod dai;
-mod float_maths;
-mod jsonrpc;
-mod markets;
-mod ongoing_swaps;
-mod publish;
-mod rate;
-mod swap;
+/// How often the mid-market rate and the wallet balance are refreshed
+/// and a new order is built.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(15);
 
-#[cfg(all(test, feature = "test-docker"))]
-pub mod test_harness;
+/// How long to wait before resuming a failed swap the first time.
+const SWAP_RETRY_DELAY: Duration = Duration::from_secs(10);
 
-pub static SECP: Lazy<::bitcoin::secp256k1::Secp256k1<::bitcoin::secp256k1::All>> =
-    Lazy::new(::bitcoin::secp256k1::Secp256k1::new);
+/// The longest wait between two attempts at resuming a failed swap.
+const MAX_SWAP_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
 
-fn main() {
-    println!("Hello, world!");
+/// Environment variable holding the passphrase of the seed, it is
+/// asked for on the terminal otherwise.
+const PASSPHRASE_VAR: &str = "NECTAR_PASSPHRASE";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let options = Options::from_args();
+
+    let config_file = options
+        .config_file
+        .or_else(config::default_config_file)
+        .ok_or_else(|| anyhow::anyhow!("could not determine the configuration directory"))?;
+    let settings = Settings::read(&config_file)?;
+
+    match options.cmd {
+        Command::Trade => {
+            let seed = read_seed(&settings)?;
+            trade(settings, seed).await
+        }
+        Command::Balance => {
+            let seed = read_seed(&settings)?;
+            let bitcoin_wallet = init_bitcoin_wallet(&settings, seed.clone()).await?;
+            let balance = bitcoin_wallet.balance().await?;
+            println!("Bitcoin: {}", balance);
+
+            let ethereum_wallet = ethereum_wallet::Wallet::new(
+                seed,
+                settings.ethereum.node_url.clone(),
+                settings.ethereum.gas_price,
+            )?;
+            let balance = ethereum_wallet.ether_balance().await?;
+            println!("Ether: {} wei", balance);
+
+            Ok(())
+        }
+        Command::Deposit => {
+            let seed = read_seed(&settings)?;
+            let bitcoin_wallet = init_bitcoin_wallet(&settings, seed).await?;
+            let address = bitcoin_wallet.new_address().await?;
+            println!("Bitcoin: {}", address);
+
+            Ok(())
+        }
+        Command::Withdraw { address, amount } => {
+            let seed = read_seed(&settings)?;
+            let bitcoin_wallet = init_bitcoin_wallet(&settings, seed).await?;
+            let txid = bitcoin_wallet.send_to_address(address, amount).await?;
+            println!("Bitcoin transaction id: {}", txid);
+
+            Ok(())
+        }
+        Command::Backup => {
+            let seed = read_seed(&settings)?;
+            println!("{}", seed.to_mnemonic()?);
+
+            Ok(())
+        }
+        Command::Restore => {
+            eprint!("Mnemonic: ");
+            let mut mnemonic = String::new();
+            std::io::stdin().read_line(&mut mnemonic)?;
+            let seed = Seed::from_mnemonic(mnemonic.trim())?;
+
+            let passphrase = passphrase()?;
+            seed.persist(&seed_file(&settings), &passphrase)?;
+
+            // Creates the wallet in bitcoind from the seed if needed
+            let bitcoin_wallet = init_bitcoin_wallet(&settings, seed).await?;
+            bitcoin_wallet.rescan().await?;
+            println!("Bitcoin: {}", bitcoin_wallet.balance().await?);
+
+            Ok(())
+        }
+        Command::DumpConfig => {
+            let file = config::File::from(settings);
+            print!("{}", file.to_toml()?);
+
+            Ok(())
+        }
+    }
+}
+
+/// Read the seed from the data directory, generating it on the first
+/// start.
+fn read_seed(settings: &Settings) -> anyhow::Result<Seed> {
+    let passphrase = passphrase()?;
+
+    Seed::read_or_generate(&seed_file(settings), &passphrase)
+        .context("refusing to start without the seed")
+}
+
+fn seed_file(settings: &Settings) -> PathBuf {
+    settings.data_dir.join("seed.json")
+}
+
+/// The passphrase the seed is encrypted with, taken from the
+/// environment or typed in.
+fn passphrase() -> anyhow::Result<String> {
+    let passphrase = match std::env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            eprint!("Passphrase of the seed: ");
+            let mut passphrase = String::new();
+            std::io::stdin().read_line(&mut passphrase)?;
+            passphrase.trim_end_matches(&['\r', '\n'][..]).to_owned()
+        }
+    };
+
+    if passphrase.is_empty() {
+        anyhow::bail!("the passphrase of the seed must not be empty")
+    }
+
+    Ok(passphrase)
+}
+
+async fn init_bitcoin_wallet(
+    settings: &Settings,
+    seed: Seed,
+) -> anyhow::Result<bitcoin_wallet::Wallet> {
+    let bitcoin_wallet =
+        bitcoin_wallet::Wallet::new(seed, bitcoind_client(settings), settings.bitcoin.network)?;
+    bitcoin_wallet
+        .init()
+        .await
+        .context("failed to initialise the bitcoin wallet")?;
+
+    Ok(bitcoin_wallet)
+}
+
+fn bitcoind_client(settings: &Settings) -> bitcoind::Client {
+    bitcoind::Client::with_auth(
+        settings.bitcoin.bitcoind.node_url.clone(),
+        settings.bitcoin.bitcoind.auth.clone(),
+    )
+}
+
+async fn trade(settings: Settings, seed: Seed) -> anyhow::Result<()> {
+    let bitcoin_network = settings.bitcoin.network;
+
+    bitcoind_client(&settings)
+        .blockchain_info()
+        .await?
+        .ensure_ready_for(bitcoin_network)
+        .context("refusing to trade")?;
+
+    let bitcoin_wallet = init_bitcoin_wallet(&settings, seed.clone()).await?;
+    let bitcoin_fee_estimator =
+        bitcoin::fee::Estimator::new(bitcoind_client(&settings), settings.bitcoin.fees);
+    let ethereum_wallet = ethereum_wallet::Wallet::new(
+        seed,
+        settings.ethereum.node_url.clone(),
+        settings.ethereum.gas_price,
+    )?;
+    let ethereum_chain_id = ethereum_wallet.chain_id().await?;
+    tracing::info!(
+        "Ethereum account: {:?}, chain id: {}",
+        ethereum_wallet.account(),
+        u32::from(ethereum_chain_id)
+    );
+
+    let context = SwapContext {
+        bitcoin_connector: Arc::new(bitcoind::Connector::new(
+            settings.bitcoin.bitcoind.node_url.clone(),
+            bitcoin_network,
+            settings.bitcoin.bitcoind.auth.clone(),
+        )?),
+        ethereum_connector: Arc::new(Web3Connector::new(settings.ethereum.node_url.clone())),
+        bitcoin_wallet: bitcoin_wallet.clone(),
+        bitcoin_fee_estimator: bitcoin_fee_estimator.clone(),
+        ethereum_wallet,
+        safety_margins: safety_margins(&settings)?,
+        confirmations: Confirmations {
+            bitcoin: settings.bitcoin.confirmations,
+            ethereum: settings.ethereum.confirmations,
+        },
+        db: Arc::new(Database::open(&settings.data_dir.join("database"))?),
+    };
+
+    let mut maker = Maker::new(
+        bitcoin_fee_estimator.max_fees_per_swap().await?,
+        settings.maker.max_sell_amount,
+        settings.maker.max_sell_dai_amount.clone(),
+        settings.maker.spread,
+    );
+
+    // TODO: Hand the sender to the network layer once it exists, until
+    // then nothing is taken and the sender is only kept alive
+    let (_taken_order_sender, mut taken_orders) = mpsc::channel::<TakenOrder>(16);
+    let (finished_swap_sender, mut finished_swaps) = mpsc::channel::<(SwapId, TakenOrder)>(16);
+
+    // Transactions sent before the restart must keep their nonce
+    // whichever swap resumes first
+    for nonce in context.db.ethereum_nonces_in_flight()? {
+        context.ethereum_wallet.reserve_nonce(nonce);
+    }
+
+    for (swap_id, swap) in context.db.all_swaps()? {
+        let order = TakenOrder::from(swap);
+        maker.process_resumed_swap(&order)?;
+        if let Err(e) = order.ensure_chains(bitcoin_network, ethereum_chain_id) {
+            tracing::error!("not resuming swap {}: {}", swap_id, e);
+            continue;
+        }
+
+        tracing::info!("resuming swap {}", swap_id);
+        spawn_swap(
+            context.clone(),
+            swap_id,
+            order,
+            finished_swap_sender.clone(),
+        );
+    }
+
+    let mut update_interval = tokio::time::interval(UPDATE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = update_interval.tick() => {
+                let ohlc_interval = settings.maker.kraken_ohlc_interval;
+                match update_order(&mut maker, &bitcoin_wallet, &bitcoin_fee_estimator, ohlc_interval).await {
+                    Ok(order) => tracing::info!(
+                        "new order: selling {} BTC for {} attodai",
+                        order.sell_amount.as_btc(),
+                        order.buy_amount
+                    ),
+                    Err(e) => tracing::warn!("could not build new order: {:#}", e),
+                }
+                let ethereum_wallet = &context.ethereum_wallet;
+                let dai_contract = settings.ethereum.dai_contract;
+                match update_dai_order(&mut maker, ethereum_wallet, dai_contract, ohlc_interval).await {
+                    Ok(Some(order)) => tracing::info!(
+                        "new order: selling {} attodai for {} BTC",
+                        order.sell_amount,
+                        order.buy_amount.as_btc()
+                    ),
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("could not build new dai order: {:#}", e),
+                }
+            }
+            Some(order) = taken_orders.recv() => {
+                match maker.accept_taken_order(&order, &context.db, bitcoin_network, ethereum_chain_id) {
+                    Ok(swap_id) => {
+                        tracing::info!("starting swap {}", swap_id);
+                        spawn_swap(context.clone(), swap_id, order, finished_swap_sender.clone());
+                    }
+                    Err(e) => tracing::info!("refusing taken order: {:#}", e),
+                }
+            }
+            Some((swap_id, order)) = finished_swaps.recv() => {
+                // The swap is over either way, at worst it is resumed
+                // and found finished again on the next start
+                if let Err(e) = context.db.remove_swap(&swap_id) {
+                    tracing::error!("failed to remove finished swap {}: {:#}", swap_id, e);
+                }
+                maker.process_finished_swap(&order);
+            }
+        }
+    }
+}
+
+async fn update_order(
+    maker: &mut Maker,
+    bitcoin_wallet: &bitcoin_wallet::Wallet,
+    bitcoin_fee_estimator: &bitcoin::fee::Estimator,
+    ohlc_interval: OhlcInterval,
+) -> anyhow::Result<nectar::publish::DaiBitcoinOrder> {
+    let mid_market_rate =
+        markets::get_rate(TradingPair::BtcDai, Position::Sell, ohlc_interval).await?;
+    maker.update_rate(rate::Rate::try_from(mid_market_rate)?);
+
+    let balance = bitcoin_wallet.balance().await?;
+    maker.update_bitcoin_balance(bitcoin::Amount::from_sat(balance.as_sat()));
+
+    let fees = bitcoin_fee_estimator.max_fees_per_swap().await?;
+    maker.update_bitcoin_fees(fees);
+
+    maker.new_dai_bitcoin_order()
+}
+
+/// Nothing is fetched if nectar does not sell dai.
+async fn update_dai_order(
+    maker: &mut Maker,
+    ethereum_wallet: &ethereum_wallet::Wallet,
+    dai_contract: comit::ethereum::Address,
+    ohlc_interval: OhlcInterval,
+) -> anyhow::Result<Option<nectar::publish::BitcoinDaiOrder>> {
+    if !maker.sells_dai() {
+        return Ok(None);
+    }
+
+    let mid_market_rate =
+        markets::get_rate(TradingPair::BtcDai, Position::Buy, ohlc_interval).await?;
+    maker.update_dai_rate(rate::Rate::try_from(mid_market_rate)?);
+
+    let balance = ethereum_wallet.erc20_balance(dai_contract).await?;
+    maker.update_dai_balance(dai::Amount::from_atto(balance));
+
+    maker.new_bitcoin_dai_order()
+}
+
+fn safety_margins(settings: &Settings) -> anyhow::Result<SafetyMargins> {
+    let bitcoin = settings
+        .bitcoin
+        .safety
+        .margin()
+        .context("bitcoin safety margin is too long")?;
+    let ethereum = settings
+        .ethereum
+        .safety
+        .margin()
+        .context("ethereum safety margin is too long")?;
+
+    Ok(SafetyMargins { bitcoin, ethereum })
+}
+
+/// Everything a swap execution needs to interact with the
+/// blockchains and to record its progress.
+#[derive(Debug, Clone)]
+struct SwapContext {
+    bitcoin_connector: Arc<bitcoind::Connector>,
+    ethereum_connector: Arc<Web3Connector>,
+    bitcoin_wallet: bitcoin_wallet::Wallet,
+    bitcoin_fee_estimator: bitcoin::fee::Estimator,
+    ethereum_wallet: ethereum_wallet::Wallet,
+    safety_margins: SafetyMargins,
+    confirmations: Confirmations,
+    db: Arc<Database>,
+}
+
+/// Execute the swap in the background and report back once it is
+/// over so that the locked funds can be released.
+///
+/// A failed swap is resumed from its last recorded step after a
+/// delay, which doubles with every failure. It stays in the database
+/// and its funds stay locked until it is over.
+fn spawn_swap(
+    context: SwapContext,
+    swap_id: SwapId,
+    order: TakenOrder,
+    mut finished_swap_sender: mpsc::Sender<(SwapId, TakenOrder)>,
+) {
+    tokio::spawn(async move {
+        let mut retry_delay = SWAP_RETRY_DELAY;
+
+        loop {
+            match execute_swap(context.clone(), swap_id, order.clone()).await {
+                Ok(outcome) => {
+                    if outcome.is_redeemed() {
+                        tracing::info!("swap {} finished: {:?}", swap_id, outcome);
+                    } else {
+                        tracing::warn!("swap {} did not go through: {:?}", swap_id, outcome);
+                    }
+                    let _ = finished_swap_sender.send((swap_id, order)).await;
+
+                    return;
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "swap {} failed, resuming it in {}s: {:#}",
+                        swap_id,
+                        retry_delay.as_secs(),
+                        e
+                    );
+                    tokio::time::delay_for(retry_delay).await;
+                    retry_delay = min(retry_delay * 2, MAX_SWAP_RETRY_DELAY);
+                }
+            }
+        }
+    });
+}
+
+async fn execute_swap(
+    context: SwapContext,
+    swap_id: SwapId,
+    order: TakenOrder,
+) -> anyhow::Result<SwapOutcome> {
+    match order.direction {
+        Direction::HbitHerc20 => execute_hbit_herc20_swap(context, swap_id, order).await,
+        Direction::Herc20Hbit => execute_herc20_hbit_swap(context, swap_id, order).await,
+    }
+}
+
+/// Sell bitcoin for dai.
+async fn execute_hbit_herc20_swap(
+    context: SwapContext,
+    swap_id: SwapId,
+    order: TakenOrder,
+) -> anyhow::Result<SwapOutcome> {
+    let secret_hash = SecretHash::new(order.secret);
+    let journal = Journal::new(Arc::clone(&context.db), swap_id);
+
+    let alice = Persisted {
+        actor: WalletAlice {
+            alpha_wallet: BitcoinWallet::new(
+                Arc::clone(&context.bitcoin_connector),
+                context.bitcoin_wallet,
+                context.bitcoin_fee_estimator,
+            )
+            .with_journal(journal.clone()),
+            beta_wallet: EthereumWallet::new(
+                Arc::clone(&context.ethereum_connector),
+                context.ethereum_wallet,
+            )
+            .with_journal(journal),
+            private_protocol_details: order.funder_details(),
+            secret: order.secret,
+        },
+        watch_only: WatchOnlyAlice {
+            alpha_connector: Arc::clone(&context.bitcoin_connector),
+            beta_connector: Arc::clone(&context.ethereum_connector),
+            secret_hash,
+            start_of_swap: order.start_of_swap,
+            confirmations: context.confirmations,
+        },
+        role: Role::Alice,
+        direction: Direction::HbitHerc20,
+        db: Arc::clone(&context.db),
+        swap_id,
+    };
+    let watch_only_bob = WatchOnlyBob {
+        alpha_connector: context.bitcoin_connector,
+        beta_connector: context.ethereum_connector,
+        secret_hash,
+        start_of_swap: order.start_of_swap,
+        confirmations: context.confirmations,
+    };
+    let bob = Persisted {
+        actor: watch_only_bob.clone(),
+        watch_only: watch_only_bob,
+        role: Role::Bob,
+        direction: Direction::HbitHerc20,
+        db: context.db,
+        swap_id,
+    };
+
+    swap::hbit_herc20(
+        alice,
+        bob,
+        order.hbit_params,
+        order.herc20_params,
+        context.safety_margins,
+        &*nectar::SECP,
+    )
+    .await
+}
+
+/// Sell dai for bitcoin.
+async fn execute_herc20_hbit_swap(
+    context: SwapContext,
+    swap_id: SwapId,
+    order: TakenOrder,
+) -> anyhow::Result<SwapOutcome> {
+    let secret_hash = SecretHash::new(order.secret);
+    let journal = Journal::new(Arc::clone(&context.db), swap_id);
+
+    let alice = Persisted {
+        actor: WalletAlice {
+            alpha_wallet: EthereumWallet::new(
+                Arc::clone(&context.ethereum_connector),
+                context.ethereum_wallet,
+            )
+            .with_journal(journal.clone()),
+            beta_wallet: BitcoinWallet::new(
+                Arc::clone(&context.bitcoin_connector),
+                context.bitcoin_wallet,
+                context.bitcoin_fee_estimator,
+            )
+            .with_journal(journal),
+            private_protocol_details: order.redeemer_details(),
+            secret: order.secret,
+        },
+        watch_only: WatchOnlyAlice {
+            alpha_connector: Arc::clone(&context.ethereum_connector),
+            beta_connector: Arc::clone(&context.bitcoin_connector),
+            secret_hash,
+            start_of_swap: order.start_of_swap,
+            confirmations: context.confirmations,
+        },
+        role: Role::Alice,
+        direction: Direction::Herc20Hbit,
+        db: Arc::clone(&context.db),
+        swap_id,
+    };
+    let watch_only_bob = WatchOnlyBob {
+        alpha_connector: context.ethereum_connector,
+        beta_connector: context.bitcoin_connector,
+        secret_hash,
+        start_of_swap: order.start_of_swap,
+        confirmations: context.confirmations,
+    };
+    let bob = Persisted {
+        actor: watch_only_bob.clone(),
+        watch_only: watch_only_bob,
+        role: Role::Bob,
+        direction: Direction::Herc20Hbit,
+        db: context.db,
+        swap_id,
+    };
+
+    swap::herc20_hbit(
+        alice,
+        bob,
+        order.herc20_params,
+        order.hbit_params,
+        context.safety_margins,
+        &*nectar::SECP,
+    )
+    .await
 }
@@ -0,0 +1,634 @@
+use crate::bitcoin;
+use crate::dai;
+use crate::database::{Database, Swap, SwapId};
+use crate::ongoing_swaps::{self, OngoingSwaps, Peer};
+use crate::publish::{
+    new_bitcoin_dai_order, new_dai_bitcoin_order, BitcoinBalance, BitcoinDaiOrder, BitcoinFees,
+    BitcoinLockedFunds, DaiBalance, DaiBitcoinOrder, DaiLockedFunds,
+};
+use crate::rate::{Rate, Spread};
+use crate::swap::{hbit, herc20, Direction};
+use ::bitcoin::{secp256k1::SecretKey, Address, Network};
+use chrono::NaiveDateTime;
+use comit::{ethereum::ChainId, Secret};
+use num::BigUint;
+
+/// Everything needed to execute a swap as Alice once a taker has
+/// accepted one of our orders, whichever asset we sell.
+///
+/// The hbit key and address are used to refund the bitcoin we sell or
+/// to redeem the bitcoin we buy, depending on the direction.
+#[derive(Debug, Clone)]
+pub struct TakenOrder {
+    pub peer: Peer,
+    pub direction: Direction,
+    pub hbit_params: hbit::Params,
+    pub herc20_params: herc20::Params,
+    pub hbit_transient_sk: SecretKey,
+    pub hbit_final_identity: Address,
+    pub secret: Secret,
+    pub start_of_swap: NaiveDateTime,
+}
+
+impl TakenOrder {
+    pub fn funder_details(&self) -> hbit::PrivateDetailsFunder {
+        hbit::PrivateDetailsFunder {
+            transient_refund_sk: self.hbit_transient_sk,
+            final_refund_identity: self.hbit_final_identity.clone(),
+        }
+    }
+
+    pub fn redeemer_details(&self) -> hbit::PrivateDetailsRedeemer {
+        hbit::PrivateDetailsRedeemer {
+            transient_redeem_sk: self.hbit_transient_sk,
+            final_redeem_identity: self.hbit_final_identity.clone(),
+        }
+    }
+
+    /// The bitcoin we sell, if any.
+    fn locked_bitcoin(&self) -> bitcoin::Amount {
+        match self.direction {
+            Direction::HbitHerc20 => bitcoin::Amount::from_sat(self.hbit_params.asset.as_sat()),
+            Direction::Herc20Hbit => bitcoin::Amount::from_sat(0),
+        }
+    }
+
+    /// The dai we sell, if any.
+    fn locked_dai(&self) -> dai::Amount {
+        match self.direction {
+            Direction::HbitHerc20 => dai::Amount::from_atto(BigUint::from(0u8)),
+            Direction::Herc20Hbit => {
+                let atto = self.herc20_params.asset.quantity.to_wei_dec();
+                dai::Amount::from_atto(atto.parse().unwrap_or_default())
+            }
+        }
+    }
+
+    /// Make sure the swap takes place on the chains followed by our
+    /// nodes, a swap on another chain would never see its HTLCs.
+    pub fn ensure_chains(
+        &self,
+        bitcoin_network: Network,
+        ethereum_chain_id: ChainId,
+    ) -> Result<(), WrongChain> {
+        if self.hbit_params.network != bitcoin_network {
+            return Err(WrongChain::Bitcoin {
+                order: self.hbit_params.network,
+                node: bitcoin_network,
+            });
+        }
+        if self.herc20_params.chain_id != ethereum_chain_id {
+            return Err(WrongChain::Ethereum {
+                order: u32::from(self.herc20_params.chain_id),
+                node: u32::from(ethereum_chain_id),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps track of the state needed to publish orders and to decide
+/// whether a taken order can be executed.
+///
+/// Dai is only sold if a maximum amount to sell is set.
+#[derive(Debug)]
+pub struct Maker {
+    bitcoin_balance: bitcoin::Amount,
+    bitcoin_fees: bitcoin::Amount,
+    bitcoin_locked_funds: bitcoin::Amount,
+    max_sell_amount: bitcoin::Amount,
+    mid_market_rate: Option<Rate>,
+    dai_balance: dai::Amount,
+    dai_locked_funds: dai::Amount,
+    max_dai_sell_amount: Option<dai::Amount>,
+    dai_mid_market_rate: Option<Rate>,
+    spread: Spread,
+    ongoing_swaps: OngoingSwaps,
+}
+
+impl Maker {
+    pub fn new(
+        bitcoin_fees: bitcoin::Amount,
+        max_sell_amount: bitcoin::Amount,
+        max_dai_sell_amount: Option<dai::Amount>,
+        spread: Spread,
+    ) -> Self {
+        Maker {
+            bitcoin_balance: bitcoin::Amount::from_sat(0),
+            bitcoin_fees,
+            bitcoin_locked_funds: bitcoin::Amount::from_sat(0),
+            max_sell_amount,
+            mid_market_rate: None,
+            dai_balance: dai::Amount::from_atto(BigUint::from(0u8)),
+            dai_locked_funds: dai::Amount::from_atto(BigUint::from(0u8)),
+            max_dai_sell_amount,
+            dai_mid_market_rate: None,
+            spread,
+            ongoing_swaps: OngoingSwaps::default(),
+        }
+    }
+
+    pub fn sells_dai(&self) -> bool {
+        self.max_dai_sell_amount.is_some()
+    }
+
+    pub fn update_rate(&mut self, mid_market_rate: Rate) {
+        self.mid_market_rate = Some(mid_market_rate);
+    }
+
+    /// The rate of 1 DAI in bitcoin, used for the orders selling dai.
+    pub fn update_dai_rate(&mut self, mid_market_rate: Rate) {
+        self.dai_mid_market_rate = Some(mid_market_rate);
+    }
+
+    pub fn update_dai_balance(&mut self, balance: dai::Amount) {
+        self.dai_balance = balance;
+    }
+
+    pub fn update_bitcoin_balance(&mut self, balance: bitcoin::Amount) {
+        self.bitcoin_balance = balance;
+    }
+
+    pub fn update_bitcoin_fees(&mut self, fees: bitcoin::Amount) {
+        self.bitcoin_fees = fees;
+    }
+
+    pub fn new_dai_bitcoin_order(&self) -> anyhow::Result<DaiBitcoinOrder> {
+        let mid_market_rate = self.mid_market_rate.ok_or(NoMidMarketRate)?;
+
+        if self.bitcoin_balance < self.bitcoin_locked_funds + self.bitcoin_fees {
+            anyhow::bail!(InsufficientFunds {
+                balance: self.bitcoin_balance,
+                locked_funds: self.bitcoin_locked_funds,
+                fees: self.bitcoin_fees,
+            })
+        }
+
+        new_dai_bitcoin_order(
+            self,
+            self,
+            self.max_sell_amount,
+            mid_market_rate,
+            self.spread,
+        )
+    }
+
+    /// `None` if nectar does not sell dai.
+    pub fn new_bitcoin_dai_order(&self) -> anyhow::Result<Option<BitcoinDaiOrder>> {
+        let max_sell_amount = match &self.max_dai_sell_amount {
+            Some(max_sell_amount) => max_sell_amount.clone(),
+            None => return Ok(None),
+        };
+        let mid_market_rate = self.dai_mid_market_rate.ok_or(NoMidMarketRate)?;
+
+        let order =
+            new_bitcoin_dai_order(self, self, max_sell_amount, mid_market_rate, self.spread)?;
+
+        Ok(Some(order))
+    }
+
+    /// Decide whether the swap for this order can be executed. If it
+    /// can, the sell amount is locked until the swap finishes.
+    ///
+    /// The fees of the swap must be left in the wallet on top of the
+    /// bitcoin sell amount, the HTLC could not be funded otherwise.
+    pub fn process_taken_order(&mut self, order: &TakenOrder) -> anyhow::Result<()> {
+        match order.direction {
+            Direction::HbitHerc20 => {
+                let available = self
+                    .bitcoin_balance
+                    .checked_sub(self.bitcoin_locked_funds)
+                    .and_then(|available| available.checked_sub(self.bitcoin_fees))
+                    .unwrap_or_else(|| bitcoin::Amount::from_sat(0));
+
+                if order.locked_bitcoin() > available {
+                    anyhow::bail!(InsufficientFunds {
+                        balance: self.bitcoin_balance,
+                        locked_funds: self.bitcoin_locked_funds,
+                        fees: self.bitcoin_fees,
+                    })
+                }
+            }
+            Direction::Herc20Hbit => {
+                if !self.sells_dai() {
+                    anyhow::bail!("not selling dai")
+                }
+
+                let available = self
+                    .dai_balance
+                    .clone()
+                    .checked_sub(self.dai_locked_funds.clone())
+                    .unwrap_or_else(|| dai::Amount::from_atto(BigUint::from(0u8)));
+
+                if order.locked_dai() > available {
+                    anyhow::bail!(InsufficientDai {
+                        balance: self.dai_balance.clone(),
+                        locked_funds: self.dai_locked_funds.clone(),
+                    })
+                }
+            }
+        }
+
+        self.ongoing_swaps
+            .insert(ongoing_swaps::Order::new(order.peer))?;
+        self.lock(order);
+
+        Ok(())
+    }
+
+    /// Accept a taken order: it must be on our chains and within our
+    /// balance. The swap is recorded before it is returned for
+    /// execution so that it is resumed after a restart.
+    pub fn accept_taken_order(
+        &mut self,
+        order: &TakenOrder,
+        db: &Database,
+        bitcoin_network: Network,
+        ethereum_chain_id: ChainId,
+    ) -> anyhow::Result<SwapId> {
+        order.ensure_chains(bitcoin_network, ethereum_chain_id)?;
+        self.process_taken_order(order)?;
+
+        let swap_id = SwapId::random();
+        if let Err(e) = db.insert_swap(&swap_id, &Swap::from(order)) {
+            self.process_finished_swap(order);
+            return Err(e);
+        }
+
+        Ok(swap_id)
+    }
+
+    /// Register a swap that was already in progress before a restart.
+    /// Its funds are locked regardless of the current balance since
+    /// the swap is going ahead either way.
+    pub fn process_resumed_swap(&mut self, order: &TakenOrder) -> anyhow::Result<()> {
+        self.ongoing_swaps
+            .insert(ongoing_swaps::Order::new(order.peer))?;
+        self.lock(order);
+
+        Ok(())
+    }
+
+    /// Release the funds locked for this order, whatever the outcome
+    /// of the swap.
+    pub fn process_finished_swap(&mut self, order: &TakenOrder) {
+        self.ongoing_swaps
+            .remove(ongoing_swaps::Order::new(order.peer));
+        self.bitcoin_locked_funds = self
+            .bitcoin_locked_funds
+            .checked_sub(order.locked_bitcoin())
+            .unwrap_or_else(|| bitcoin::Amount::from_sat(0));
+        self.dai_locked_funds = self
+            .dai_locked_funds
+            .clone()
+            .checked_sub(order.locked_dai())
+            .unwrap_or_else(|| dai::Amount::from_atto(BigUint::from(0u8)));
+    }
+
+    fn lock(&mut self, order: &TakenOrder) {
+        self.bitcoin_locked_funds = self.bitcoin_locked_funds + order.locked_bitcoin();
+        self.dai_locked_funds =
+            dai::Amount::from_atto(self.dai_locked_funds.as_atto() + order.locked_dai().as_atto());
+    }
+}
+
+impl BitcoinBalance for &Maker {
+    fn bitcoin_balance(&self) -> bitcoin::Amount {
+        self.bitcoin_balance
+    }
+}
+
+impl BitcoinFees for &Maker {
+    fn bitcoin_fees(&self) -> bitcoin::Amount {
+        self.bitcoin_fees
+    }
+}
+
+impl BitcoinLockedFunds for &Maker {
+    fn bitcoin_locked_funds(&self) -> bitcoin::Amount {
+        self.bitcoin_locked_funds
+    }
+}
+
+impl DaiBalance for &Maker {
+    fn dai_balance(&self) -> dai::Amount {
+        self.dai_balance.clone()
+    }
+}
+
+impl DaiLockedFunds for &Maker {
+    fn dai_locked_funds(&self) -> dai::Amount {
+        self.dai_locked_funds.clone()
+    }
+}
+
+#[derive(Copy, Clone, Debug, thiserror::Error)]
+#[error("mid-market rate has not been fetched yet")]
+pub struct NoMidMarketRate;
+
+#[derive(Copy, Clone, Debug, thiserror::Error)]
+#[error("insufficient bitcoin funds: balance {balance:?}, locked {locked_funds:?}, fees {fees:?}")]
+pub struct InsufficientFunds {
+    balance: bitcoin::Amount,
+    locked_funds: bitcoin::Amount,
+    fees: bitcoin::Amount,
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("insufficient dai funds: balance {balance}, locked {locked_funds}")]
+pub struct InsufficientDai {
+    balance: dai::Amount,
+    locked_funds: dai::Amount,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, thiserror::Error)]
+pub enum WrongChain {
+    #[error("order is on bitcoin network {order} but bitcoind follows {node}")]
+    Bitcoin { order: Network, node: Network },
+    #[error("order is on ethereum chain {order} but the node follows chain {node}")]
+    Ethereum { order: u32, node: u32 },
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ::bitcoin::secp256k1;
+    use comit::{
+        asset::{
+            self,
+            ethereum::{Erc20Quantity, FromWei},
+        },
+        ethereum, identity, SecretHash, Timestamp,
+    };
+    use num::ToPrimitive;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    fn btc(btc: f64) -> bitcoin::Amount {
+        bitcoin::Amount::from_btc(btc).unwrap()
+    }
+
+    fn dai(dai: f64) -> dai::Amount {
+        dai::Amount::from_dai_trunc(dai).unwrap()
+    }
+
+    pub(crate) fn taken_order(peer: Peer, sell_amount: bitcoin::Amount) -> TakenOrder {
+        let secret = Secret::from(*b"hello world, you are beautiful!!");
+        let secret_hash = SecretHash::new(secret);
+        let sk = secp256k1::SecretKey::from_str(
+            "01010101010101010001020304050607ffff0000ffff00006363636363636363",
+        )
+        .unwrap();
+        let bitcoin_identity = identity::Bitcoin::from_secret_key(&*crate::SECP, &sk);
+        let address =
+            ::bitcoin::Address::from_str("bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl").unwrap();
+        let ethereum_identity =
+            identity::Ethereum::from_str("c5549e335b2786520f4c5d706c76c9ee69d0a028").unwrap();
+        let token_contract =
+            ethereum::Address::from_str("6b175474e89094c44da98b954eedeac495271d0f").unwrap();
+
+        TakenOrder {
+            peer,
+            direction: Direction::HbitHerc20,
+            hbit_params: hbit::Params {
+                network: Network::Regtest,
+                asset: asset::Bitcoin::from_sat(sell_amount.as_sat()),
+                redeem_identity: bitcoin_identity,
+                refund_identity: bitcoin_identity,
+                expiry: Timestamp::from(0),
+                secret_hash,
+            },
+            herc20_params: herc20::Params {
+                asset: asset::Erc20::new(token_contract, Erc20Quantity::from_wei(1_000u32)),
+                redeem_identity: ethereum_identity,
+                refund_identity: ethereum_identity,
+                expiry: Timestamp::from(0),
+                chain_id: ethereum::ChainId::regtest(),
+                secret_hash,
+            },
+            hbit_transient_sk: sk,
+            hbit_final_identity: address,
+            secret,
+            start_of_swap: chrono::Utc::now().naive_local(),
+        }
+    }
+
+    fn dai_taken_order(peer: Peer, sell_amount: dai::Amount) -> TakenOrder {
+        let mut order = taken_order(peer, btc(1.0));
+        order.direction = Direction::Herc20Hbit;
+        let atto = sell_amount.as_atto().to_u128().unwrap();
+        order.herc20_params.asset.quantity = Erc20Quantity::from_wei(atto);
+
+        order
+    }
+
+    fn maker() -> Maker {
+        let mut maker = Maker::new(
+            btc(0.0),
+            btc(100.0),
+            Some(dai(1_000.0)),
+            Spread::new(0).unwrap(),
+        );
+        maker.update_bitcoin_balance(btc(10.0));
+        maker.update_rate(Rate::try_from(1.0).unwrap());
+        maker.update_dai_balance(dai(500.0));
+        maker.update_dai_rate(Rate::try_from(1.0).unwrap());
+
+        maker
+    }
+
+    #[test]
+    fn given_no_rate_then_no_order_is_created() {
+        let maker = Maker::new(
+            btc(0.0),
+            btc(100.0),
+            Some(dai(1_000.0)),
+            Spread::new(0).unwrap(),
+        );
+
+        assert!(maker.new_dai_bitcoin_order().is_err());
+        assert!(maker.new_bitcoin_dai_order().is_err());
+    }
+
+    #[test]
+    fn given_no_max_dai_sell_amount_then_dai_is_not_sold() {
+        let mut maker = Maker::new(btc(0.0), btc(100.0), None, Spread::new(0).unwrap());
+        maker.update_dai_balance(dai(500.0));
+        maker.update_dai_rate(Rate::try_from(1.0).unwrap());
+
+        let order = maker.new_bitcoin_dai_order().unwrap();
+        let result = maker.process_taken_order(&dai_taken_order(Peer::new(0), dai(1.0)));
+
+        assert!(order.is_none());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn given_dai_taken_order_then_dai_is_locked_and_bitcoin_is_not() {
+        let mut maker = maker();
+
+        maker
+            .process_taken_order(&dai_taken_order(Peer::new(0), dai(200.0)))
+            .unwrap();
+        let dai_order = maker.new_bitcoin_dai_order().unwrap().unwrap();
+        let bitcoin_order = maker.new_dai_bitcoin_order().unwrap();
+
+        assert_eq!(dai_order.sell_amount, dai(300.0));
+        assert_eq!(bitcoin_order.sell_amount, btc(10.0));
+    }
+
+    #[test]
+    fn given_finished_dai_swap_then_dai_is_released() {
+        let mut maker = maker();
+        let taken_order = dai_taken_order(Peer::new(0), dai(200.0));
+
+        maker.process_taken_order(&taken_order).unwrap();
+        maker.process_finished_swap(&taken_order);
+        let order = maker.new_bitcoin_dai_order().unwrap().unwrap();
+
+        assert_eq!(order.sell_amount, dai(500.0));
+    }
+
+    #[test]
+    fn given_dai_taken_order_above_available_balance_then_refuse() {
+        let mut maker = maker();
+
+        let result = maker.process_taken_order(&dai_taken_order(Peer::new(0), dai(501.0)));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn given_taken_order_then_sell_amount_is_locked() {
+        let mut maker = maker();
+
+        maker
+            .process_taken_order(&taken_order(Peer::new(0), btc(3.0)))
+            .unwrap();
+        let order = maker.new_dai_bitcoin_order().unwrap();
+
+        assert_eq!(order.sell_amount, btc(7.0));
+    }
+
+    #[test]
+    fn given_finished_swap_then_sell_amount_is_released() {
+        let mut maker = maker();
+        let taken_order = taken_order(Peer::new(0), btc(3.0));
+
+        maker.process_taken_order(&taken_order).unwrap();
+        maker.process_finished_swap(&taken_order);
+        let order = maker.new_dai_bitcoin_order().unwrap();
+
+        assert_eq!(order.sell_amount, btc(10.0));
+    }
+
+    #[test]
+    fn given_updated_fees_then_they_are_deducted_from_sell_amount() {
+        let mut maker = maker();
+
+        maker.update_bitcoin_fees(btc(0.5));
+        let order = maker.new_dai_bitcoin_order().unwrap();
+
+        assert_eq!(order.sell_amount, btc(9.5));
+    }
+
+    #[test]
+    fn given_taken_order_above_available_balance_then_refuse() {
+        let mut maker = maker();
+
+        let result = maker.process_taken_order(&taken_order(Peer::new(0), btc(11.0)));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn given_taken_order_leaving_nothing_for_fees_then_refuse() {
+        let mut maker = maker();
+        maker.update_bitcoin_fees(btc(0.5));
+
+        let whole_balance = maker.process_taken_order(&taken_order(Peer::new(0), btc(10.0)));
+        let balance_minus_fees = maker.process_taken_order(&taken_order(Peer::new(1), btc(9.5)));
+
+        assert!(whole_balance.is_err());
+        assert!(balance_minus_fees.is_ok());
+    }
+
+    #[test]
+    fn given_two_taken_orders_from_same_peer_then_refuse_second() {
+        let mut maker = maker();
+
+        let first = maker.process_taken_order(&taken_order(Peer::new(0), btc(1.0)));
+        let second = maker.process_taken_order(&taken_order(Peer::new(0), btc(1.0)));
+
+        assert!(first.is_ok());
+        assert!(second.is_err());
+    }
+
+    #[tokio::test]
+    async fn given_taken_order_then_swap_is_recorded_and_funds_are_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let mut maker = maker();
+        let (mut sender, mut taken_orders) = tokio::sync::mpsc::channel(1);
+
+        sender
+            .send(taken_order(Peer::new(0), btc(3.0)))
+            .await
+            .unwrap();
+        let order = taken_orders.recv().await.unwrap();
+        let swap_id = maker
+            .accept_taken_order(&order, &db, Network::Regtest, ethereum::ChainId::regtest())
+            .unwrap();
+
+        let swaps = db.all_swaps().unwrap();
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].0, swap_id);
+        assert_eq!(maker.new_dai_bitcoin_order().unwrap().sell_amount, btc(7.0));
+    }
+
+    #[test]
+    fn given_taken_order_on_other_chain_then_it_is_not_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path()).unwrap();
+        let mut maker = maker();
+
+        let result = maker.accept_taken_order(
+            &taken_order(Peer::new(0), btc(3.0)),
+            &db,
+            Network::Testnet,
+            ethereum::ChainId::regtest(),
+        );
+
+        assert!(result.is_err());
+        assert!(db.all_swaps().unwrap().is_empty());
+        assert_eq!(
+            maker.new_dai_bitcoin_order().unwrap().sell_amount,
+            btc(10.0)
+        );
+    }
+
+    #[test]
+    fn given_order_on_other_chain_then_refuse() {
+        let order = taken_order(Peer::new(0), btc(1.0));
+
+        assert_eq!(
+            order.ensure_chains(Network::Regtest, ethereum::ChainId::regtest()),
+            Ok(())
+        );
+        assert_eq!(
+            order.ensure_chains(Network::Testnet, ethereum::ChainId::regtest()),
+            Err(WrongChain::Bitcoin {
+                order: Network::Regtest,
+                node: Network::Testnet,
+            })
+        );
+        assert_eq!(
+            order.ensure_chains(Network::Regtest, ethereum::ChainId::mainnet()),
+            Err(WrongChain::Ethereum {
+                order: u32::from(ethereum::ChainId::regtest()),
+                node: u32::from(ethereum::ChainId::mainnet()),
+            })
+        );
+    }
+}
@@ -1,5 +1,9 @@
 mod kraken;
+pub use kraken::OhlcInterval;
+
+use crate::float_maths::truncate;
 use chrono::{DateTime, Utc};
+use std::convert::TryFrom;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, strum_macros::Display)]
 pub enum TradingPair {
@@ -61,9 +65,25 @@ impl Ohlc {
     }
 }
 
+impl TryFrom<Rate> for crate::rate::Rate {
+    type Error = anyhow::Error;
+
+    fn try_from(rate: Rate) -> Result<Self, Self::Error> {
+        let rate = truncate(rate.rate, crate::rate::Rate::PRECISION);
+
+        crate::rate::Rate::try_from(rate)
+    }
+}
+
 // Only Kraken atm, can be extended to more markets later (and then choosing best rate or whatnot)
-pub async fn get_rate(trading_pair: TradingPair, position: Position) -> anyhow::Result<Rate> {
-    kraken::get_ohlc(trading_pair).await?.to_rate(position)
+pub async fn get_rate(
+    trading_pair: TradingPair,
+    position: Position,
+    ohlc_interval: OhlcInterval,
+) -> anyhow::Result<Rate> {
+    kraken::get_ohlc(trading_pair, ohlc_interval)
+        .await?
+        .to_rate(position)
 }
 
 #[derive(Copy, Clone, Debug, thiserror::Error)]
@@ -140,4 +160,13 @@ mod tests {
 
         assert_eq!(rate.rate, 1.0 / 9806.7);
     }
+
+    #[test]
+    fn given_buy_rate_with_high_precision_then_conversion_truncates() {
+        let rate = ohlc_with_vwap().to_rate(Position::Buy).unwrap();
+
+        let rate = crate::rate::Rate::try_from(rate).unwrap();
+
+        assert_eq!(rate, crate::rate::Rate::new(101_971));
+    }
 }
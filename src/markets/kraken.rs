@@ -5,16 +5,47 @@ use serde::de::Error;
 use serde::Deserialize;
 use std::convert::TryFrom;
 
+/// Interval in minutes used when fetching the ohlc data from Kraken.
+/// The data returned will contain segments according to the interval.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OhlcInterval(u32);
+
+impl OhlcInterval {
+    /// The highest frequency time interval for OHLC data is 1 minute, possible values:
+    /// 1 (default), 5, 15, 30, 60, 240, 1440, 10080, 21600
+    const ALLOWED_MINUTES: [u32; 9] = [1, 5, 15, 30, 60, 240, 1440, 10080, 21600];
+
+    pub fn new(minutes: u32) -> anyhow::Result<OhlcInterval> {
+        if !Self::ALLOWED_MINUTES.contains(&minutes) {
+            anyhow::bail!(
+                "OHLC interval must be one of {:?} minutes",
+                Self::ALLOWED_MINUTES
+            );
+        }
+
+        Ok(OhlcInterval(minutes))
+    }
+
+    pub fn as_minutes(self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for OhlcInterval {
+    fn default() -> Self {
+        OhlcInterval(30)
+    }
+}
+
 /// Fetch OHLC (open-high-low-close) data
 /// More info here: https://www.kraken.com/features/api
-pub async fn get_ohlc(trading_pair: TradingPair) -> anyhow::Result<markets::Ohlc> {
+pub async fn get_ohlc(
+    trading_pair: TradingPair,
+    interval: OhlcInterval,
+) -> anyhow::Result<markets::Ohlc> {
     let trading_pair_code = get_trading_pair_code(trading_pair);
 
-    // Interval used when fetching the ohlc data from Kraken.
-    // The data returned will contain segments according to the interval.
-    // The highest frequency time interval for OHLC data is 1 minute, possible values:
-    // 1 (default), 5, 15, 30, 60, 240, 1440, 10080, 21600
-    let time_interval = 30;
+    let time_interval = interval.as_minutes();
     // By passing in a timestamp far in the futrue we reduce the API to return only the last OHLC value
     let since = 2_147_483_647;
 
@@ -147,4 +178,15 @@ mod tests {
     fn given_ohlc_example_data_deserializes_correctly() {
         serde_json::from_str::<OhlcResponse>(OHLC_EXAMPLE_DATA).unwrap();
     }
+
+    #[test]
+    fn given_unsupported_ohlc_interval_then_error() {
+        assert!(OhlcInterval::new(2).is_err());
+        assert!(OhlcInterval::new(0).is_err());
+    }
+
+    #[test]
+    fn given_supported_ohlc_interval_then_ok() {
+        assert_eq!(OhlcInterval::new(60).unwrap().as_minutes(), 60);
+    }
 }
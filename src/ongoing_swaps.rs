@@ -1,7 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 
-#[derive(Copy, Clone)]
-struct Order {
+#[derive(Copy, Clone, Debug)]
+pub struct Order {
     pub peer: Peer,
 }
 
@@ -11,34 +12,38 @@ impl Order {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash)]
-struct Peer(u32);
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Peer(u32);
 
 impl Peer {
-    fn new(index: u32) -> Peer {
+    pub fn new(index: u32) -> Peer {
         Peer(index)
     }
 }
 
-#[derive(Default)]
-struct OngoingSwaps {
+#[derive(Debug, Default)]
+pub struct OngoingSwaps {
     peers: HashSet<Peer>,
 }
 
 impl OngoingSwaps {
-    fn insert(&mut self, order: Order) -> Result<(), ()> {
+    pub fn insert(&mut self, order: Order) -> Result<(), PeerBusy> {
         if self.peers.contains(&order.peer) {
-            Err(())
+            Err(PeerBusy(order.peer))
         } else {
             self.peers.insert(order.peer);
             Ok(())
         }
     }
-    fn remove(&mut self, order: Order) {
+    pub fn remove(&mut self, order: Order) {
         self.peers.remove(&order.peer);
     }
 }
 
+#[derive(Copy, Clone, Debug, thiserror::Error)]
+#[error("peer {0:?} already has an ongoing swap")]
+pub struct PeerBusy(Peer);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,76 @@
+use bitcoin::{Address, Amount, Denomination};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "nectar", about = "Market maker for atomic swaps")]
+pub struct Options {
+    /// Path to the configuration file
+    #[structopt(long = "config", parse(from_os_str))]
+    pub config_file: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum Command {
+    /// Publish orders and execute swaps
+    Trade,
+    /// Print the bitcoin and ether balances of the wallets
+    Balance,
+    /// Print a new bitcoin address to deposit funds
+    Deposit,
+    /// Send bitcoin from the wallet to an address
+    Withdraw {
+        /// The address to send the bitcoin to
+        address: Address,
+        /// The amount to send, in BTC
+        #[structopt(parse(try_from_str = parse_bitcoin))]
+        amount: Amount,
+    },
+    /// Print the seed as a mnemonic to write down as a backup
+    Backup,
+    /// Restore the seed from a mnemonic, read from the standard input,
+    /// and rescan the chain for the funds of the bitcoin wallet
+    Restore,
+    /// Print the configuration in use, including default values, but
+    /// without bitcoind's RPC password which has to be set by hand
+    DumpConfig,
+}
+
+fn parse_bitcoin(str: &str) -> anyhow::Result<Amount> {
+    let amount = Amount::from_str_in(str, Denomination::Bitcoin)?;
+
+    Ok(amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_withdraw_command() {
+        let options = Options::from_iter_safe(&[
+            "nectar",
+            "withdraw",
+            "bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl",
+            "0.5",
+        ])
+        .unwrap();
+
+        match options.cmd {
+            Command::Withdraw { amount, .. } => assert_eq!(amount, Amount::from_sat(50_000_000)),
+            _ => panic!("expected withdraw command"),
+        }
+    }
+
+    #[test]
+    fn parse_config_file_before_subcommand() {
+        let options =
+            Options::from_iter_safe(&["nectar", "--config", "/tmp/nectar.toml", "dump-config"])
+                .unwrap();
+
+        assert_eq!(options.config_file, Some(PathBuf::from("/tmp/nectar.toml")));
+    }
+}
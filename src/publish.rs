@@ -15,7 +15,16 @@ pub trait BitcoinFees {
     fn bitcoin_fees(&self) -> bitcoin::Amount;
 }
 
-struct DaiBitcoinOrder {
+pub trait DaiLockedFunds {
+    fn dai_locked_funds(&self) -> dai::Amount;
+}
+
+pub trait DaiBalance {
+    fn dai_balance(&self) -> dai::Amount;
+}
+
+#[derive(Debug, Clone)]
+pub struct DaiBitcoinOrder {
     pub buy_amount: dai::Amount,
     pub sell_amount: bitcoin::Amount,
 }
@@ -34,7 +43,7 @@ struct DaiBitcoinOrder {
 ///     selling 10000 DAI with spread_pc of 3% => buy 1.03 BTC
 ///     selling 1000 DAI with spread_pc of 3% => buy 0.103 DAI
 ///
-fn new_dai_bitcoin_order<W, B>(
+pub fn new_dai_bitcoin_order<W, B>(
     bitcoin_wallet: W,
     book: B,
     max_sell_amount: bitcoin::Amount,
@@ -60,6 +69,49 @@ where
     })
 }
 
+#[derive(Debug, Clone)]
+pub struct BitcoinDaiOrder {
+    pub buy_amount: bitcoin::Amount,
+    pub sell_amount: dai::Amount,
+}
+
+/// The counterpart of `new_dai_bitcoin_order`: the maker sells dai
+/// and buys bitcoin.
+///
+/// mid_market_rate is set as 1 DAI => x BTC
+///
+/// Given DAI:BTC and a rate of 1:0.0001
+///     selling 10000 DAI with spread_pc of 3% => buy 1.03 BTC
+///
+/// The Ethereum transaction fees are paid in ether, hence not
+/// deducted from the amount of dai sold.
+pub fn new_bitcoin_dai_order<W, B>(
+    dai_wallet: W,
+    book: B,
+    max_sell_amount: dai::Amount,
+    mid_market_rate: Rate,
+    spread: Spread,
+) -> anyhow::Result<BitcoinDaiOrder>
+where
+    W: DaiBalance,
+    B: DaiLockedFunds,
+{
+    let available = dai_wallet
+        .dai_balance()
+        .checked_sub(book.dai_locked_funds())
+        .ok_or_else(|| anyhow::anyhow!("dai locked in swaps exceeds the dai balance"))?;
+    let sell_amount = min(available, max_sell_amount);
+
+    let rate = spread.apply(mid_market_rate)?;
+
+    let buy_amount = sell_amount.worth_in(rate)?;
+
+    Ok(BitcoinDaiOrder {
+        sell_amount,
+        buy_amount,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,6 +163,28 @@ mod tests {
         }
     }
 
+    #[derive(Clone)]
+    struct DaiWallet {
+        balance: dai::Amount,
+    }
+
+    impl DaiBalance for DaiWallet {
+        fn dai_balance(&self) -> dai::Amount {
+            self.balance.clone()
+        }
+    }
+
+    #[derive(Clone)]
+    struct DaiBook {
+        locked_funds: dai::Amount,
+    }
+
+    impl DaiLockedFunds for DaiBook {
+        fn dai_locked_funds(&self) -> dai::Amount {
+            self.locked_funds.clone()
+        }
+    }
+
     fn btc(btc: f64) -> bitcoin::Amount {
         bitcoin::Amount::from_btc(btc).unwrap()
     }
@@ -202,4 +276,55 @@ mod tests {
         assert_eq!(order.sell_amount, btc(1000.0));
         assert_eq!(order.buy_amount, dai(103.0));
     }
+
+    #[test]
+    fn given_a_dai_balance_and_locked_funds_return_order_selling_available_dai() {
+        let wallet = DaiWallet {
+            balance: dai(10_000.0),
+        };
+        let book = DaiBook {
+            locked_funds: dai(2_000.0),
+        };
+        let rate = Rate::try_from(0.0001).unwrap();
+
+        let order =
+            new_bitcoin_dai_order(wallet, book, dai(100_000.0), rate, Spread::new(0).unwrap())
+                .unwrap();
+
+        assert_eq!(order.sell_amount, dai(8_000.0));
+        assert_eq!(order.buy_amount, btc(0.8));
+    }
+
+    #[test]
+    fn given_more_dai_locked_than_the_balance_then_no_order() {
+        let wallet = DaiWallet {
+            balance: dai(1_000.0),
+        };
+        let book = DaiBook {
+            locked_funds: dai(2_000.0),
+        };
+        let rate = Rate::try_from(0.0001).unwrap();
+
+        let result =
+            new_bitcoin_dai_order(wallet, book, dai(100_000.0), rate, Spread::new(0).unwrap());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn given_a_dai_rate_and_spread_return_order_with_both_amounts() {
+        let wallet = DaiWallet {
+            balance: dai(20_000.0),
+        };
+        let book = DaiBook {
+            locked_funds: dai(0.0),
+        };
+        let rate = Rate::try_from(0.0001).unwrap();
+        let spread = Spread::new(300).unwrap();
+
+        let order = new_bitcoin_dai_order(wallet, book, dai(10_000.0), rate, spread).unwrap();
+
+        assert_eq!(order.sell_amount, dai(10_000.0));
+        assert_eq!(order.buy_amount, btc(1.03));
+    }
 }
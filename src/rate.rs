@@ -83,6 +83,10 @@ impl Spread {
         Ok(Spread(permyriad))
     }
 
+    pub fn as_permyriad(&self) -> u16 {
+        self.0
+    }
+
     pub fn apply(&self, rate: Rate) -> anyhow::Result<Rate> {
         let ten_thousand = BigUint::from(10_000u16);
         let integer = rate.integer() * (ten_thousand.clone() + self.0);
@@ -0,0 +1,24 @@
+use std::fmt;
+
+/// A value which must not end up in logs, such as a private key or a
+/// password. `Debug` only tells that it is there.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_does_not_show_the_value() {
+        let password = Redacted("hunter2");
+
+        assert_eq!(format!("{:?}", password), "<redacted>");
+    }
+}
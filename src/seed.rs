@@ -0,0 +1,380 @@
+use crate::redacted::Redacted;
+use ::bitcoin::hashes::{sha256, Hash, HashEngine};
+use ::bitcoin::secp256k1;
+use ::bitcoin::secp256k1::constants::SECRET_KEY_SIZE;
+use anyhow::Context;
+use rand::prelude::*;
+use ring::{aead, pbkdf2};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    num::NonZeroU32,
+    path::Path,
+};
+
+/// PBKDF2 iterations used to derive the encryption key of new seed
+/// files. Existing files keep the number they were written with.
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Seed files asking for more iterations are rejected rather than
+/// keeping the start-up busy for minutes.
+const MAX_PBKDF2_ITERATIONS: u32 = 10 * PBKDF2_ITERATIONS;
+
+const SALT_LEN: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Seed(Redacted<[u8; SECRET_KEY_SIZE]>);
+
+impl Seed {
+    pub fn new() -> Self {
+        let mut bytes = [0u8; SECRET_KEY_SIZE];
+
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Seed(Redacted(bytes))
+    }
+
+    /// Read the seed from `path`, or generate a new one and write it
+    /// there if the file does not exist yet, e.g. on the first start.
+    ///
+    /// The seed is encrypted with a key derived from `passphrase`.
+    pub fn read_or_generate(path: &Path, passphrase: &str) -> anyhow::Result<Seed> {
+        if path.exists() {
+            return Seed::read(path, passphrase);
+        }
+
+        let seed = Seed::new();
+        seed.write(path, passphrase)?;
+        tracing::info!("generated a new seed in {}", path.display());
+
+        Ok(seed)
+    }
+
+    pub fn read(path: &Path, passphrase: &str) -> anyhow::Result<Seed> {
+        let content = fs::read(path)
+            .with_context(|| format!("failed to read seed file {}", path.display()))?;
+        let file = serde_json::from_slice::<SeedFile>(&content)
+            .with_context(|| format!("seed file {} is corrupted", path.display()))?;
+
+        file.decrypt(passphrase)
+            .with_context(|| format!("failed to decrypt seed file {}", path.display()))
+    }
+
+    /// Write the encrypted seed to `path`, which must not exist yet so
+    /// that a seed holding funds is never overwritten.
+    pub fn write(&self, path: &Path, passphrase: &str) -> anyhow::Result<()> {
+        let file = SeedFile::encrypt(self, passphrase, PBKDF2_ITERATIONS)?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut out = options
+            .open(path)
+            .with_context(|| format!("failed to create seed file {}", path.display()))?;
+        out.write_all(&serde_json::to_vec_pretty(&file)?)?;
+        out.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Write the seed to `path` unless the file already holds it, e.g.
+    /// when restoring a seed for the second time. A file holding
+    /// another seed is left untouched.
+    pub fn persist(&self, path: &Path, passphrase: &str) -> anyhow::Result<()> {
+        if !path.exists() {
+            return self.write(path, passphrase);
+        }
+
+        if &Seed::read(path, passphrase)? != self {
+            anyhow::bail!(
+                "seed file {} holds another seed, move it away first",
+                path.display()
+            )
+        }
+
+        Ok(())
+    }
+
+    /// The seed as a BIP39 mnemonic of 24 words, to be written down as
+    /// a backup.
+    ///
+    /// The mnemonic encodes the seed itself, it is not meant to be
+    /// imported in other wallets as these derive their keys from the
+    /// mnemonic differently.
+    pub fn to_mnemonic(&self) -> anyhow::Result<String> {
+        let mnemonic = bip39::Mnemonic::from_entropy(&self.0 .0)?;
+
+        Ok(mnemonic.to_string())
+    }
+
+    pub fn from_mnemonic(words: &str) -> anyhow::Result<Seed> {
+        let mnemonic = bip39::Mnemonic::parse(words)?;
+        let entropy = mnemonic.to_entropy();
+
+        let mut bytes = [0u8; SECRET_KEY_SIZE];
+        if entropy.len() != bytes.len() {
+            anyhow::bail!(
+                "mnemonic has {} words but a seed takes 24",
+                words.split_whitespace().count()
+            )
+        }
+        bytes.copy_from_slice(&entropy);
+
+        Ok(Seed(Redacted(bytes)))
+    }
+
+    pub fn secret_key(&self) -> anyhow::Result<secp256k1::SecretKey> {
+        Ok(secp256k1::SecretKey::from_slice(&self.0 .0)?)
+    }
+
+    /// Derive an independent seed for another use, such as the
+    /// Ethereum wallet, so that the same key is not used on two
+    /// chains.
+    pub fn derive(&self, tag: &[u8]) -> Seed {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&self.0 .0);
+        engine.input(tag);
+
+        Seed(Redacted(sha256::Hash::from_engine(engine).into_inner()))
+    }
+}
+
+impl Default for Seed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The content of the seed file. Binary values are hex-encoded.
+#[derive(Debug, Deserialize, Serialize)]
+struct SeedFile {
+    /// PBKDF2-HMAC-SHA256 iterations to derive the key
+    iterations: u32,
+    salt: String,
+    /// ChaCha20-Poly1305 nonce
+    nonce: String,
+    /// The encrypted seed followed by the authentication tag
+    ciphertext: String,
+}
+
+impl SeedFile {
+    fn encrypt(seed: &Seed, passphrase: &str, iterations: u32) -> anyhow::Result<Self> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let key = key(passphrase, &salt, iterations)?;
+        let mut ciphertext = seed.0 .0.to_vec();
+        key.seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::empty(),
+            &mut ciphertext,
+        )
+        .map_err(|_| anyhow::anyhow!("failed to encrypt the seed"))?;
+
+        Ok(SeedFile {
+            iterations,
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    fn decrypt(&self, passphrase: &str) -> anyhow::Result<Seed> {
+        if self.iterations > MAX_PBKDF2_ITERATIONS {
+            anyhow::bail!(
+                "seed file asks for {} PBKDF2 iterations, at most {} are supported",
+                self.iterations,
+                MAX_PBKDF2_ITERATIONS
+            )
+        }
+        let salt = hex::decode(&self.salt).context("invalid salt")?;
+        let nonce = aead::Nonce::try_assume_unique_for_key(
+            &hex::decode(&self.nonce).context("invalid nonce")?,
+        )
+        .map_err(|_| anyhow::anyhow!("invalid nonce length"))?;
+        let mut ciphertext = hex::decode(&self.ciphertext).context("invalid ciphertext")?;
+
+        let key = key(passphrase, &salt, self.iterations)?;
+        let plaintext = key
+            .open_in_place(nonce, aead::Aad::empty(), &mut ciphertext)
+            .map_err(|_| WrongPassphrase)?;
+
+        let mut bytes = [0u8; SECRET_KEY_SIZE];
+        if plaintext.len() != bytes.len() {
+            anyhow::bail!("decrypted seed has {} bytes", plaintext.len())
+        }
+        bytes.copy_from_slice(plaintext);
+
+        Ok(Seed(Redacted(bytes)))
+    }
+}
+
+fn key(passphrase: &str, salt: &[u8], iterations: u32) -> anyhow::Result<aead::LessSafeKey> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or_else(|| anyhow::anyhow!("PBKDF2 iterations must not be zero"))?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+
+    let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow::anyhow!("invalid key length"))?;
+
+    Ok(aead::LessSafeKey::new(key))
+}
+
+/// The authentication tag does not tell a wrong passphrase apart
+/// from a tampered file.
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("wrong passphrase or corrupted seed file")]
+pub struct WrongPassphrase;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_random_seed() {
+        let _seed = Seed::new();
+    }
+
+    #[test]
+    fn derived_seeds_depend_on_the_tag() {
+        let seed = Seed::new();
+
+        let ethereum = seed.derive(b"ETHEREUM");
+
+        assert_eq!(ethereum.0 .0, seed.derive(b"ETHEREUM").0 .0);
+        assert_ne!(ethereum.0 .0, seed.derive(b"BITCOIN").0 .0);
+        assert_ne!(ethereum.0 .0, seed.0 .0);
+    }
+
+    #[test]
+    fn seed_is_read_back_with_the_same_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seed.json");
+
+        let generated = Seed::read_or_generate(&path, "correct horse").unwrap();
+        let read = Seed::read_or_generate(&path, "correct horse").unwrap();
+
+        assert_eq!(generated, read);
+    }
+
+    #[test]
+    fn given_wrong_passphrase_then_error() {
+        let file = SeedFile::encrypt(&Seed::new(), "correct horse", 1).unwrap();
+
+        let error = file.decrypt("battery staple").unwrap_err();
+
+        assert!(error.is::<WrongPassphrase>());
+    }
+
+    #[test]
+    fn given_tampered_ciphertext_then_error() {
+        let mut file = SeedFile::encrypt(&Seed::new(), "correct horse", 1).unwrap();
+        let flipped = if file.ciphertext.starts_with('0') {
+            "1"
+        } else {
+            "0"
+        };
+        file.ciphertext.replace_range(..1, flipped);
+
+        assert!(file.decrypt("correct horse").is_err());
+    }
+
+    #[test]
+    fn given_too_many_iterations_then_error() {
+        let mut file = SeedFile::encrypt(&Seed::new(), "correct horse", 1).unwrap();
+        file.iterations = u32::MAX;
+
+        let error = file.decrypt("correct horse").unwrap_err();
+
+        assert!(!error.is::<WrongPassphrase>());
+    }
+
+    #[test]
+    fn given_corrupted_file_then_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seed.json");
+        fs::write(&path, "{\"iterations\":").unwrap();
+
+        assert!(Seed::read_or_generate(&path, "correct horse").is_err());
+    }
+
+    #[test]
+    fn existing_seed_file_is_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seed.json");
+        Seed::new().write(&path, "correct horse").unwrap();
+
+        assert!(Seed::new().write(&path, "correct horse").is_err());
+    }
+
+    #[test]
+    fn restoring_the_same_seed_twice_is_fine() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seed.json");
+        let seed = Seed::new();
+
+        seed.persist(&path, "correct horse").unwrap();
+        seed.persist(&path, "correct horse").unwrap();
+
+        assert!(Seed::new().persist(&path, "correct horse").is_err());
+        assert_eq!(Seed::read(&path, "correct horse").unwrap(), seed);
+    }
+
+    #[test]
+    fn seed_roundtrips_through_mnemonic() {
+        let seed = Seed::new();
+
+        let mnemonic = seed.to_mnemonic().unwrap();
+
+        assert_eq!(mnemonic.split_whitespace().count(), 24);
+        assert_eq!(Seed::from_mnemonic(&mnemonic).unwrap(), seed);
+    }
+
+    #[test]
+    fn mnemonic_of_zero_seed() {
+        // BIP39 test vector for 256 bits of zero entropy
+        let seed = Seed(Redacted([0u8; SECRET_KEY_SIZE]));
+
+        assert_eq!(
+            seed.to_mnemonic().unwrap(),
+            format!("{}art", "abandon ".repeat(23))
+        );
+    }
+
+    #[test]
+    fn given_mnemonic_of_12_words_then_error() {
+        let twelve_words = format!("{}about", "abandon ".repeat(11));
+
+        assert!(Seed::from_mnemonic(&twelve_words).is_err());
+    }
+
+    #[test]
+    fn given_mnemonic_with_wrong_checksum_then_error() {
+        let words = format!("{}abandon", "abandon ".repeat(23));
+
+        assert!(Seed::from_mnemonic(&words).is_err());
+    }
+
+    #[test]
+    fn debug_does_not_show_the_seed() {
+        let seed = Seed(Redacted([0xab; SECRET_KEY_SIZE]));
+
+        assert_eq!(format!("{:?}", seed), "Seed(<redacted>)");
+    }
+}
@@ -1,12 +1,31 @@
 #![allow(clippy::trivially_copy_pass_by_ref)]
 
+use crate::database::{BitcoinTransaction, InFlight, Step};
+use anyhow::Context;
 use chrono::NaiveDateTime;
 use comit::{
     btsieve::{ethereum::ReceiptByHash, BlockByHash, LatestBlock},
     ethereum, Secret, SecretHash, Timestamp,
 };
+use confirmations::{watch_until_confirmed, Search};
 use futures::future::{self, Either};
-use std::{sync::Arc, time::Duration};
+use persisted::EthereumJournal;
+use std::{
+    convert::TryFrom,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+mod confirmations;
+mod expiries;
+mod outcome;
+mod persisted;
+
+pub use confirmations::Confirmations;
+pub use expiries::{Expiries, Expiry, SafetyMargins};
+pub use outcome::{Reason, SwapOutcome, TransactionId};
+pub use persisted::{Direction, Journal, Persisted, Role};
 
 pub mod hbit {
     use bitcoin::{secp256k1::SecretKey, *};
@@ -65,12 +84,13 @@ pub mod hbit {
 
     #[async_trait::async_trait]
     pub trait Refund {
+        /// Returns `None` if the refund was not observed.
         async fn refund<SC>(
             &self,
             params: &Params,
             fund_event: CorrectlyFunded,
             secp: &bitcoin::secp256k1::Secp256k1<SC>,
-        ) -> anyhow::Result<()>
+        ) -> anyhow::Result<Option<Refunded>>
         where
             SC: bitcoin::secp256k1::Signing;
     }
@@ -143,7 +163,12 @@ pub mod herc20 {
 
     #[async_trait::async_trait]
     pub trait Refund {
-        async fn refund(&self, params: &Params, deploy_event: Deployed) -> anyhow::Result<()>;
+        /// Returns `None` if the refund was not observed.
+        async fn refund(
+            &self,
+            params: &Params,
+            deploy_event: Deployed,
+        ) -> anyhow::Result<Option<Refunded>>;
     }
 
     #[derive(Debug, Clone)]
@@ -175,11 +200,12 @@ pub mod herc20 {
 }
 
 #[derive(Clone, Debug)]
-struct WatchOnlyAlice<AC, BC> {
-    alpha_connector: Arc<AC>,
-    beta_connector: Arc<BC>,
-    secret_hash: SecretHash,
-    start_of_swap: NaiveDateTime,
+pub struct WatchOnlyAlice<AC, BC> {
+    pub alpha_connector: Arc<AC>,
+    pub beta_connector: Arc<BC>,
+    pub secret_hash: SecretHash,
+    pub start_of_swap: NaiveDateTime,
+    pub confirmations: Confirmations,
 }
 
 #[async_trait::async_trait]
@@ -192,9 +218,14 @@ where
         + ReceiptByHash,
 {
     async fn fund(&self, params: &hbit::Params) -> anyhow::Result<hbit::CorrectlyFunded> {
-        let event =
-            hbit::watch_for_funded(self.alpha_connector.as_ref(), &params, self.start_of_swap)
-                .await?;
+        let event = watch_until_confirmed(
+            self.alpha_connector.as_ref(),
+            self.confirmations.bitcoin,
+            self.start_of_swap,
+            || hbit::watch_for_funded(self.alpha_connector.as_ref(), &params, self.start_of_swap),
+            |event| event.location.txid,
+        )
+        .await?;
 
         Ok(event)
     }
@@ -242,11 +273,11 @@ where
         _params: &hbit::Params,
         _fund_event: hbit::CorrectlyFunded,
         _secp: &bitcoin::secp256k1::Secp256k1<SC>,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<Option<hbit::Refunded>>
     where
         SC: bitcoin::secp256k1::Signing,
     {
-        Ok(())
+        Ok(None)
     }
 }
 
@@ -262,7 +293,10 @@ pub struct WalletBob<AW, BW, E> {
 impl herc20::Deploy for WalletBob<BitcoinWallet, EthereumWallet, hbit::PrivateDetailsRedeemer> {
     async fn deploy(&self, params: &herc20::Params) -> anyhow::Result<herc20::Deployed> {
         let deploy_action = params.build_deploy_action();
-        let event = self.beta_wallet.deploy(deploy_action).await?;
+        let event = self
+            .beta_wallet
+            .deploy(deploy_action, params.expiry)
+            .await?;
 
         Ok(event)
     }
@@ -276,7 +310,10 @@ impl herc20::Fund for WalletBob<BitcoinWallet, EthereumWallet, hbit::PrivateDeta
         deploy_event: herc20::Deployed,
     ) -> anyhow::Result<herc20::CorrectlyFunded> {
         let fund_action = params.build_fund_action(deploy_event.location)?;
-        let event = self.beta_wallet.fund(fund_action).await?;
+        let event = self
+            .beta_wallet
+            .fund(fund_action, params.asset.clone(), params.expiry)
+            .await?;
 
         Ok(event)
     }
@@ -294,15 +331,21 @@ impl hbit::RedeemAsBob for WalletBob<BitcoinWallet, EthereumWallet, hbit::Privat
     where
         SC: bitcoin::secp256k1::Signing,
     {
-        let redeem_action = params.build_redeem_action(
-            &secp,
-            fund_event.asset,
-            fund_event.location,
-            self.private_protocol_details.clone().transient_redeem_sk,
-            self.private_protocol_details.clone().final_redeem_identity,
-            secret,
-        )?;
-        let event = self.alpha_wallet.redeem(redeem_action).await?;
+        let build_redeem_action = |vbyte_rate| -> anyhow::Result<_> {
+            Ok(params.build_redeem_action(
+                &secp,
+                fund_event.asset,
+                fund_event.location,
+                self.private_protocol_details.clone().transient_redeem_sk,
+                self.private_protocol_details.clone().final_redeem_identity,
+                secret,
+                vbyte_rate,
+            )?)
+        };
+        let event = self
+            .alpha_wallet
+            .redeem(build_redeem_action, params.expiry, secret)
+            .await?;
 
         Ok(event)
     }
@@ -314,7 +357,7 @@ impl herc20::Refund for WalletBob<BitcoinWallet, EthereumWallet, hbit::PrivateDe
         &self,
         params: &herc20::Params,
         deploy_event: herc20::Deployed,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<Option<herc20::Refunded>> {
         loop {
             let ethereum_time = ethereum_latest_time(self.beta_wallet.connector.as_ref()).await?;
 
@@ -326,136 +369,303 @@ impl herc20::Refund for WalletBob<BitcoinWallet, EthereumWallet, hbit::PrivateDe
         }
 
         let refund_action = params.build_refund_action(deploy_event.location)?;
-        let _event = self.beta_wallet.refund(refund_action).await?;
+        let event = self.beta_wallet.refund(refund_action).await?;
 
-        Ok(())
+        Ok(Some(event))
     }
 }
 
 /// Determine whether funding a smart contract is safe.
 ///
-/// Implementations should decide based on blockchain time and
-/// Beta expiry, the shorter of the two expiries.
+/// Implementations should decide based on blockchain time and the
+/// expiries, keeping the safety margin of each chain.
 #[async_trait::async_trait]
 pub trait SafeToFund {
-    async fn is_safe_to_fund(&self, beta_expiry: Timestamp) -> anyhow::Result<bool>;
+    async fn is_safe_to_fund(&self, expiries: Expiries) -> anyhow::Result<bool>;
 }
 
 /// Determine whether redeeming a smart contract is safe.
 ///
 /// Implementations should decide based on blockchain time and
-/// expiries.
+/// expiries, keeping the safety margin of each chain.
 #[async_trait::async_trait]
 pub trait SafeToRedeem {
-    async fn is_safe_to_redeem(&self, beta_expiry: Timestamp) -> anyhow::Result<bool>;
+    async fn is_safe_to_redeem(&self, expiries: Expiries) -> anyhow::Result<bool>;
 }
 
 #[async_trait::async_trait]
 impl<AC, BC> SafeToFund for WatchOnlyAlice<AC, BC>
 where
-    BC: LatestBlock<Block = ethereum::Block>,
+    BC: LedgerTime + Send + Sync,
     AC: Send + Sync,
 {
-    async fn is_safe_to_fund(&self, beta_expiry: Timestamp) -> anyhow::Result<bool> {
-        let ethereum_time = ethereum_latest_time(self.beta_connector.as_ref()).await?;
-        // TODO: Apply a buffer depending on the blocktime and how
-        // safe we want to be
+    async fn is_safe_to_fund(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        let beta_ledger_time = self.beta_connector.ledger_time().await?;
 
-        Ok(beta_expiry > ethereum_time)
+        Ok(expiries.beta.is_far_from(beta_ledger_time))
     }
 }
 
 #[async_trait::async_trait]
 impl<AW, BW, E> SafeToFund for WalletBob<AW, BW, E>
 where
-    AW: Send + Sync,
-    BW: LatestBlock<Block = ethereum::Block>,
+    AW: LedgerTime + Send + Sync,
+    BW: LedgerTime + Send + Sync,
     E: Send + Sync,
 {
-    async fn is_safe_to_fund(&self, beta_expiry: Timestamp) -> anyhow::Result<bool> {
-        let ethereum_time = ethereum_latest_time(&self.beta_wallet).await?;
-        // TODO: Apply a buffer depending on the blocktime and how
-        // safe we want to be.
-
-        Ok(beta_expiry > ethereum_time)
+    /// Bob must also be able to redeem the alpha HTLC once Alice
+    /// reveals the secret, before she can refund it.
+    async fn is_safe_to_fund(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        let alpha_ledger_time = self.alpha_wallet.ledger_time().await?;
+        let beta_ledger_time = self.beta_wallet.ledger_time().await?;
+
+        Ok(expiries.beta.is_far_from(beta_ledger_time)
+            && expiries.alpha.is_far_from(alpha_ledger_time)
+            && expiries.alpha_outlasts_beta())
     }
 }
 
 #[async_trait::async_trait]
 impl<AC, BC> SafeToRedeem for WatchOnlyAlice<AC, BC>
 where
-    BC: LatestBlock<Block = ethereum::Block>,
+    BC: LedgerTime + Send + Sync,
     AC: Send + Sync,
 {
-    async fn is_safe_to_redeem(&self, beta_expiry: Timestamp) -> anyhow::Result<bool> {
-        let ethereum_time = ethereum_latest_time(self.beta_connector.as_ref()).await?;
-        // TODO: Apply a buffer depending on the blocktime and how
-        // safe we want to be.
+    async fn is_safe_to_redeem(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        let beta_ledger_time = self.beta_connector.ledger_time().await?;
 
-        Ok(beta_expiry > ethereum_time)
+        Ok(expiries.beta.is_far_from(beta_ledger_time))
     }
 }
 
-async fn hbit_herc20<A, B, SC>(
+pub async fn hbit_herc20<A, B, SC>(
     alice: A,
     bob: B,
     hbit_params: hbit::Params,
     herc20_params: herc20::Params,
+    safety_margins: SafetyMargins,
     secp: &bitcoin::secp256k1::Secp256k1<SC>,
-) -> anyhow::Result<()>
+) -> anyhow::Result<SwapOutcome>
 where
     A: hbit::Fund + herc20::RedeemAsAlice + hbit::Refund + SafeToFund + SafeToRedeem,
     B: herc20::Deploy + herc20::Fund + hbit::RedeemAsBob + herc20::Refund + SafeToFund,
     SC: bitcoin::secp256k1::Signing,
 {
-    if !alice.is_safe_to_fund(herc20_params.expiry).await? {
-        return Ok(());
+    let expiries = Expiries {
+        alpha: Expiry {
+            timestamp: hbit_params.expiry,
+            margin: safety_margins.bitcoin,
+        },
+        beta: Expiry {
+            timestamp: herc20_params.expiry,
+            margin: safety_margins.ethereum,
+        },
+    };
+
+    if !alice.is_safe_to_fund(expiries).await? {
+        return Ok(SwapOutcome::AbortedBeforeFunding {
+            reason: Reason::AliceNotSafeToFund,
+        });
     }
 
     let hbit_funded = alice.fund(&hbit_params).await?;
 
-    if !bob.is_safe_to_fund(herc20_params.expiry).await? {
-        alice.refund(&hbit_params, hbit_funded, secp).await?;
+    if !bob.is_safe_to_fund(expiries).await? {
+        let hbit_refunded = alice.refund(&hbit_params, hbit_funded, secp).await?;
 
-        return Ok(());
+        return Ok(SwapOutcome::Refunded {
+            reason: Reason::BobNotSafeToFund,
+            alpha_refund: hbit_refunded.map(TransactionId::from),
+            beta_refund: None,
+            beta_redeem: None,
+        });
     }
 
     let herc20_deployed = bob.deploy(&herc20_params).await?;
 
-    if !bob.is_safe_to_fund(herc20_params.expiry).await? {
-        alice.refund(&hbit_params, hbit_funded, secp).await?;
+    if !bob.is_safe_to_fund(expiries).await? {
+        let hbit_refunded = alice.refund(&hbit_params, hbit_funded, secp).await?;
 
-        return Ok(());
+        return Ok(SwapOutcome::Refunded {
+            reason: Reason::BobNotSafeToFund,
+            alpha_refund: hbit_refunded.map(TransactionId::from),
+            beta_refund: None,
+            beta_redeem: None,
+        });
     }
 
     let _herc20_funded = bob.fund(&herc20_params, herc20_deployed.clone()).await?;
 
-    if !alice.is_safe_to_redeem(herc20_params.expiry).await? {
-        alice.refund(&hbit_params, hbit_funded, secp).await?;
-        bob.refund(&herc20_params, herc20_deployed.clone()).await?;
+    if !alice.is_safe_to_redeem(expiries).await? {
+        let hbit_refunded = alice.refund(&hbit_params, hbit_funded, secp).await?;
+        let herc20_refunded = bob.refund(&herc20_params, herc20_deployed.clone()).await?;
 
-        return Ok(());
+        return Ok(SwapOutcome::Refunded {
+            reason: Reason::AliceNotSafeToRedeem,
+            alpha_refund: hbit_refunded.map(TransactionId::from),
+            beta_refund: herc20_refunded.map(TransactionId::from),
+            beta_redeem: None,
+        });
     }
 
     let herc20_redeemed = alice.redeem(&herc20_params, herc20_deployed).await?;
+    let secret = herc20_redeemed.secret;
+    let beta_redeem = TransactionId::from(herc20_redeemed);
 
-    let hbit_redeem = bob.redeem(&hbit_params, hbit_funded, herc20_redeemed.secret, &secp);
+    let hbit_redeem = bob.redeem(&hbit_params, hbit_funded, secret, &secp);
     let hbit_refund = alice.refund(&hbit_params, hbit_funded, secp);
 
     // It's always safe for Bob to redeem, he just has to do it before
     // Alice refunds
-    match future::try_select(hbit_redeem, hbit_refund).await {
-        Ok(Either::Left((_hbit_redeemed, _))) => Ok(()),
-        Ok(Either::Right((_hbit_refunded, _))) => Ok(()),
-        Err(either) => {
-            let (error, _other_future) = either.factor_first();
-            Err(error)
+    match redeem_or_refund(hbit_redeem, hbit_refund).await? {
+        Either::Left(hbit_redeemed) => Ok(SwapOutcome::Redeemed {
+            alpha_redeem: TransactionId::from(hbit_redeemed),
+            beta_redeem,
+        }),
+        Either::Right(hbit_refunded) => Ok(SwapOutcome::Refunded {
+            reason: Reason::BobDidNotRedeem,
+            alpha_refund: hbit_refunded.map(TransactionId::from),
+            beta_refund: None,
+            beta_redeem: Some(beta_redeem),
+        }),
+    }
+}
+
+/// Execute a swap where Alice sells ERC20 tokens for Bob's bitcoin.
+///
+/// This mirrors `hbit_herc20`: Alice deploys and funds the herc20
+/// HTLC first, and Bob funds the hbit HTLC once he has seen it.
+pub async fn herc20_hbit<A, B, SC>(
+    alice: A,
+    bob: B,
+    herc20_params: herc20::Params,
+    hbit_params: hbit::Params,
+    safety_margins: SafetyMargins,
+    secp: &bitcoin::secp256k1::Secp256k1<SC>,
+) -> anyhow::Result<SwapOutcome>
+where
+    A: herc20::Deploy
+        + herc20::Fund
+        + hbit::RedeemAsAlice
+        + herc20::Refund
+        + SafeToFund
+        + SafeToRedeem,
+    B: hbit::Fund + herc20::RedeemAsBob + hbit::Refund + SafeToFund,
+    SC: bitcoin::secp256k1::Signing,
+{
+    let expiries = Expiries {
+        alpha: Expiry {
+            timestamp: herc20_params.expiry,
+            margin: safety_margins.ethereum,
+        },
+        beta: Expiry {
+            timestamp: hbit_params.expiry,
+            margin: safety_margins.bitcoin,
+        },
+    };
+
+    if !alice.is_safe_to_fund(expiries).await? {
+        return Ok(SwapOutcome::AbortedBeforeFunding {
+            reason: Reason::AliceNotSafeToFund,
+        });
+    }
+
+    let herc20_deployed = alice.deploy(&herc20_params).await?;
+    let _herc20_funded = alice.fund(&herc20_params, herc20_deployed.clone()).await?;
+
+    if !bob.is_safe_to_fund(expiries).await? {
+        let herc20_refunded = alice
+            .refund(&herc20_params, herc20_deployed.clone())
+            .await?;
+
+        return Ok(SwapOutcome::Refunded {
+            reason: Reason::BobNotSafeToFund,
+            alpha_refund: herc20_refunded.map(TransactionId::from),
+            beta_refund: None,
+            beta_redeem: None,
+        });
+    }
+
+    let hbit_funded = bob.fund(&hbit_params).await?;
+
+    if !alice.is_safe_to_redeem(expiries).await? {
+        let herc20_refunded = alice
+            .refund(&herc20_params, herc20_deployed.clone())
+            .await?;
+        let hbit_refunded = bob.refund(&hbit_params, hbit_funded, secp).await?;
+
+        return Ok(SwapOutcome::Refunded {
+            reason: Reason::AliceNotSafeToRedeem,
+            alpha_refund: herc20_refunded.map(TransactionId::from),
+            beta_refund: hbit_refunded.map(TransactionId::from),
+            beta_redeem: None,
+        });
+    }
+
+    let hbit_redeemed = alice.redeem(&hbit_params, hbit_funded, secp).await?;
+    let secret = hbit_redeemed.secret;
+    let beta_redeem = TransactionId::from(hbit_redeemed);
+
+    let herc20_redeem = bob.redeem(&herc20_params, herc20_deployed.clone(), secret);
+    let herc20_refund = alice.refund(&herc20_params, herc20_deployed);
+
+    // It's always safe for Bob to redeem, he just has to do it before
+    // Alice refunds
+    match redeem_or_refund(herc20_redeem, herc20_refund).await? {
+        Either::Left(herc20_redeemed) => Ok(SwapOutcome::Redeemed {
+            alpha_redeem: TransactionId::from(herc20_redeemed),
+            beta_redeem,
+        }),
+        Either::Right(herc20_refunded) => Ok(SwapOutcome::Refunded {
+            reason: Reason::BobDidNotRedeem,
+            alpha_refund: herc20_refunded.map(TransactionId::from),
+            beta_refund: None,
+            beta_redeem: Some(beta_redeem),
+        }),
+    }
+}
+
+/// Wait for the alpha HTLC to be redeemed or refunded, whichever
+/// happens first.
+///
+/// The swap only fails if both fail: once Bob redeemed, Alice's
+/// refund is rejected because the HTLC is spent, and the other way
+/// around.
+async fn redeem_or_refund<R, F, T, U>(redeem: R, refund: F) -> anyhow::Result<Either<T, U>>
+where
+    R: Future<Output = anyhow::Result<T>> + Unpin,
+    F: Future<Output = anyhow::Result<U>> + Unpin,
+{
+    match future::try_select(redeem, refund).await {
+        Ok(Either::Left((redeemed, _))) => Ok(Either::Left(redeemed)),
+        Ok(Either::Right((refunded, _))) => Ok(Either::Right(refunded)),
+        Err(Either::Left((redeem_error, refund))) => {
+            tracing::warn!(
+                "failed to redeem, waiting for the refund: {:#}",
+                redeem_error
+            );
+            let refunded = refund
+                .await
+                .with_context(|| format!("failed to redeem: {:#}", redeem_error))?;
+
+            Ok(Either::Right(refunded))
+        }
+        Err(Either::Right((refund_error, redeem))) => {
+            tracing::warn!(
+                "failed to refund, waiting for the redeem: {:#}",
+                refund_error
+            );
+            let redeemed = redeem
+                .await
+                .with_context(|| format!("failed to refund: {:#}", refund_error))?;
+
+            Ok(Either::Left(redeemed))
         }
     }
 }
 
 #[derive(Clone, Copy, Debug)]
-struct WalletAlice<AW, BW, E> {
+pub struct WalletAlice<AW, BW, E> {
     pub alpha_wallet: AW,
     pub beta_wallet: BW,
     pub private_protocol_details: E,
@@ -482,7 +692,10 @@ impl herc20::RedeemAsAlice
         deploy_event: herc20::Deployed,
     ) -> anyhow::Result<herc20::Redeemed> {
         let redeem_action = params.build_redeem_action(deploy_event.location, self.secret)?;
-        let event = self.beta_wallet.redeem(redeem_action).await?;
+        let event = self
+            .beta_wallet
+            .redeem(redeem_action, self.secret, params.expiry)
+            .await?;
 
         Ok(event)
     }
@@ -495,7 +708,7 @@ impl hbit::Refund for WalletAlice<BitcoinWallet, EthereumWallet, hbit::PrivateDe
         params: &hbit::Params,
         fund_event: hbit::CorrectlyFunded,
         secp: &bitcoin::secp256k1::Secp256k1<SC>,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<Option<hbit::Refunded>>
     where
         SC: bitcoin::secp256k1::Signing,
     {
@@ -510,25 +723,29 @@ impl hbit::Refund for WalletAlice<BitcoinWallet, EthereumWallet, hbit::PrivateDe
             tokio::time::delay_for(Duration::from_secs(1)).await;
         }
 
-        let refund_action = params.build_refund_action(
-            secp,
-            fund_event.asset,
-            fund_event.location,
-            self.private_protocol_details.transient_refund_sk,
-            self.private_protocol_details.final_refund_identity.clone(),
-        )?;
-        let _event = self.alpha_wallet.refund(refund_action).await?;
+        let build_refund_action = |vbyte_rate| -> anyhow::Result<_> {
+            Ok(params.build_refund_action(
+                secp,
+                fund_event.asset,
+                fund_event.location,
+                self.private_protocol_details.transient_refund_sk,
+                self.private_protocol_details.final_refund_identity.clone(),
+                vbyte_rate,
+            )?)
+        };
+        let event = self.alpha_wallet.refund(build_refund_action).await?;
 
-        Ok(())
+        Ok(Some(event))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct WatchOnlyBob<AC, BC> {
-    alpha_connector: Arc<AC>,
-    beta_connector: Arc<BC>,
-    secret_hash: SecretHash,
-    start_of_swap: NaiveDateTime,
+    pub alpha_connector: Arc<AC>,
+    pub beta_connector: Arc<BC>,
+    pub secret_hash: SecretHash,
+    pub start_of_swap: NaiveDateTime,
+    pub confirmations: Confirmations,
 }
 
 #[async_trait::async_trait]
@@ -541,10 +758,18 @@ where
         + ReceiptByHash,
 {
     async fn deploy(&self, params: &herc20::Params) -> anyhow::Result<herc20::Deployed> {
-        let event = herc20::watch_for_deployed(
+        let event = watch_until_confirmed(
             self.beta_connector.as_ref(),
-            params.clone(),
+            self.confirmations.ethereum,
             self.start_of_swap,
+            || {
+                herc20::watch_for_deployed(
+                    self.beta_connector.as_ref(),
+                    params.clone(),
+                    self.start_of_swap,
+                )
+            },
+            |event| event.transaction.hash,
         )
         .await?;
 
@@ -566,11 +791,19 @@ where
         params: &herc20::Params,
         deploy_event: herc20::Deployed,
     ) -> anyhow::Result<herc20::CorrectlyFunded> {
-        let event = herc20::watch_for_funded(
+        let event = watch_until_confirmed(
             self.beta_connector.as_ref(),
-            params.clone(),
+            self.confirmations.ethereum,
             self.start_of_swap,
-            deploy_event,
+            || {
+                herc20::watch_for_funded(
+                    self.beta_connector.as_ref(),
+                    params.clone(),
+                    self.start_of_swap,
+                    deploy_event.clone(),
+                )
+            },
+            |event| event.transaction.hash,
         )
         .await?;
 
@@ -625,8 +858,8 @@ where
         &self,
         _params: &comit::herc20::Params,
         _deploy_event: comit::herc20::Deployed,
-    ) -> anyhow::Result<()> {
-        Ok(())
+    ) -> anyhow::Result<Option<herc20::Refunded>> {
+        Ok(None)
     }
 }
 
@@ -634,30 +867,31 @@ where
 impl<AW, BW, E> SafeToFund for WalletAlice<AW, BW, E>
 where
     AW: Send + Sync,
-    BW: LatestBlock<Block = ethereum::Block>,
+    BW: LedgerTime + Send + Sync,
     E: Send + Sync,
 {
-    async fn is_safe_to_fund(&self, beta_expiry: Timestamp) -> anyhow::Result<bool> {
-        let ethereum_time = ethereum_latest_time(&self.beta_wallet).await?;
-        // TODO: Apply a buffer depending on the blocktime and how
-        // safe we want to be
+    async fn is_safe_to_fund(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        let beta_ledger_time = self.beta_wallet.ledger_time().await?;
 
-        Ok(beta_expiry > ethereum_time)
+        Ok(expiries.beta.is_far_from(beta_ledger_time))
     }
 }
 
 #[async_trait::async_trait]
 impl<AC, BC> SafeToFund for WatchOnlyBob<AC, BC>
 where
-    AC: Send + Sync,
-    BC: LatestBlock<Block = ethereum::Block>,
+    AC: LedgerTime + Send + Sync,
+    BC: LedgerTime + Send + Sync,
 {
-    async fn is_safe_to_fund(&self, beta_expiry: Timestamp) -> anyhow::Result<bool> {
-        let ethereum_time = ethereum_latest_time(self.beta_connector.as_ref()).await?;
-        // TODO: Apply a buffer depending on the blocktime and how
-        // safe we want to be.
-
-        Ok(beta_expiry > ethereum_time)
+    /// Bob must also be able to redeem the alpha HTLC once Alice
+    /// reveals the secret, before she can refund it.
+    async fn is_safe_to_fund(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        let alpha_ledger_time = self.alpha_connector.ledger_time().await?;
+        let beta_ledger_time = self.beta_connector.ledger_time().await?;
+
+        Ok(expiries.beta.is_far_from(beta_ledger_time)
+            && expiries.alpha.is_far_from(alpha_ledger_time)
+            && expiries.alpha_outlasts_beta())
     }
 }
 
@@ -665,175 +899,1000 @@ where
 impl<AW, BW, E> SafeToRedeem for WalletAlice<AW, BW, E>
 where
     AW: Send + Sync,
-    BW: LatestBlock<Block = ethereum::Block>,
+    BW: LedgerTime + Send + Sync,
     E: Send + Sync,
 {
-    async fn is_safe_to_redeem(&self, beta_expiry: Timestamp) -> anyhow::Result<bool> {
-        let ethereum_time = ethereum_latest_time(&self.beta_wallet).await?;
-        // TODO: Apply a buffer depending on the blocktime and how
-        // safe we want to be
+    async fn is_safe_to_redeem(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        let beta_ledger_time = self.beta_wallet.ledger_time().await?;
 
-        Ok(beta_expiry > ethereum_time)
+        Ok(expiries.beta.is_far_from(beta_ledger_time))
     }
 }
 
-#[derive(Debug)]
-pub struct BitcoinWallet {
-    connector: Arc<comit::btsieve::bitcoin::BitcoindConnector>,
-}
+#[async_trait::async_trait]
+impl<AC, BC> herc20::Deploy for WatchOnlyAlice<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    async fn deploy(&self, params: &herc20::Params) -> anyhow::Result<herc20::Deployed> {
+        let event = watch_until_confirmed(
+            self.alpha_connector.as_ref(),
+            self.confirmations.ethereum,
+            self.start_of_swap,
+            || {
+                herc20::watch_for_deployed(
+                    self.alpha_connector.as_ref(),
+                    params.clone(),
+                    self.start_of_swap,
+                )
+            },
+            |event| event.transaction.hash,
+        )
+        .await?;
 
-impl BitcoinWallet {
-    pub async fn fund(
-        &self,
-        _action: hbit::SendToAddress,
-    ) -> anyhow::Result<hbit::CorrectlyFunded> {
-        todo!()
+        Ok(event)
     }
+}
 
-    pub async fn redeem(
+#[async_trait::async_trait]
+impl<AC, BC> herc20::Fund for WatchOnlyAlice<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    async fn fund(
         &self,
-        _action: hbit::BroadcastSignedTransaction,
-    ) -> anyhow::Result<hbit::Redeemed> {
-        todo!()
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<herc20::CorrectlyFunded> {
+        let event = watch_until_confirmed(
+            self.alpha_connector.as_ref(),
+            self.confirmations.ethereum,
+            self.start_of_swap,
+            || {
+                herc20::watch_for_funded(
+                    self.alpha_connector.as_ref(),
+                    params.clone(),
+                    self.start_of_swap,
+                    deploy_event.clone(),
+                )
+            },
+            |event| event.transaction.hash,
+        )
+        .await?;
+
+        Ok(event)
     }
+}
 
-    pub async fn refund(
+#[async_trait::async_trait]
+impl<AC, BC> hbit::RedeemAsAlice for WatchOnlyAlice<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    async fn redeem<SC>(
         &self,
-        _action: hbit::BroadcastSignedTransaction,
-    ) -> anyhow::Result<hbit::Refunded> {
-        todo!()
+        params: &hbit::Params,
+        fund_event: hbit::CorrectlyFunded,
+        _secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<hbit::Redeemed>
+    where
+        SC: bitcoin::secp256k1::Signing,
+    {
+        let event = hbit::watch_for_redeemed(
+            self.beta_connector.as_ref(),
+            &params,
+            fund_event.location,
+            self.start_of_swap,
+        )
+        .await?;
+
+        Ok(event)
     }
 }
 
 #[async_trait::async_trait]
-impl LatestBlock for BitcoinWallet {
-    type Block = bitcoin::Block;
-    async fn latest_block(&self) -> anyhow::Result<Self::Block> {
-        self.connector.as_ref().latest_block().await
+impl<AC, BC> herc20::Refund for WatchOnlyAlice<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    /// Refund for a watch-only actor is a no-op. The protocol is
+    /// finished, so there is verifying that the other party has
+    /// refunded
+    async fn refund(
+        &self,
+        _params: &herc20::Params,
+        _deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<Option<herc20::Refunded>> {
+        Ok(None)
     }
 }
 
-#[derive(Debug)]
-pub struct EthereumWallet {
-    connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+#[async_trait::async_trait]
+impl<AC, BC> hbit::Fund for WatchOnlyBob<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    async fn fund(&self, params: &hbit::Params) -> anyhow::Result<hbit::CorrectlyFunded> {
+        let event = watch_until_confirmed(
+            self.beta_connector.as_ref(),
+            self.confirmations.bitcoin,
+            self.start_of_swap,
+            || hbit::watch_for_funded(self.beta_connector.as_ref(), &params, self.start_of_swap),
+            |event| event.location.txid,
+        )
+        .await?;
+
+        Ok(event)
+    }
 }
 
-impl EthereumWallet {
-    pub async fn deploy(
+#[async_trait::async_trait]
+impl<AC, BC> herc20::RedeemAsBob for WatchOnlyBob<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    async fn redeem(
         &self,
-        _action: herc20::DeployContract,
-    ) -> anyhow::Result<herc20::Deployed> {
-        todo!()
+        _params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+        _secret: Secret,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        let event = herc20::watch_for_redeemed(
+            self.alpha_connector.as_ref(),
+            self.start_of_swap,
+            deploy_event,
+        )
+        .await?;
+
+        Ok(event)
     }
+}
 
-    pub async fn fund(
+#[async_trait::async_trait]
+impl<AC, BC> hbit::Refund for WatchOnlyBob<AC, BC>
+where
+    AC: LatestBlock<Block = ethereum::Block>
+        + BlockByHash<Block = ethereum::Block, BlockHash = ethereum::Hash>
+        + ReceiptByHash,
+    BC: LatestBlock<Block = bitcoin::Block>
+        + BlockByHash<Block = bitcoin::Block, BlockHash = bitcoin::BlockHash>,
+{
+    /// Refund for a watch-only actor is a no-op. The protocol is
+    /// finished, so there is verifying that the other party has
+    /// refunded
+    async fn refund<SC>(
         &self,
-        _action: herc20::CallContract,
-    ) -> anyhow::Result<herc20::CorrectlyFunded> {
-        todo!()
+        _params: &hbit::Params,
+        _fund_event: hbit::CorrectlyFunded,
+        _secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<Option<hbit::Refunded>>
+    where
+        SC: bitcoin::secp256k1::Signing,
+    {
+        Ok(None)
     }
+}
 
-    pub async fn redeem(&self, _action: herc20::CallContract) -> anyhow::Result<herc20::Redeemed> {
-        todo!()
-    }
+#[async_trait::async_trait]
+impl herc20::Deploy for WalletAlice<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsRedeemer> {
+    async fn deploy(&self, params: &herc20::Params) -> anyhow::Result<herc20::Deployed> {
+        let deploy_action = params.build_deploy_action();
+        let event = self
+            .alpha_wallet
+            .deploy(deploy_action, params.expiry)
+            .await?;
 
-    pub async fn refund(&self, _action: herc20::CallContract) -> anyhow::Result<herc20::Refunded> {
-        todo!()
+        Ok(event)
     }
 }
 
 #[async_trait::async_trait]
-impl LatestBlock for EthereumWallet {
-    type Block = ethereum::Block;
-    async fn latest_block(&self) -> anyhow::Result<Self::Block> {
-        self.connector.latest_block().await
+impl herc20::Fund for WalletAlice<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsRedeemer> {
+    async fn fund(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<herc20::CorrectlyFunded> {
+        let fund_action = params.build_fund_action(deploy_event.location)?;
+        let event = self
+            .alpha_wallet
+            .fund(fund_action, params.asset.clone(), params.expiry)
+            .await?;
+
+        Ok(event)
     }
 }
 
-async fn ethereum_latest_time<C>(connector: &C) -> anyhow::Result<Timestamp>
-where
-    C: LatestBlock<Block = ethereum::Block>,
+#[async_trait::async_trait]
+impl hbit::RedeemAsAlice
+    for WalletAlice<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsRedeemer>
 {
-    let timestamp = connector.latest_block().await?.timestamp.into();
+    async fn redeem<SC>(
+        &self,
+        params: &hbit::Params,
+        fund_event: hbit::CorrectlyFunded,
+        secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<hbit::Redeemed>
+    where
+        SC: bitcoin::secp256k1::Signing,
+    {
+        let build_redeem_action = |vbyte_rate| -> anyhow::Result<_> {
+            Ok(params.build_redeem_action(
+                &secp,
+                fund_event.asset,
+                fund_event.location,
+                self.private_protocol_details.clone().transient_redeem_sk,
+                self.private_protocol_details.clone().final_redeem_identity,
+                self.secret,
+                vbyte_rate,
+            )?)
+        };
+        let event = self
+            .beta_wallet
+            .redeem(build_redeem_action, params.expiry, self.secret)
+            .await?;
 
-    Ok(timestamp)
+        Ok(event)
+    }
 }
 
-#[cfg(all(test, feature = "test-docker"))]
-mod tests {
-    use super::*;
-    use crate::test_harness::{BitcoinBlockchain, EthereumBlockchain};
-    use bitcoin::{secp256k1, Network};
-    use chrono::Utc;
-    use comit::{
-        asset::{
-            self,
-            ethereum::{Erc20Quantity, FromWei},
-        },
-        btsieve::{bitcoin::BitcoindConnector, ethereum::Web3Connector},
-        ethereum, identity, Secret, SecretHash, Timestamp,
-    };
-    use std::{str::FromStr, sync::Arc};
-    use testcontainers::clients;
-
-    fn hbit_params<C>(
-        secret_hash: SecretHash,
-        secp: &bitcoin::secp256k1::Secp256k1<C>,
-    ) -> (
-        hbit::Params,
-        hbit::PrivateDetailsFunder,
-        hbit::PrivateDetailsRedeemer,
-    )
+#[async_trait::async_trait]
+impl herc20::Refund for WalletAlice<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsRedeemer> {
+    async fn refund(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<Option<herc20::Refunded>> {
+        loop {
+            let ethereum_time = ethereum_latest_time(self.alpha_wallet.connector.as_ref()).await?;
+
+            if ethereum_time >= params.expiry {
+                break;
+            }
+
+            tokio::time::delay_for(Duration::from_secs(1)).await;
+        }
+
+        let refund_action = params.build_refund_action(deploy_event.location)?;
+        let event = self.alpha_wallet.refund(refund_action).await?;
+
+        Ok(Some(event))
+    }
+}
+
+#[async_trait::async_trait]
+impl hbit::Fund for WalletBob<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsFunder> {
+    async fn fund(&self, params: &hbit::Params) -> anyhow::Result<hbit::CorrectlyFunded> {
+        let fund_action = params.build_fund_action();
+        let event = self.beta_wallet.fund(fund_action).await?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl herc20::RedeemAsBob for WalletBob<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsFunder> {
+    async fn redeem(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+        secret: Secret,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        let redeem_action = params.build_redeem_action(deploy_event.location, secret)?;
+        let event = self
+            .alpha_wallet
+            .redeem(redeem_action, secret, params.expiry)
+            .await?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl hbit::Refund for WalletBob<EthereumWallet, BitcoinWallet, hbit::PrivateDetailsFunder> {
+    async fn refund<SC>(
+        &self,
+        params: &hbit::Params,
+        fund_event: hbit::CorrectlyFunded,
+        secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<Option<hbit::Refunded>>
     where
-        C: secp256k1::Signing,
+        SC: bitcoin::secp256k1::Signing,
     {
-        let asset = asset::Bitcoin::from_sat(100_000_000);
-        let network = Network::Regtest;
-        let expiry = Timestamp::from(0);
+        loop {
+            let bitcoin_time =
+                comit::bitcoin::median_time_past(self.beta_wallet.connector.as_ref()).await?;
 
-        let (private_details_funder, transient_refund_pk) = {
-            let transient_refund_sk = secp256k1::SecretKey::from_str(
-                "01010101010101010001020304050607ffff0000ffff00006363636363636363",
-            )
-            .unwrap();
-            // FIXME: Get final_refund_identity from funder wallet
-            let final_refund_identity =
-                bitcoin::Address::from_str("bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl").unwrap();
-            let private_details_funder = hbit::PrivateDetailsFunder {
-                transient_refund_sk,
-                final_refund_identity,
-            };
+            if bitcoin_time >= params.expiry {
+                break;
+            }
+
+            tokio::time::delay_for(Duration::from_secs(1)).await;
+        }
+
+        let build_refund_action = |vbyte_rate| -> anyhow::Result<_> {
+            Ok(params.build_refund_action(
+                secp,
+                fund_event.asset,
+                fund_event.location,
+                self.private_protocol_details.transient_refund_sk,
+                self.private_protocol_details.final_refund_identity.clone(),
+                vbyte_rate,
+            )?)
+        };
+        let event = self.beta_wallet.refund(build_refund_action).await?;
+
+        Ok(Some(event))
+    }
+}
+
+/// How long an HTLC spend can wait to be mined before its fee is
+/// raised, about a block.
+const BUMP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug)]
+pub struct BitcoinWallet {
+    connector: Arc<crate::bitcoind::Connector>,
+    wallet: crate::bitcoin_wallet::Wallet,
+    fee_estimator: crate::bitcoin::fee::Estimator,
+    journal: Option<Journal>,
+}
+
+impl BitcoinWallet {
+    pub fn new(
+        connector: Arc<crate::bitcoind::Connector>,
+        wallet: crate::bitcoin_wallet::Wallet,
+        fee_estimator: crate::bitcoin::fee::Estimator,
+    ) -> Self {
+        Self {
+            connector,
+            wallet,
+            fee_estimator,
+            journal: None,
+        }
+    }
+
+    /// Record the transactions of the HTLC in `journal` before
+    /// broadcasting them, so that a resumed swap does not fund the HTLC
+    /// twice or spend it with a conflicting transaction.
+    pub fn with_journal(self, journal: Journal) -> Self {
+        Self {
+            journal: Some(journal),
+            ..self
+        }
+    }
+
+    /// The fee rate, per vbyte, of redeem and refund transactions.
+    pub async fn spend_vbyte_rate(&self) -> anyhow::Result<bitcoin::Amount> {
+        self.fee_estimator.spend_vbyte_rate().await
+    }
+
+    pub async fn fund(&self, action: hbit::SendToAddress) -> anyhow::Result<hbit::CorrectlyFunded> {
+        let transaction = match self.in_flight(Step::HbitFunded)? {
+            Some(transaction) => transaction,
+            None => {
+                let amount = bitcoin::Amount::from_sat(action.amount.as_sat());
+                let transaction = self.wallet.build_to_address(&action.to, amount).await?;
+                self.record_in_flight(Step::HbitFunded, &transaction)?;
+                transaction
+            }
+        };
+        let txid = self.wallet.rebroadcast(&transaction).await?;
+
+        let script_pubkey = action.to.script_pubkey();
+        let vout = transaction
+            .output
+            .iter()
+            .position(|output| output.script_pubkey == script_pubkey)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "transaction {} does not pay to the HTLC {}",
+                    txid,
+                    action.to
+                )
+            })?;
+        let location = hbit::htlc_location::Bitcoin {
+            txid,
+            vout: u32::try_from(vout)?,
+        };
+
+        Ok(hbit::CorrectlyFunded {
+            asset: action.amount,
+            location,
+        })
+    }
+
+    /// Redeem the HTLC with the transaction `build` returns for a
+    /// given fee rate, raising the fee rate until it is mined.
+    pub async fn redeem<B>(
+        &self,
+        build: B,
+        expiry: Timestamp,
+        secret: Secret,
+    ) -> anyhow::Result<hbit::Redeemed>
+    where
+        B: FnOnce(bitcoin::Amount) -> anyhow::Result<hbit::BroadcastSignedTransaction>,
+    {
+        let transaction = self.spend(Step::HbitRedeemed, build, expiry).await?;
+
+        Ok(hbit::Redeemed {
+            transaction,
+            secret,
+        })
+    }
+
+    /// Refund the expired HTLC with the transaction `build` returns for
+    /// a given fee rate, raising the fee rate until it is mined.
+    pub async fn refund<B>(&self, build: B) -> anyhow::Result<hbit::Refunded>
+    where
+        B: FnOnce(bitcoin::Amount) -> anyhow::Result<hbit::BroadcastSignedTransaction>,
+    {
+        let now = comit::bitcoin::median_time_past(self.connector.as_ref()).await?;
+        let deadline = crate::bitcoin::fee::refund_deadline(now);
+        let transaction = self.spend(Step::HbitRefunded, build, deadline).await?;
 
-            let transient_refund_pk =
-                identity::Bitcoin::from_secret_key(&secp, &transient_refund_sk);
+        Ok(hbit::Refunded { transaction })
+    }
+
+    /// Broadcast a transaction spending an HTLC for `step` and wait
+    /// until it is mined.
+    ///
+    /// comit signs HTLC spends without signalling replaceability, so
+    /// every time the transaction is not mined within `BUMP_INTERVAL`
+    /// a child transaction spending its output back to the wallet
+    /// raises the fee rate of both (CPFP).
+    ///
+    /// Close to the `deadline` the maximum fee rate is paid.
+    ///
+    /// A transaction recorded before a restart is broadcast again
+    /// instead of building a new one, which would conflict with it.
+    async fn spend<B>(
+        &self,
+        step: Step,
+        build: B,
+        deadline: Timestamp,
+    ) -> anyhow::Result<bitcoin::Transaction>
+    where
+        B: FnOnce(bitcoin::Amount) -> anyhow::Result<hbit::BroadcastSignedTransaction>,
+    {
+        // The fee rate of a transaction sent before a restart is not
+        // recorded, the current one is close enough to pay for a child.
+        let parent_vbyte_rate = self.spend_vbyte_rate().await?;
+        let mut vbyte_rate = parent_vbyte_rate;
+
+        let (parent, mut search) = match self.in_flight(step)? {
+            Some(transaction) => {
+                let mut search = Search::new(self.start_of_swap()?);
+
+                if let Err(e) = self.wallet.send_raw_transaction(&transaction).await {
+                    // bitcoind rejects a transaction that is mined already
+                    if search
+                        .mined(self.connector.as_ref(), &transaction.txid())
+                        .await?
+                    {
+                        return Ok(transaction);
+                    }
+
+                    return Err(e);
+                }
+
+                (transaction, search)
+            }
+            None => {
+                // Blocks mined after the broadcast are more recent than
+                // the median time past at the time of the broadcast.
+                let since = comit::bitcoin::median_time_past(self.connector.as_ref()).await?;
+                let transaction = build(parent_vbyte_rate)?.transaction;
+
+                self.record_in_flight(step, &transaction)?;
+                self.wallet.send_raw_transaction(&transaction).await?;
+
+                (transaction, Search::new(since))
+            }
+        };
+
+        while !self.wait_until_mined(&mut search, &parent).await? {
+            let now = comit::bitcoin::median_time_past(self.connector.as_ref()).await?;
+            let bumped = self
+                .fee_estimator
+                .bumped_vbyte_rate(vbyte_rate, now, deadline);
+            if bumped <= vbyte_rate {
+                continue;
+            }
+
+            self.bump_with_child(&parent, parent_vbyte_rate, bumped)
+                .await;
+            vbyte_rate = bumped;
+        }
+
+        Ok(parent)
+    }
+
+    /// Spend the output of `parent` paying to the wallet so that both
+    /// transactions together pay `vbyte_rate` (CPFP).
+    async fn bump_with_child(
+        &self,
+        parent: &bitcoin::Transaction,
+        parent_vbyte_rate: bitcoin::Amount,
+        vbyte_rate: bitcoin::Amount,
+    ) {
+        let fee = crate::bitcoin::fee::child_fee(parent, parent_vbyte_rate, vbyte_rate);
+
+        let child = match self.wallet.owned_output(parent).await {
+            Ok(Some(vout)) => self.wallet.spend_child(parent, vout, fee).await,
+            Ok(None) => Err(anyhow::anyhow!("none of its outputs pays to the wallet")),
+            Err(e) => Err(e),
+        };
+
+        match child {
+            Ok(child) => tracing::info!(
+                "bumped the fee of transaction {} with child {}, paying {} per vbyte",
+                parent.txid(),
+                child,
+                vbyte_rate
+            ),
+            Err(e) => tracing::warn!(
+                "could not bump the fee of transaction {} with a child: {:#}",
+                parent.txid(),
+                e
+            ),
+        }
+    }
+
+    /// Wait up to `BUMP_INTERVAL` for `transaction` to be mined,
+    /// searching every new block once.
+    async fn wait_until_mined(
+        &self,
+        search: &mut Search<bitcoin::BlockHash>,
+        transaction: &bitcoin::Transaction,
+    ) -> anyhow::Result<bool> {
+        let started = Instant::now();
+        let txid = transaction.txid();
+
+        while started.elapsed() < BUMP_INTERVAL {
+            if search.mined(self.connector.as_ref(), &txid).await? {
+                return Ok(true);
+            }
 
-            (private_details_funder, transient_refund_pk)
+            tokio::time::delay_for(Duration::from_secs(1)).await;
+        }
+
+        Ok(false)
+    }
+
+    /// The transaction recorded for `step` before a restart, if any.
+    fn in_flight(&self, step: Step) -> anyhow::Result<Option<bitcoin::Transaction>> {
+        let in_flight = match &self.journal {
+            Some(journal) => journal.in_flight(step)?,
+            None => None,
         };
 
-        let (private_details_redeemer, transient_redeem_pk) = {
-            let transient_redeem_sk = secp256k1::SecretKey::from_str(
-                "01010101010101010001020304050607ffff0000ffff00006363636363636363",
+        match in_flight {
+            Some(InFlight::Bitcoin(BitcoinTransaction(transaction))) => Ok(Some(transaction)),
+            Some(InFlight::Ethereum(_)) => anyhow::bail!(
+                "recorded transaction of {:?} is not a Bitcoin transaction",
+                step
+            ),
+            None => Ok(None),
+        }
+    }
+
+    fn record_in_flight(
+        &self,
+        step: Step,
+        transaction: &bitcoin::Transaction,
+    ) -> anyhow::Result<()> {
+        match &self.journal {
+            Some(journal) => journal.record_in_flight(
+                step,
+                InFlight::Bitcoin(BitcoinTransaction(transaction.clone())),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Transactions recorded in the journal were sent after the swap
+    /// started.
+    fn start_of_swap(&self) -> anyhow::Result<Timestamp> {
+        let start_of_swap = match &self.journal {
+            Some(journal) => journal.start_of_swap()?,
+            None => anyhow::bail!("a transaction was recorded without a journal"),
+        };
+
+        Ok(Timestamp::from(
+            u32::try_from(start_of_swap.timestamp()).unwrap_or(0),
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl LatestBlock for BitcoinWallet {
+    type Block = bitcoin::Block;
+    async fn latest_block(&self) -> anyhow::Result<Self::Block> {
+        self.connector.as_ref().latest_block().await
+    }
+}
+
+#[derive(Debug)]
+pub struct EthereumWallet {
+    connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+    wallet: crate::ethereum_wallet::Wallet,
+    journal: Option<Journal>,
+}
+
+impl EthereumWallet {
+    pub fn new(
+        connector: Arc<comit::btsieve::ethereum::Web3Connector>,
+        wallet: crate::ethereum_wallet::Wallet,
+    ) -> Self {
+        Self {
+            connector,
+            wallet,
+            journal: None,
+        }
+    }
+
+    /// Record the transactions of the HTLC in `journal`, so that a
+    /// resumed swap sends them again with the same nonce instead of
+    /// sending new ones.
+    pub fn with_journal(self, journal: Journal) -> Self {
+        Self {
+            journal: Some(journal),
+            ..self
+        }
+    }
+
+    /// The transactions of the herc20 HTLC are replaced by ones paying
+    /// a higher gas price until they are mined, paying the maximum gas
+    /// price close to the `expiry`.
+    pub async fn deploy(
+        &self,
+        action: herc20::DeployContract,
+        expiry: Timestamp,
+    ) -> anyhow::Result<herc20::Deployed> {
+        let journal = EthereumJournal::new(self.journal.as_ref(), Step::Herc20Deployed);
+        let receipt = self
+            .wallet
+            .deploy_contract(action, expiry, &journal)
+            .await?;
+        let location = receipt.contract_address.ok_or_else(|| {
+            anyhow::anyhow!(
+                "transaction {:?} did not deploy a contract",
+                receipt.transaction_hash
+            )
+        })?;
+        let transaction = self
+            .wallet
+            .get_transaction(receipt.transaction_hash)
+            .await?;
+
+        Ok(herc20::Deployed {
+            transaction,
+            location,
+        })
+    }
+
+    pub async fn fund(
+        &self,
+        action: herc20::CallContract,
+        asset: herc20::asset::Erc20,
+        expiry: Timestamp,
+    ) -> anyhow::Result<herc20::CorrectlyFunded> {
+        let transaction = self.call(action, expiry, Step::Herc20Funded).await?;
+
+        Ok(herc20::CorrectlyFunded { transaction, asset })
+    }
+
+    pub async fn redeem(
+        &self,
+        action: herc20::CallContract,
+        secret: Secret,
+        expiry: Timestamp,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        let transaction = self.call(action, expiry, Step::Herc20Redeemed).await?;
+
+        Ok(herc20::Redeemed {
+            transaction,
+            secret,
+        })
+    }
+
+    /// The HTLC has expired, the refund is given some time to be mined
+    /// before the maximum gas price is paid.
+    pub async fn refund(&self, action: herc20::CallContract) -> anyhow::Result<herc20::Refunded> {
+        let now = ethereum_latest_time(self.connector.as_ref()).await?;
+        let deadline = crate::ethereum_wallet::refund_deadline(now);
+        let transaction = self.call(action, deadline, Step::Herc20Refunded).await?;
+
+        Ok(herc20::Refunded { transaction })
+    }
+
+    async fn call(
+        &self,
+        action: herc20::CallContract,
+        deadline: Timestamp,
+        step: Step,
+    ) -> anyhow::Result<ethereum::Transaction> {
+        let journal = EthereumJournal::new(self.journal.as_ref(), step);
+        let receipt = self
+            .wallet
+            .call_contract(action, deadline, &journal)
+            .await?;
+        let transaction = self
+            .wallet
+            .get_transaction(receipt.transaction_hash)
+            .await?;
+
+        Ok(transaction)
+    }
+}
+
+#[async_trait::async_trait]
+impl LatestBlock for EthereumWallet {
+    type Block = ethereum::Block;
+    async fn latest_block(&self) -> anyhow::Result<Self::Block> {
+        self.connector.latest_block().await
+    }
+}
+
+/// The time of a blockchain against which HTLC expiries are
+/// evaluated.
+#[async_trait::async_trait]
+pub trait LedgerTime {
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp>;
+}
+
+#[async_trait::async_trait]
+impl LedgerTime for crate::bitcoind::Connector {
+    /// Bitcoin timelocks are checked against the median time past
+    /// (BIP 113), not the timestamp of the latest block.
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        comit::bitcoin::median_time_past(self).await
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerTime for comit::btsieve::ethereum::Web3Connector {
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        ethereum_latest_time(self).await
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerTime for BitcoinWallet {
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        self.connector.ledger_time().await
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerTime for EthereumWallet {
+    async fn ledger_time(&self) -> anyhow::Result<Timestamp> {
+        self.connector.ledger_time().await
+    }
+}
+
+async fn ethereum_latest_time<C>(connector: &C) -> anyhow::Result<Timestamp>
+where
+    C: LatestBlock<Block = ethereum::Block>,
+{
+    let timestamp = connector.latest_block().await?.timestamp.into();
+
+    Ok(timestamp)
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod tests {
+    use super::*;
+    use crate::{
+        bitcoind, jsonrpc,
+        test_harness::{BitcoinBlockchain, EthereumBlockchain},
+    };
+    use bitcoin::{secp256k1, Network};
+    use chrono::Utc;
+    use comit::{
+        asset::{
+            self,
+            ethereum::{Erc20Quantity, FromWei},
+        },
+        btsieve::ethereum::Web3Connector,
+        ethereum, identity, Secret, SecretHash, Timestamp,
+    };
+    use num::BigUint;
+    use std::{convert::TryFrom, str::FromStr, sync::Arc};
+    use testcontainers::clients;
+
+    const BITCOIN_AMOUNT: u64 = 100_000_000;
+    const TOKEN_QUANTITY: u32 = 1_000;
+
+    /// Upper bound of the fees paid to fund and spend an HTLC.
+    const MAX_FEES: u64 = 100_000;
+
+    /// The nodes and a funded wallet for each party on both chains.
+    struct Setup {
+        bitcoin_connector: Arc<bitcoind::Connector>,
+        ethereum_connector: Arc<Web3Connector>,
+        fee_estimator: crate::bitcoin::fee::Estimator,
+        chain_id: ethereum::ChainId,
+        token_contract: ethereum::Address,
+        alice: Wallets,
+        bob: Wallets,
+    }
+
+    struct Wallets {
+        bitcoin: crate::bitcoin_wallet::Wallet,
+        ethereum: crate::ethereum_wallet::Wallet,
+    }
+
+    impl Wallets {
+        fn bitcoin_wallet(&self, setup: &Setup) -> BitcoinWallet {
+            BitcoinWallet::new(
+                Arc::clone(&setup.bitcoin_connector),
+                self.bitcoin.clone(),
+                setup.fee_estimator.clone(),
+            )
+        }
+
+        fn ethereum_wallet(&self, setup: &Setup) -> EthereumWallet {
+            EthereumWallet::new(Arc::clone(&setup.ethereum_connector), self.ethereum.clone())
+        }
+
+        async fn bitcoin_balance(&self) -> bitcoin::Amount {
+            self.bitcoin.balance().await.unwrap()
+        }
+
+        async fn token_balance(&self, setup: &Setup) -> BigUint {
+            self.ethereum
+                .erc20_balance(setup.token_contract)
+                .await
+                .unwrap()
+        }
+    }
+
+    /// The bitcoin funder gets `BITCOIN_AMOUNT` on top of the fees and
+    /// the token funder gets `TOKEN_QUANTITY`, both get ether for gas.
+    async fn setup(
+        bitcoin_blockchain: &BitcoinBlockchain<'_>,
+        ethereum_blockchain: &EthereumBlockchain<'_>,
+        bitcoin_funder: Role,
+    ) -> Setup {
+        bitcoin_blockchain.init().await.unwrap();
+        bitcoin_blockchain.start_mining().await.unwrap();
+
+        let bitcoind_client = crate::bitcoind::Client::new(bitcoin_blockchain.node_url.clone());
+        let fee_estimator = crate::bitcoin::fee::Estimator::new(
+            bitcoind_client.clone(),
+            crate::config::BitcoinFees::DEFAULT,
+        );
+        let token_contract = ethereum_blockchain.deploy_erc20_token().await.unwrap();
+
+        let mut wallets = Vec::new();
+        for _ in 0..2 {
+            let bitcoin = crate::bitcoin_wallet::Wallet::new(
+                crate::seed::Seed::new(),
+                bitcoind_client.clone(),
+                Network::Regtest,
             )
             .unwrap();
-            // FIXME: Get final_redeem_identity from funder wallet
-            let final_redeem_identity =
-                bitcoin::Address::from_str("bcrt1q2nfxmhd4n3c8834pj72xagvyr9gl57n5r94fsl").unwrap();
-            let private_details_redeemer = hbit::PrivateDetailsRedeemer {
-                transient_redeem_sk,
-                final_redeem_identity,
-            };
+            bitcoin.init().await.unwrap();
+            let ethereum = crate::ethereum_wallet::Wallet::new(
+                crate::seed::Seed::new(),
+                ethereum_blockchain.node_url.clone(),
+                crate::config::GasPrice::DEFAULT,
+            )
+            .unwrap();
+            ethereum_blockchain
+                .mint_ether(ethereum.account(), 1_000_000_000_000_000_000)
+                .await
+                .unwrap();
+            wallets.push(Wallets { bitcoin, ethereum });
+        }
+        let bob = wallets.pop().unwrap();
+        let alice = wallets.pop().unwrap();
 
-            let transient_redeem_pk =
-                identity::Bitcoin::from_secret_key(&secp, &transient_redeem_sk);
+        let (bitcoin_funder, token_funder) = match bitcoin_funder {
+            Role::Alice => (&alice, &bob),
+            Role::Bob => (&bob, &alice),
+        };
+        let address = bitcoin_funder.bitcoin.new_address().await.unwrap();
+        bitcoin_blockchain
+            .mint(
+                address,
+                bitcoin::Amount::from_sat(BITCOIN_AMOUNT + MAX_FEES),
+            )
+            .await
+            .unwrap();
+        ethereum_blockchain
+            .mint_erc20(
+                token_contract,
+                token_funder.ethereum.account(),
+                u128::from(TOKEN_QUANTITY),
+            )
+            .await
+            .unwrap();
+        let chain_id = alice.ethereum.chain_id().await.unwrap();
+
+        Setup {
+            bitcoin_connector: Arc::new(
+                bitcoind::Connector::new(
+                    bitcoin_blockchain.node_url.clone(),
+                    Network::Regtest,
+                    jsonrpc::Auth::None,
+                )
+                .unwrap(),
+            ),
+            ethereum_connector: Arc::new(Web3Connector::new(ethereum_blockchain.node_url.clone())),
+            fee_estimator,
+            chain_id,
+            token_contract,
+            alice,
+            bob,
+        }
+    }
 
-            (private_details_redeemer, transient_redeem_pk)
+    /// The HTLC refunds to an address of `funder` and redeems to one of
+    /// `redeemer`.
+    async fn hbit_params<C>(
+        funder: &Wallets,
+        redeemer: &Wallets,
+        expiry: Timestamp,
+        secret_hash: SecretHash,
+        secp: &bitcoin::secp256k1::Secp256k1<C>,
+    ) -> (
+        hbit::Params,
+        hbit::PrivateDetailsFunder,
+        hbit::PrivateDetailsRedeemer,
+    )
+    where
+        C: secp256k1::Signing,
+    {
+        let transient_refund_sk = secp256k1::SecretKey::from_str(
+            "01010101010101010001020304050607ffff0000ffff00006363636363636363",
+        )
+        .unwrap();
+        let private_details_funder = hbit::PrivateDetailsFunder {
+            transient_refund_sk,
+            final_refund_identity: funder.bitcoin.new_address().await.unwrap(),
+        };
+
+        let transient_redeem_sk = secp256k1::SecretKey::from_str(
+            "02020202020202020001020304050607ffff0000ffff00006363636363636363",
+        )
+        .unwrap();
+        let private_details_redeemer = hbit::PrivateDetailsRedeemer {
+            transient_redeem_sk,
+            final_redeem_identity: redeemer.bitcoin.new_address().await.unwrap(),
         };
 
         let params = hbit::Params {
-            network,
-            asset,
-            redeem_identity: transient_redeem_pk,
-            refund_identity: transient_refund_pk,
+            network: Network::Regtest,
+            asset: asset::Bitcoin::from_sat(BITCOIN_AMOUNT),
+            redeem_identity: identity::Bitcoin::from_secret_key(&secp, &transient_redeem_sk),
+            refund_identity: identity::Bitcoin::from_secret_key(&secp, &transient_refund_sk),
             expiry,
             secret_hash,
         };
@@ -841,102 +1900,451 @@ mod tests {
         (params, private_details_funder, private_details_redeemer)
     }
 
-    fn herc20_params(secret_hash: SecretHash) -> herc20::Params {
-        let token_contract =
-            ethereum::Address::from_str("6b175474e89094c44da98b954eedeac495271d0f").unwrap();
-        let quantity = Erc20Quantity::from_wei(1_000u32);
-        let asset = asset::Erc20::new(token_contract, quantity);
-
-        // FIXME: Obtain identities from user wallets
-        let identity =
-            identity::Ethereum::from_str("c5549e335b2786520f4c5d706c76c9ee69d0a028").unwrap();
+    fn herc20_params(
+        setup: &Setup,
+        funder: &Wallets,
+        redeemer: &Wallets,
+        expiry: Timestamp,
+        secret_hash: SecretHash,
+    ) -> herc20::Params {
+        let quantity = Erc20Quantity::from_wei(TOKEN_QUANTITY);
 
         herc20::Params {
-            asset,
-            redeem_identity: identity,
-            refund_identity: identity,
-            expiry: Timestamp::from(0),
-            chain_id: ethereum::ChainId::regtest(),
+            asset: asset::Erc20::new(setup.token_contract, quantity),
+            redeem_identity: redeemer.ethereum.account(),
+            refund_identity: funder.ethereum.account(),
+            expiry,
+            chain_id: setup.chain_id,
             secret_hash,
         }
     }
 
+    fn in_seconds(seconds: u32) -> Timestamp {
+        let now = u32::try_from(Utc::now().timestamp()).unwrap();
+
+        Timestamp::from(now + seconds)
+    }
+
+    fn safety_margins() -> SafetyMargins {
+        SafetyMargins {
+            bitcoin: Duration::from_secs(60),
+            ethereum: Duration::from_secs(15),
+        }
+    }
+
+    fn confirmations() -> Confirmations {
+        Confirmations {
+            bitcoin: 1,
+            ethereum: 1,
+        }
+    }
+
     fn secret() -> Secret {
         let bytes = b"hello world, you are beautiful!!";
         Secret::from(*bytes)
     }
 
     #[tokio::test]
-    async fn execute_alice_hbit_herc20_swap() {
-        let secp: bitcoin::secp256k1::Secp256k1<bitcoin::secp256k1::All> =
-            bitcoin::secp256k1::Secp256k1::new();
+    async fn alice_and_bob_redeem_hbit_herc20_swap() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let client = clients::Cli::default();
+        let bitcoin_blockchain = BitcoinBlockchain::new(&client).unwrap();
+        let ethereum_blockchain = EthereumBlockchain::new(&client).unwrap();
+        let setup = setup(&bitcoin_blockchain, &ethereum_blockchain, Role::Alice).await;
 
-        let bitcoin_connector = {
-            let client = clients::Cli::default();
-            let blockchain = BitcoinBlockchain::new(&client).unwrap();
+        let secret = secret();
+        let secret_hash = SecretHash::new(secret);
+        let start_of_swap = Utc::now().naive_local();
+        let (hbit_params, private_details_funder, private_details_redeemer) = hbit_params(
+            &setup.alice,
+            &setup.bob,
+            in_seconds(20 * 60),
+            secret_hash,
+            &secp,
+        )
+        .await;
+        let herc20_params = herc20_params(
+            &setup,
+            &setup.bob,
+            &setup.alice,
+            in_seconds(10 * 60),
+            secret_hash,
+        );
+        let alice_bitcoin_balance = setup.alice.bitcoin_balance().await;
 
-            Arc::new(BitcoindConnector::new(blockchain.node_url, Network::Regtest).unwrap())
+        let alice_swap = {
+            let alice = WalletAlice {
+                alpha_wallet: setup.alice.bitcoin_wallet(&setup),
+                beta_wallet: setup.alice.ethereum_wallet(&setup),
+                private_protocol_details: private_details_funder,
+                secret,
+            };
+            let bob = WatchOnlyBob {
+                alpha_connector: Arc::clone(&setup.bitcoin_connector),
+                beta_connector: Arc::clone(&setup.ethereum_connector),
+                secret_hash,
+                start_of_swap,
+                confirmations: confirmations(),
+            };
+
+            hbit_herc20(
+                alice,
+                bob,
+                hbit_params,
+                herc20_params.clone(),
+                safety_margins(),
+                &secp,
+            )
         };
-        let ethereum_connector = {
-            let client = clients::Cli::default();
-            let blockchain = EthereumBlockchain::new(&client).unwrap();
+        let bob_swap = {
+            let alice = WatchOnlyAlice {
+                alpha_connector: Arc::clone(&setup.bitcoin_connector),
+                beta_connector: Arc::clone(&setup.ethereum_connector),
+                secret_hash,
+                start_of_swap,
+                confirmations: confirmations(),
+            };
+            let bob = WalletBob {
+                alpha_wallet: setup.bob.bitcoin_wallet(&setup),
+                beta_wallet: setup.bob.ethereum_wallet(&setup),
+                secret_hash,
+                private_protocol_details: private_details_redeemer,
+            };
 
-            Arc::new(Web3Connector::new(blockchain.node_url))
+            hbit_herc20(
+                alice,
+                bob,
+                hbit_params,
+                herc20_params,
+                safety_margins(),
+                &secp,
+            )
         };
 
+        let (alice_outcome, bob_outcome) = futures::join!(alice_swap, bob_swap);
+        let alice_outcome = alice_outcome.unwrap();
+
+        assert!(alice_outcome.is_redeemed(), "{:?}", alice_outcome);
+        assert_eq!(bob_outcome.unwrap(), alice_outcome);
+        assert_eq!(
+            setup.alice.token_balance(&setup).await,
+            BigUint::from(TOKEN_QUANTITY)
+        );
+        assert_eq!(setup.bob.token_balance(&setup).await, BigUint::from(0u8));
+        assert!(
+            setup.alice.bitcoin_balance().await
+                < alice_bitcoin_balance - bitcoin::Amount::from_sat(BITCOIN_AMOUNT)
+        );
+        let bob_bitcoin_balance = setup.bob.bitcoin_balance().await;
+        assert!(bob_bitcoin_balance > bitcoin::Amount::from_sat(BITCOIN_AMOUNT - MAX_FEES));
+        assert!(bob_bitcoin_balance < bitcoin::Amount::from_sat(BITCOIN_AMOUNT));
+    }
+
+    /// The hbit HTLC does not expire long enough after the herc20 one
+    /// for Bob to redeem it in time, he never funds.
+    #[tokio::test]
+    async fn given_bob_not_safe_to_fund_then_alice_refunds_hbit() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let client = clients::Cli::default();
+        let bitcoin_blockchain = BitcoinBlockchain::new(&client).unwrap();
+        let ethereum_blockchain = EthereumBlockchain::new(&client).unwrap();
+        let setup = setup(&bitcoin_blockchain, &ethereum_blockchain, Role::Alice).await;
+
         let secret = secret();
         let secret_hash = SecretHash::new(secret);
+        let (hbit_params, private_details_funder, _) =
+            hbit_params(&setup.alice, &setup.bob, in_seconds(30), secret_hash, &secp).await;
+        let herc20_params = herc20_params(
+            &setup,
+            &setup.bob,
+            &setup.alice,
+            in_seconds(10 * 60),
+            secret_hash,
+        );
+        let alice_bitcoin_balance = setup.alice.bitcoin_balance().await;
 
-        let start_of_swap = Utc::now().naive_local();
+        let alice = WalletAlice {
+            alpha_wallet: setup.alice.bitcoin_wallet(&setup),
+            beta_wallet: setup.alice.ethereum_wallet(&setup),
+            private_protocol_details: private_details_funder,
+            secret,
+        };
+        let bob = WatchOnlyBob {
+            alpha_connector: Arc::clone(&setup.bitcoin_connector),
+            beta_connector: Arc::clone(&setup.ethereum_connector),
+            secret_hash,
+            start_of_swap: Utc::now().naive_local(),
+            confirmations: confirmations(),
+        };
+
+        let outcome = hbit_herc20(
+            alice,
+            bob,
+            hbit_params,
+            herc20_params,
+            safety_margins(),
+            &secp,
+        )
+        .await
+        .unwrap();
+
+        match outcome {
+            SwapOutcome::Refunded {
+                reason: Reason::BobNotSafeToFund,
+                alpha_refund: Some(TransactionId::Bitcoin(_)),
+                beta_refund: None,
+                beta_redeem: None,
+            } => {}
+            outcome => panic!("expected alice to refund the hbit HTLC, got {:?}", outcome),
+        }
+        assert!(
+            setup.alice.bitcoin_balance().await
+                > alice_bitcoin_balance - bitcoin::Amount::from_sat(MAX_FEES)
+        );
+    }
+
+    /// The herc20 HTLC expires within the Ethereum safety margin.
+    #[tokio::test]
+    async fn given_alice_not_safe_to_fund_then_swap_is_aborted() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let client = clients::Cli::default();
+        let bitcoin_blockchain = BitcoinBlockchain::new(&client).unwrap();
+        let ethereum_blockchain = EthereumBlockchain::new(&client).unwrap();
+        let setup = setup(&bitcoin_blockchain, &ethereum_blockchain, Role::Alice).await;
+
+        let secret = secret();
+        let secret_hash = SecretHash::new(secret);
+        let (hbit_params, private_details_funder, _) = hbit_params(
+            &setup.alice,
+            &setup.bob,
+            in_seconds(20 * 60),
+            secret_hash,
+            &secp,
+        )
+        .await;
+        let herc20_params = herc20_params(
+            &setup,
+            &setup.bob,
+            &setup.alice,
+            in_seconds(10),
+            secret_hash,
+        );
+        let alice_bitcoin_balance = setup.alice.bitcoin_balance().await;
+
+        let alice = WalletAlice {
+            alpha_wallet: setup.alice.bitcoin_wallet(&setup),
+            beta_wallet: setup.alice.ethereum_wallet(&setup),
+            private_protocol_details: private_details_funder,
+            secret,
+        };
+        let bob = WatchOnlyBob {
+            alpha_connector: Arc::clone(&setup.bitcoin_connector),
+            beta_connector: Arc::clone(&setup.ethereum_connector),
+            secret_hash,
+            start_of_swap: Utc::now().naive_local(),
+            confirmations: confirmations(),
+        };
 
-        let (hbit_params, private_details_funder, private_details_redeemer) =
-            hbit_params(secret_hash, &secp);
+        let outcome = hbit_herc20(
+            alice,
+            bob,
+            hbit_params,
+            herc20_params,
+            safety_margins(),
+            &secp,
+        )
+        .await
+        .unwrap();
 
-        let herc20_params = herc20_params(secret_hash);
+        assert_eq!(
+            outcome,
+            SwapOutcome::AbortedBeforeFunding {
+                reason: Reason::AliceNotSafeToFund
+            }
+        );
+        assert_eq!(setup.alice.bitcoin_balance().await, alice_bitcoin_balance);
+    }
 
-        let _alice_swap = {
+    #[tokio::test]
+    async fn alice_and_bob_redeem_herc20_hbit_swap() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let client = clients::Cli::default();
+        let bitcoin_blockchain = BitcoinBlockchain::new(&client).unwrap();
+        let ethereum_blockchain = EthereumBlockchain::new(&client).unwrap();
+        let setup = setup(&bitcoin_blockchain, &ethereum_blockchain, Role::Bob).await;
+
+        let secret = secret();
+        let secret_hash = SecretHash::new(secret);
+        let start_of_swap = Utc::now().naive_local();
+        let (hbit_params, private_details_funder, private_details_redeemer) = hbit_params(
+            &setup.bob,
+            &setup.alice,
+            in_seconds(10 * 60),
+            secret_hash,
+            &secp,
+        )
+        .await;
+        let herc20_params = herc20_params(
+            &setup,
+            &setup.alice,
+            &setup.bob,
+            in_seconds(20 * 60),
+            secret_hash,
+        );
+
+        let alice_swap = {
             let alice = WalletAlice {
-                alpha_wallet: BitcoinWallet {
-                    connector: Arc::clone(&bitcoin_connector),
-                },
-                beta_wallet: EthereumWallet {
-                    connector: Arc::clone(&ethereum_connector),
-                },
-                private_protocol_details: private_details_funder,
+                alpha_wallet: setup.alice.ethereum_wallet(&setup),
+                beta_wallet: setup.alice.bitcoin_wallet(&setup),
+                private_protocol_details: private_details_redeemer,
                 secret,
             };
             let bob = WatchOnlyBob {
-                alpha_connector: Arc::clone(&bitcoin_connector),
-                beta_connector: Arc::clone(&ethereum_connector),
+                alpha_connector: Arc::clone(&setup.ethereum_connector),
+                beta_connector: Arc::clone(&setup.bitcoin_connector),
                 secret_hash,
                 start_of_swap,
+                confirmations: confirmations(),
             };
 
-            hbit_herc20(alice, bob, hbit_params, herc20_params.clone(), &secp)
+            herc20_hbit(
+                alice,
+                bob,
+                herc20_params.clone(),
+                hbit_params,
+                safety_margins(),
+                &secp,
+            )
         };
-
-        let _bob_swap = {
+        let bob_swap = {
             let alice = WatchOnlyAlice {
-                alpha_connector: Arc::clone(&bitcoin_connector),
-                beta_connector: Arc::clone(&ethereum_connector),
+                alpha_connector: Arc::clone(&setup.ethereum_connector),
+                beta_connector: Arc::clone(&setup.bitcoin_connector),
                 secret_hash,
                 start_of_swap,
+                confirmations: confirmations(),
             };
             let bob = WalletBob {
-                alpha_wallet: BitcoinWallet {
-                    connector: bitcoin_connector,
-                },
-                beta_wallet: EthereumWallet {
-                    connector: ethereum_connector,
-                },
+                alpha_wallet: setup.bob.ethereum_wallet(&setup),
+                beta_wallet: setup.bob.bitcoin_wallet(&setup),
                 secret_hash,
-                private_protocol_details: private_details_redeemer,
+                private_protocol_details: private_details_funder,
             };
 
-            hbit_herc20(alice, bob, hbit_params, herc20_params, &secp)
+            herc20_hbit(
+                alice,
+                bob,
+                herc20_params,
+                hbit_params,
+                safety_margins(),
+                &secp,
+            )
         };
 
-        // TODO: Actually spawn both swap executions
-        // TODO: Assert that the money moves as expected
+        let (alice_outcome, bob_outcome) = futures::join!(alice_swap, bob_swap);
+        let alice_outcome = alice_outcome.unwrap();
+
+        assert!(alice_outcome.is_redeemed(), "{:?}", alice_outcome);
+        assert_eq!(bob_outcome.unwrap(), alice_outcome);
+        assert_eq!(setup.alice.token_balance(&setup).await, BigUint::from(0u8));
+        assert_eq!(
+            setup.bob.token_balance(&setup).await,
+            BigUint::from(TOKEN_QUANTITY)
+        );
+        let alice_bitcoin_balance = setup.alice.bitcoin_balance().await;
+        assert!(alice_bitcoin_balance > bitcoin::Amount::from_sat(BITCOIN_AMOUNT - MAX_FEES));
+        assert!(alice_bitcoin_balance < bitcoin::Amount::from_sat(BITCOIN_AMOUNT));
+    }
+
+    /// The herc20 HTLC does not expire long enough after the hbit one
+    /// for Bob to redeem it in time, he never funds.
+    #[tokio::test]
+    async fn given_bob_not_safe_to_fund_then_alice_refunds_herc20() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let client = clients::Cli::default();
+        let bitcoin_blockchain = BitcoinBlockchain::new(&client).unwrap();
+        let ethereum_blockchain = EthereumBlockchain::new(&client).unwrap();
+        let setup = setup(&bitcoin_blockchain, &ethereum_blockchain, Role::Bob).await;
+
+        let secret = secret();
+        let secret_hash = SecretHash::new(secret);
+        let (hbit_params, _, private_details_redeemer) = hbit_params(
+            &setup.bob,
+            &setup.alice,
+            in_seconds(10 * 60),
+            secret_hash,
+            &secp,
+        )
+        .await;
+        let herc20_params = herc20_params(
+            &setup,
+            &setup.alice,
+            &setup.bob,
+            in_seconds(30),
+            secret_hash,
+        );
+
+        let alice = WalletAlice {
+            alpha_wallet: setup.alice.ethereum_wallet(&setup),
+            beta_wallet: setup.alice.bitcoin_wallet(&setup),
+            private_protocol_details: private_details_redeemer,
+            secret,
+        };
+        let bob = WatchOnlyBob {
+            alpha_connector: Arc::clone(&setup.ethereum_connector),
+            beta_connector: Arc::clone(&setup.bitcoin_connector),
+            secret_hash,
+            start_of_swap: Utc::now().naive_local(),
+            confirmations: confirmations(),
+        };
+
+        let outcome = herc20_hbit(
+            alice,
+            bob,
+            herc20_params,
+            hbit_params,
+            safety_margins(),
+            &secp,
+        )
+        .await
+        .unwrap();
+
+        match outcome {
+            SwapOutcome::Refunded {
+                reason: Reason::BobNotSafeToFund,
+                alpha_refund: Some(TransactionId::Ethereum(_)),
+                beta_refund: None,
+                beta_redeem: None,
+            } => {}
+            outcome => panic!(
+                "expected alice to refund the herc20 HTLC, got {:?}",
+                outcome
+            ),
+        }
+        assert_eq!(
+            setup.alice.token_balance(&setup).await,
+            BigUint::from(TOKEN_QUANTITY)
+        );
+    }
+
+    #[tokio::test]
+    async fn given_refund_rejected_then_the_redeem_is_waited_for() {
+        let redeem = Box::pin(async {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            Ok::<_, anyhow::Error>("redeemed")
+        });
+        let refund = future::ready(Err::<(), _>(anyhow::anyhow!("input already spent")));
+
+        let outcome = redeem_or_refund(redeem, refund).await.unwrap();
+
+        assert!(matches!(outcome, Either::Left("redeemed")));
+    }
+
+    #[tokio::test]
+    async fn given_redeem_and_refund_fail_then_error() {
+        let redeem = future::ready(Err::<(), _>(anyhow::anyhow!("no secret")));
+        let refund = future::ready(Err::<(), _>(anyhow::anyhow!("input already spent")));
+
+        assert!(redeem_or_refund(redeem, refund).await.is_err());
     }
 }
@@ -0,0 +1,772 @@
+use chrono::NaiveDateTime;
+use comit::{
+    btsieve::{BlockByHash, LatestBlock},
+    ethereum, Timestamp,
+};
+use std::{convert::TryFrom, fmt, future::Future, time::Duration};
+
+/// How often to check whether a transaction is buried deep enough.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How far before the start of the swap a block may be stamped and
+/// still be searched. Bitcoin block timestamps are not monotonic, a
+/// block can be stamped up to about an hour before its parent.
+const TIMESTAMP_TOLERANCE: u32 = 2 * 60 * 60;
+
+/// The most blocks the tip may have moved by between two polls. If
+/// the tip is further away from the blocks seen before, they have
+/// been reorged out.
+const MAX_NEW_BLOCKS: usize = 100;
+
+/// The number of blocks, including the one it was mined in, a
+/// counterparty transaction must be buried under before nectar acts
+/// on it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Confirmations {
+    pub bitcoin: u32,
+    pub ethereum: u32,
+}
+
+/// A block that links to its parent and can be searched for a
+/// transaction.
+pub trait LinkedBlock: Send {
+    type Hash: Clone + PartialEq + Send + Sync;
+    type TransactionId: fmt::Debug + Send + Sync;
+
+    fn hash(&self) -> Self::Hash;
+    fn parent_hash(&self) -> Self::Hash;
+    fn timestamp(&self) -> Timestamp;
+    fn contains(&self, transaction: &Self::TransactionId) -> bool;
+}
+
+impl LinkedBlock for bitcoin::Block {
+    type Hash = bitcoin::BlockHash;
+    type TransactionId = bitcoin::Txid;
+
+    fn hash(&self) -> Self::Hash {
+        self.block_hash()
+    }
+
+    fn parent_hash(&self) -> Self::Hash {
+        self.header.prev_blockhash
+    }
+
+    fn timestamp(&self) -> Timestamp {
+        Timestamp::from(self.header.time)
+    }
+
+    fn contains(&self, txid: &Self::TransactionId) -> bool {
+        self.txdata
+            .iter()
+            .any(|transaction| transaction.txid() == *txid)
+    }
+}
+
+impl LinkedBlock for ethereum::Block {
+    type Hash = ethereum::Hash;
+    type TransactionId = ethereum::Hash;
+
+    fn hash(&self) -> Self::Hash {
+        self.hash
+    }
+
+    fn parent_hash(&self) -> Self::Hash {
+        self.parent_hash
+    }
+
+    fn timestamp(&self) -> Timestamp {
+        self.timestamp.into()
+    }
+
+    fn contains(&self, hash: &Self::TransactionId) -> bool {
+        self.transactions
+            .iter()
+            .any(|transaction| transaction.hash == *hash)
+    }
+}
+
+/// What became of a transaction while waiting for its confirmations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Finality {
+    Confirmed,
+    /// The transaction is no longer part of the chain, the event it
+    /// produced is invalid.
+    Reorged,
+}
+
+/// Watch for an event until the transaction that produced it has at
+/// least `confirmations` confirmations.
+///
+/// If the transaction is reorged out of the chain in the meantime,
+/// the event is watched for again: the transaction may be mined
+/// anew, possibly in a different form.
+pub async fn watch_until_confirmed<C, B, E, W, F, T>(
+    connector: &C,
+    confirmations: u32,
+    start_of_swap: NaiveDateTime,
+    watch: W,
+    transaction_of: T,
+) -> anyhow::Result<E>
+where
+    C: LatestBlock<Block = B> + BlockByHash<Block = B, BlockHash = B::Hash>,
+    B: LinkedBlock,
+    W: Fn() -> F,
+    F: Future<Output = anyhow::Result<E>>,
+    T: Fn(&E) -> B::TransactionId,
+{
+    loop {
+        let event = watch().await?;
+        let transaction = transaction_of(&event);
+
+        match wait_for_confirmations(connector, &transaction, confirmations, start_of_swap).await? {
+            Finality::Confirmed => return Ok(event),
+            Finality::Reorged => tracing::warn!(
+                "transaction {:?} was reorged out, watching for the event again",
+                transaction
+            ),
+        }
+    }
+}
+
+/// Wait until a transaction mined after the start of the swap has at
+/// least `confirmations` confirmations.
+pub async fn wait_for_confirmations<C, B>(
+    connector: &C,
+    transaction: &B::TransactionId,
+    confirmations: u32,
+    start_of_swap: NaiveDateTime,
+) -> anyhow::Result<Finality>
+where
+    C: LatestBlock<Block = B> + BlockByHash<Block = B, BlockHash = B::Hash>,
+    B: LinkedBlock,
+{
+    let since = Timestamp::from(u32::try_from(start_of_swap.timestamp()).unwrap_or(0));
+
+    let mut inclusion = match Inclusion::find(connector, transaction, since).await? {
+        Some(inclusion) => inclusion,
+        None => return Ok(Finality::Reorged),
+    };
+
+    while inclusion.confirmations() < confirmations {
+        tokio::time::delay_for(POLL_INTERVAL).await;
+
+        if !inclusion.follow_tip(connector).await? {
+            return Ok(Finality::Reorged);
+        }
+    }
+
+    Ok(Finality::Confirmed)
+}
+
+/// Looks for a transaction in the chain, searching every block only
+/// once however often it is asked.
+#[derive(Debug)]
+pub struct Search<H> {
+    since: Timestamp,
+    searched: Vec<H>,
+}
+
+impl<H> Search<H>
+where
+    H: Clone + PartialEq,
+{
+    /// A search through the blocks mined since `since`.
+    pub fn new(since: Timestamp) -> Self {
+        Search {
+            since,
+            searched: Vec::new(),
+        }
+    }
+
+    /// Whether `transaction` is in one of the blocks added to the chain
+    /// since the last call.
+    pub async fn mined<C, B>(
+        &mut self,
+        connector: &C,
+        transaction: &B::TransactionId,
+    ) -> anyhow::Result<bool>
+    where
+        C: LatestBlock<Block = B> + BlockByHash<Block = B, BlockHash = H>,
+        B: LinkedBlock<Hash = H>,
+    {
+        let cutoff = u32::from(self.since).saturating_sub(TIMESTAMP_TOLERANCE);
+        let mut block = connector.latest_block().await?;
+
+        loop {
+            let hash = block.hash();
+
+            if self.searched.contains(&hash) || u32::from(block.timestamp()) < cutoff {
+                return Ok(false);
+            }
+            if block.contains(transaction) {
+                return Ok(true);
+            }
+
+            self.searched.push(hash);
+            block = connector.block_by_hash(block.parent_hash()).await?;
+        }
+    }
+}
+
+/// The block a transaction was mined in and the blocks built on top of
+/// it up to the tip of the chain.
+///
+/// Heights count from the block the transaction was mined in: once it
+/// is found, following the tip only walks back to the blocks seen
+/// before instead of searching for the transaction again.
+#[derive(Debug)]
+struct Inclusion<H> {
+    parent: H,
+    /// Indexed by height.
+    blocks: Vec<H>,
+}
+
+impl<H> Inclusion<H>
+where
+    H: Clone + PartialEq,
+{
+    /// Walk back from the tip to the block holding `transaction`,
+    /// giving up at blocks stamped well before `since`.
+    async fn find<C, B>(
+        connector: &C,
+        transaction: &B::TransactionId,
+        since: Timestamp,
+    ) -> anyhow::Result<Option<Self>>
+    where
+        C: LatestBlock<Block = B> + BlockByHash<Block = B, BlockHash = H>,
+        B: LinkedBlock<Hash = H>,
+    {
+        let cutoff = u32::from(since).saturating_sub(TIMESTAMP_TOLERANCE);
+        let mut block = connector.latest_block().await?;
+        let mut descendants = Vec::new();
+
+        loop {
+            if block.contains(transaction) {
+                let mut blocks = vec![block.hash()];
+                blocks.extend(descendants.into_iter().rev());
+
+                return Ok(Some(Inclusion {
+                    parent: block.parent_hash(),
+                    blocks,
+                }));
+            }
+
+            if u32::from(block.timestamp()) < cutoff {
+                return Ok(None);
+            }
+
+            descendants.push(block.hash());
+            block = connector.block_by_hash(block.parent_hash()).await?;
+        }
+    }
+
+    fn confirmations(&self) -> u32 {
+        u32::try_from(self.blocks.len()).unwrap_or(u32::MAX)
+    }
+
+    /// Catch up with the tip of the chain, returning whether the block
+    /// holding the transaction is still one of its ancestors.
+    async fn follow_tip<C, B>(&mut self, connector: &C) -> anyhow::Result<bool>
+    where
+        C: LatestBlock<Block = B> + BlockByHash<Block = B, BlockHash = H>,
+        B: LinkedBlock<Hash = H>,
+    {
+        let mut block = connector.latest_block().await?;
+        let mut new_blocks = Vec::new();
+
+        loop {
+            let hash = block.hash();
+
+            if let Some(height) = self.blocks.iter().position(|known| *known == hash) {
+                self.blocks.truncate(height + 1);
+                self.blocks.extend(new_blocks.into_iter().rev());
+
+                return Ok(true);
+            }
+
+            if hash == self.parent || new_blocks.len() >= MAX_NEW_BLOCKS {
+                return Ok(false);
+            }
+
+            new_blocks.push(hash);
+            block = connector.block_by_hash(block.parent_hash()).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    const GENESIS_TIME: u32 = 1_000_000;
+    const BLOCK_INTERVAL: u32 = 60 * 60;
+
+    #[derive(Clone)]
+    struct Block {
+        hash: usize,
+        parent: usize,
+        timestamp: u32,
+        transactions: Vec<u32>,
+    }
+
+    impl LinkedBlock for Block {
+        type Hash = usize;
+        type TransactionId = u32;
+
+        fn hash(&self) -> Self::Hash {
+            self.hash
+        }
+
+        fn parent_hash(&self) -> Self::Hash {
+            self.parent
+        }
+
+        fn timestamp(&self) -> Timestamp {
+            Timestamp::from(self.timestamp)
+        }
+
+        fn contains(&self, transaction: &Self::TransactionId) -> bool {
+            self.transactions.contains(transaction)
+        }
+    }
+
+    /// A chain where the hash of a block is its index in the order
+    /// blocks were added, forks included, and blocks are mined every
+    /// hour.
+    struct Chain {
+        blocks: Mutex<Vec<Block>>,
+        tip: Mutex<usize>,
+        lookups: AtomicUsize,
+    }
+
+    impl Chain {
+        /// Every block holds a single transaction, equal to its
+        /// height.
+        fn new(height: usize) -> Chain {
+            let blocks = (0..=height)
+                .map(|height| Block {
+                    hash: height,
+                    parent: height.saturating_sub(1),
+                    timestamp: time_of(height),
+                    transactions: vec![height as u32],
+                })
+                .collect();
+
+            Chain {
+                blocks: Mutex::new(blocks),
+                tip: Mutex::new(height),
+                lookups: AtomicUsize::new(0),
+            }
+        }
+
+        /// Mine a block on the tip holding a transaction equal to its
+        /// hash.
+        fn mine(&self) {
+            let mut blocks = self.blocks.lock().unwrap();
+            let mut tip = self.tip.lock().unwrap();
+            let hash = blocks.len();
+
+            blocks.push(Block {
+                hash,
+                parent: *tip,
+                timestamp: blocks[*tip].timestamp + BLOCK_INTERVAL,
+                transactions: vec![hash as u32],
+            });
+            *tip = hash;
+        }
+
+        /// Replace the latest block with one holding no transaction.
+        fn reorg_latest_block(&self) {
+            let mut blocks = self.blocks.lock().unwrap();
+            let mut tip = self.tip.lock().unwrap();
+            let hash = blocks.len();
+            let latest = blocks[*tip].clone();
+
+            blocks.push(Block {
+                hash,
+                transactions: vec![],
+                ..latest
+            });
+            *tip = hash;
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl LatestBlock for Chain {
+        type Block = Block;
+
+        async fn latest_block(&self) -> anyhow::Result<Self::Block> {
+            let tip = *self.tip.lock().unwrap();
+
+            Ok(self.blocks.lock().unwrap()[tip].clone())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl BlockByHash for Chain {
+        type Block = Block;
+        type BlockHash = usize;
+
+        async fn block_by_hash(&self, hash: Self::BlockHash) -> anyhow::Result<Self::Block> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+
+            Ok(self.blocks.lock().unwrap()[hash].clone())
+        }
+    }
+
+    /// The number of confirmations of a transaction, `None` if it is
+    /// not in any block of the chain mined since `since`.
+    async fn confirmations_of(
+        chain: &Chain,
+        transaction: &u32,
+        since: Timestamp,
+    ) -> anyhow::Result<Option<u32>> {
+        let inclusion = Inclusion::find(chain, transaction, since).await?;
+
+        Ok(inclusion.map(|inclusion| inclusion.confirmations()))
+    }
+
+    fn time_of(height: usize) -> u32 {
+        GENESIS_TIME + height as u32 * BLOCK_INTERVAL
+    }
+
+    fn start_of_swap(height: usize) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(i64::from(time_of(height)), 0)
+    }
+
+    #[tokio::test]
+    async fn count_confirmations_from_the_latest_block() {
+        let chain = Chain::new(10);
+        let since = Timestamp::from(0);
+
+        assert_eq!(confirmations_of(&chain, &10, since).await.unwrap(), Some(1));
+        assert_eq!(confirmations_of(&chain, &7, since).await.unwrap(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn transaction_mined_before_the_swap_is_not_found() {
+        let chain = Chain::new(10);
+
+        let confirmations = confirmations_of(&chain, &2, Timestamp::from(time_of(6)))
+            .await
+            .unwrap();
+
+        assert_eq!(confirmations, None);
+    }
+
+    #[tokio::test]
+    async fn transaction_in_block_stamped_shortly_before_the_swap_is_found() {
+        let chain = Chain::new(10);
+
+        let confirmations = confirmations_of(&chain, &5, Timestamp::from(time_of(6)))
+            .await
+            .unwrap();
+
+        assert_eq!(confirmations, Some(6));
+    }
+
+    #[tokio::test]
+    async fn buried_transaction_is_confirmed() {
+        let chain = Chain::new(10);
+
+        let finality = wait_for_confirmations(&chain, &5, 6, start_of_swap(0))
+            .await
+            .unwrap();
+
+        assert_eq!(finality, Finality::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn following_the_tip_only_walks_back_to_known_blocks() {
+        let chain = Chain::new(1_000);
+        let mut inclusion = Inclusion::find(&chain, &998, Timestamp::from(0))
+            .await
+            .unwrap()
+            .unwrap();
+        chain.mine();
+        chain.mine();
+        let lookups = chain.lookups.load(Ordering::SeqCst);
+
+        assert!(inclusion.follow_tip(&chain).await.unwrap());
+
+        assert_eq!(inclusion.confirmations(), 5);
+        assert_eq!(chain.lookups.load(Ordering::SeqCst) - lookups, 2);
+    }
+
+    #[tokio::test]
+    async fn search_finds_a_transaction_mined_later() {
+        let chain = Chain::new(10);
+        let mut search = Search::new(Timestamp::from(time_of(10)));
+
+        assert!(!search.mined(&chain, &11).await.unwrap());
+        chain.mine();
+
+        assert!(search.mined(&chain, &11).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn search_looks_at_every_block_once() {
+        let chain = Chain::new(10);
+        let mut search = Search::new(Timestamp::from(time_of(10)));
+        assert!(!search.mined(&chain, &100).await.unwrap());
+        let lookups = chain.lookups.load(Ordering::SeqCst);
+
+        assert!(!search.mined(&chain, &100).await.unwrap());
+        assert_eq!(chain.lookups.load(Ordering::SeqCst), lookups);
+
+        chain.mine();
+        chain.mine();
+        assert!(!search.mined(&chain, &100).await.unwrap());
+        assert_eq!(chain.lookups.load(Ordering::SeqCst) - lookups, 2);
+    }
+
+    #[tokio::test]
+    async fn reorged_transaction_is_detected() {
+        let chain = Chain::new(10);
+        chain.reorg_latest_block();
+
+        let finality = wait_for_confirmations(&chain, &10, 1, start_of_swap(5))
+            .await
+            .unwrap();
+
+        assert_eq!(finality, Finality::Reorged);
+    }
+
+    #[tokio::test]
+    async fn transaction_reorged_while_waiting_is_detected() {
+        let chain = Chain::new(10);
+        let mut inclusion = Inclusion::find(&chain, &10, Timestamp::from(0))
+            .await
+            .unwrap()
+            .unwrap();
+        chain.reorg_latest_block();
+        chain.mine();
+
+        assert!(!inclusion.follow_tip(&chain).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reorged_event_is_watched_for_again() {
+        let chain = Chain::new(10);
+        chain.reorg_latest_block();
+        let watched = &AtomicU32::new(0);
+
+        // The first event points to the reorged transaction, the
+        // second one to a transaction that is still in the chain.
+        let event =
+            watch_until_confirmed(
+                &chain,
+                2,
+                start_of_swap(5),
+                move || async move {
+                    Ok::<_, anyhow::Error>(10 - watched.fetch_add(1, Ordering::SeqCst))
+                },
+                |event| *event,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(event, 9);
+        assert_eq!(watched.load(Ordering::SeqCst), 2);
+    }
+
+    /// A geth node which answers with the tip of its main chain once,
+    /// then with the tip of a fork.
+    struct ForkingGeth {
+        blocks: Vec<ethereum::Block>,
+        main_tip: ethereum::Hash,
+        fork_tip: ethereum::Hash,
+        answered: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl LatestBlock for ForkingGeth {
+        type Block = ethereum::Block;
+
+        async fn latest_block(&self) -> anyhow::Result<Self::Block> {
+            let tip = match self.answered.fetch_add(1, Ordering::SeqCst) {
+                0 => self.main_tip,
+                _ => self.fork_tip,
+            };
+
+            self.block_by_hash(tip).await
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl BlockByHash for ForkingGeth {
+        type Block = ethereum::Block;
+        type BlockHash = ethereum::Hash;
+
+        async fn block_by_hash(&self, hash: Self::BlockHash) -> anyhow::Result<Self::Block> {
+            self.blocks
+                .iter()
+                .find(|block| block.hash == hash)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("unknown block {:?}", hash))
+        }
+    }
+
+    fn ethereum_hash(n: u64) -> ethereum::Hash {
+        serde_json::from_value(serde_json::json!(format!("0x{:064x}", n))).unwrap()
+    }
+
+    /// A block as returned by `eth_getBlockByHash` with full transactions.
+    fn ethereum_block(
+        number: u64,
+        hash: u64,
+        parent: u64,
+        transactions: &[u64],
+    ) -> ethereum::Block {
+        let quantity = |n: u64| format!("0x{:x}", n);
+        let block_hash = format!("0x{:064x}", hash);
+        let transactions = transactions
+            .iter()
+            .enumerate()
+            .map(|(index, transaction)| {
+                serde_json::json!({
+                    "blockHash": block_hash,
+                    "blockNumber": quantity(number),
+                    "from": "0x00a329c0648769a73afac7f9381e08fb43dbea72",
+                    "gas": "0x5208",
+                    "gasPrice": "0x3b9aca00",
+                    "hash": format!("0x{:064x}", transaction),
+                    "input": "0x",
+                    "nonce": quantity(index as u64),
+                    "to": "0x0000000000000000000000000000000000000001",
+                    "transactionIndex": quantity(index as u64),
+                    "value": "0x1",
+                    "v": "0x1b",
+                    "r": format!("0x{:064x}", 1),
+                    "s": format!("0x{:064x}", 1),
+                })
+            })
+            .collect::<Vec<_>>();
+        let zeroes = |bytes: usize| format!("0x{}", "0".repeat(bytes * 2));
+
+        serde_json::from_value(serde_json::json!({
+            "number": quantity(number),
+            "hash": block_hash,
+            "parentHash": format!("0x{:064x}", parent),
+            "nonce": zeroes(8),
+            "sha3Uncles": zeroes(32),
+            "logsBloom": zeroes(256),
+            "transactionsRoot": zeroes(32),
+            "stateRoot": zeroes(32),
+            "receiptsRoot": zeroes(32),
+            "miner": zeroes(20),
+            "difficulty": "0x2",
+            "totalDifficulty": quantity(2 * number + 1),
+            "extraData": "0x",
+            "size": "0x260",
+            "gasLimit": "0x6691b7",
+            "gasUsed": quantity(21_000 * transactions.len() as u64),
+            "timestamp": quantity(u64::from(time_of(number as usize))),
+            "transactions": transactions,
+            "uncles": [],
+        }))
+        .unwrap()
+    }
+
+    /// The transaction `0xf0` is mined in block `0xb1`. The fork drops
+    /// that block and mines the transaction anew in block `0xf2`.
+    fn forking_geth() -> ForkingGeth {
+        ForkingGeth {
+            blocks: vec![
+                ethereum_block(0, 0xb0, 0xaa, &[]),
+                ethereum_block(1, 0xb1, 0xb0, &[0xf0]),
+                ethereum_block(1, 0xf1, 0xb0, &[]),
+                ethereum_block(2, 0xf2, 0xf1, &[0xf0]),
+                ethereum_block(3, 0xf3, 0xf2, &[]),
+            ],
+            main_tip: ethereum_hash(0xb1),
+            fork_tip: ethereum_hash(0xf3),
+            answered: AtomicUsize::new(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn ethereum_transaction_reorged_by_a_fork_is_detected() {
+        let geth = forking_geth();
+
+        let finality = wait_for_confirmations(&geth, &ethereum_hash(0xf0), 2, start_of_swap(0))
+            .await
+            .unwrap();
+
+        assert_eq!(finality, Finality::Reorged);
+    }
+
+    #[tokio::test]
+    async fn ethereum_event_reorged_by_a_fork_is_watched_for_again() {
+        let geth = forking_geth();
+        let watched = &AtomicU32::new(0);
+
+        let event = watch_until_confirmed(
+            &geth,
+            2,
+            start_of_swap(0),
+            move || async move {
+                watched.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, anyhow::Error>(ethereum_hash(0xf0))
+            },
+            |event| *event,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(event, ethereum_hash(0xf0));
+        assert_eq!(watched.load(Ordering::SeqCst), 2);
+    }
+}
+
+#[cfg(all(test, feature = "test-docker"))]
+mod docker_tests {
+    use super::*;
+    use crate::{bitcoind, test_harness::BitcoinBlockchain};
+    use chrono::Utc;
+    use comit::btsieve::bitcoin::BitcoindConnector;
+    use testcontainers::clients;
+
+    #[tokio::test]
+    async fn transaction_of_invalidated_block_is_reorged() {
+        let tc_client = clients::Cli::default();
+        let blockchain = BitcoinBlockchain::new(&tc_client).unwrap();
+        let start_of_swap = (Utc::now() - chrono::Duration::minutes(10)).naive_utc();
+        blockchain.init().await.unwrap();
+
+        let client = bitcoind::Client::new(blockchain.node_url.clone());
+        let connector =
+            BitcoindConnector::new(blockchain.node_url.clone(), ::bitcoin::Network::Regtest)
+                .unwrap();
+        let address = client
+            .get_new_address("testwallet", None, None)
+            .await
+            .unwrap();
+        let txid = client
+            .send_to_address(
+                "testwallet",
+                address.clone(),
+                ::bitcoin::Amount::from_sat(100_000_000),
+            )
+            .await
+            .unwrap();
+        client.generate_to_address(1, address, None).await.unwrap();
+
+        let finality = wait_for_confirmations(&connector, &txid, 1, start_of_swap)
+            .await
+            .unwrap();
+        assert_eq!(finality, Finality::Confirmed);
+
+        let tip = client.get_best_block_hash().await.unwrap();
+        client.invalidate_block(&tip).await.unwrap();
+
+        let finality = wait_for_confirmations(&connector, &txid, 1, start_of_swap)
+            .await
+            .unwrap();
+        assert_eq!(finality, Finality::Reorged);
+    }
+}
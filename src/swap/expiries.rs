@@ -0,0 +1,95 @@
+use comit::Timestamp;
+use std::{convert::TryFrom, time::Duration};
+
+/// The time needed to get a transaction mined and confirmed on each
+/// chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SafetyMargins {
+    pub bitcoin: Duration,
+    pub ethereum: Duration,
+}
+
+/// The expiry of an HTLC and how long before it nectar has to act.
+#[derive(Clone, Copy, Debug)]
+pub struct Expiry {
+    pub timestamp: Timestamp,
+    pub margin: Duration,
+}
+
+impl Expiry {
+    /// Whether a transaction sent at `ledger_time` is still
+    /// confirmed before the expiry.
+    pub fn is_far_from(&self, ledger_time: Timestamp) -> bool {
+        add(ledger_time, self.margin) < self.timestamp
+    }
+}
+
+/// The expiries of both HTLCs of a swap.
+#[derive(Clone, Copy, Debug)]
+pub struct Expiries {
+    pub alpha: Expiry,
+    pub beta: Expiry,
+}
+
+impl Expiries {
+    /// Alice can redeem the beta HTLC up until its expiry, only then
+    /// does Bob learn the secret. He must still have enough time to
+    /// redeem the alpha HTLC before Alice can refund it.
+    pub fn alpha_outlasts_beta(&self) -> bool {
+        add(self.beta.timestamp, self.alpha.margin) <= self.alpha.timestamp
+    }
+}
+
+fn add(timestamp: Timestamp, duration: Duration) -> Timestamp {
+    let seconds = u32::try_from(duration.as_secs()).unwrap_or(u32::MAX);
+
+    Timestamp::from(u32::from(timestamp).saturating_add(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expiry(timestamp: u32, margin_secs: u64) -> Expiry {
+        Expiry {
+            timestamp: Timestamp::from(timestamp),
+            margin: Duration::from_secs(margin_secs),
+        }
+    }
+
+    #[test]
+    fn given_ledger_time_within_margin_then_expiry_is_not_far() {
+        let expiry = expiry(10_000, 3_600);
+
+        assert!(expiry.is_far_from(Timestamp::from(6_000)));
+        assert!(!expiry.is_far_from(Timestamp::from(6_400)));
+        assert!(!expiry.is_far_from(Timestamp::from(12_000)));
+    }
+
+    #[test]
+    fn given_alpha_expiring_too_soon_after_beta_then_alpha_does_not_outlast_beta() {
+        let expiries = Expiries {
+            alpha: expiry(20_000, 3_600),
+            beta: expiry(17_000, 600),
+        };
+
+        assert!(!expiries.alpha_outlasts_beta());
+    }
+
+    #[test]
+    fn given_alpha_expiring_long_after_beta_then_alpha_outlasts_beta() {
+        let expiries = Expiries {
+            alpha: expiry(20_000, 3_600),
+            beta: expiry(10_000, 600),
+        };
+
+        assert!(expiries.alpha_outlasts_beta());
+    }
+
+    #[test]
+    fn given_huge_margin_then_addition_saturates() {
+        let expiry = expiry(u32::MAX, u64::MAX);
+
+        assert!(!expiry.is_far_from(Timestamp::from(0)));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::swap::{hbit, herc20};
+use comit::ethereum;
+use std::fmt;
+
+/// The identifier of a transaction on either chain of a swap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransactionId {
+    Bitcoin(bitcoin::Txid),
+    Ethereum(ethereum::Hash),
+}
+
+impl From<hbit::Redeemed> for TransactionId {
+    fn from(event: hbit::Redeemed) -> Self {
+        TransactionId::Bitcoin(event.transaction.txid())
+    }
+}
+
+impl From<hbit::Refunded> for TransactionId {
+    fn from(event: hbit::Refunded) -> Self {
+        TransactionId::Bitcoin(event.transaction.txid())
+    }
+}
+
+impl From<herc20::Redeemed> for TransactionId {
+    fn from(event: herc20::Redeemed) -> Self {
+        TransactionId::Ethereum(event.transaction.hash)
+    }
+}
+
+impl From<herc20::Refunded> for TransactionId {
+    fn from(event: herc20::Refunded) -> Self {
+        TransactionId::Ethereum(event.transaction.hash)
+    }
+}
+
+/// How a swap ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwapOutcome {
+    /// Both HTLCs were redeemed.
+    Redeemed {
+        alpha_redeem: TransactionId,
+        beta_redeem: TransactionId,
+    },
+    /// At least one HTLC was refunded.
+    ///
+    /// A refund is `None` when it is up to the counterparty, as it is
+    /// not observed.
+    Refunded {
+        reason: Reason,
+        alpha_refund: Option<TransactionId>,
+        beta_refund: Option<TransactionId>,
+        /// Alice redeemed the beta HTLC but Bob did not redeem the
+        /// alpha HTLC in time.
+        beta_redeem: Option<TransactionId>,
+    },
+    /// A safety check failed before any funds were locked.
+    AbortedBeforeFunding { reason: Reason },
+}
+
+impl SwapOutcome {
+    pub fn is_redeemed(&self) -> bool {
+        match self {
+            SwapOutcome::Redeemed { .. } => true,
+            SwapOutcome::Refunded { .. } | SwapOutcome::AbortedBeforeFunding { .. } => false,
+        }
+    }
+}
+
+/// Why a swap did not go through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    AliceNotSafeToFund,
+    BobNotSafeToFund,
+    AliceNotSafeToRedeem,
+    BobDidNotRedeem,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Reason::AliceNotSafeToFund => "too close to the expiry for Alice to fund",
+            Reason::BobNotSafeToFund => "too close to the expiries for Bob to fund",
+            Reason::AliceNotSafeToRedeem => "too close to the expiry for Alice to redeem",
+            Reason::BobDidNotRedeem => "Bob did not redeem before the alpha expiry",
+        };
+
+        write!(f, "{}", reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_redeemed_swap_is_redeemed() {
+        let txid = TransactionId::Bitcoin(bitcoin::Txid::default());
+
+        assert!(SwapOutcome::Redeemed {
+            alpha_redeem: txid,
+            beta_redeem: txid,
+        }
+        .is_redeemed());
+        assert!(!SwapOutcome::Refunded {
+            reason: Reason::BobDidNotRedeem,
+            alpha_refund: Some(txid),
+            beta_refund: None,
+            beta_redeem: Some(txid),
+        }
+        .is_redeemed());
+        assert!(!SwapOutcome::AbortedBeforeFunding {
+            reason: Reason::AliceNotSafeToFund,
+        }
+        .is_redeemed());
+    }
+}
@@ -0,0 +1,489 @@
+use crate::database::{Database, InFlight, Step, SwapId};
+use crate::ethereum_wallet::{self, Sent};
+use crate::swap::{hbit, herc20, Expiries, SafeToFund, SafeToRedeem};
+use chrono::NaiveDateTime;
+use comit::Secret;
+use serde::{Deserialize, Serialize};
+use std::{future::Future, sync::Arc, time::Duration};
+
+/// How long to look on the blockchain for the event of a transaction
+/// sent before a restart, before handing over to the actor. The
+/// watch-only actors also wait for the event to be confirmed, which
+/// can take longer; the actor then completes the step with the same
+/// transaction.
+const LOOK_UP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The role played by a persisted actor in a swap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Alice,
+    Bob,
+}
+
+/// Which asset is exchanged for which in a swap, from Alice's
+/// point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Alice sells bitcoin for ERC20 tokens, see `hbit_herc20`.
+    HbitHerc20,
+    /// Alice sells ERC20 tokens for bitcoin, see `herc20_hbit`.
+    Herc20Hbit,
+}
+
+/// Records every completed step of a swap in the database.
+///
+/// If a step has already been recorded, e.g. because nectar was
+/// restarted in the middle of a swap, the action is not executed
+/// again: the resulting event is recovered from the blockchain using
+/// the watch-only version of the actor. Refunds are stored with their
+/// transaction instead, as the watch-only actors do not observe them.
+/// A refund sent before a restart is broadcast again by the wallet.
+///
+/// If the transaction of a step was sent but the step not recorded
+/// yet, its event is looked for on the blockchain first. The actor is
+/// only called if it is not found, and sends the same transaction
+/// again through the `Journal` of its wallets.
+#[derive(Clone, Debug)]
+pub struct Persisted<A, W> {
+    pub actor: A,
+    pub watch_only: W,
+    pub role: Role,
+    pub direction: Direction,
+    pub db: Arc<Database>,
+    pub swap_id: SwapId,
+}
+
+impl<A, W> Persisted<A, W> {
+    fn has_step(&self, step: Step) -> anyhow::Result<bool> {
+        self.db.has_step(&self.swap_id, step)
+    }
+
+    fn record_step(&self, step: Step) -> anyhow::Result<()> {
+        tracing::info!("swap {}: {:?}", self.swap_id, step);
+        self.db.record_step(&self.swap_id, step)
+    }
+
+    /// Look for the event of `step` with `watch` if its transaction was
+    /// sent before a restart, recording the step if it is found.
+    async fn look_up<E, F>(&self, step: Step, watch: F) -> anyhow::Result<Option<E>>
+    where
+        F: Future<Output = anyhow::Result<E>>,
+    {
+        if self.db.in_flight(&self.swap_id, step)?.is_none() {
+            return Ok(None);
+        }
+
+        match tokio::time::timeout(LOOK_UP_TIMEOUT, watch).await {
+            Ok(event) => {
+                let event = event?;
+                self.record_step(step)?;
+                Ok(Some(event))
+            }
+            Err(_elapsed) => Ok(None),
+        }
+    }
+
+    /// The step recorded once the actor has funded its HTLC.
+    fn own_fund_step(&self) -> Step {
+        match (self.role, self.direction) {
+            (Role::Alice, Direction::HbitHerc20) | (Role::Bob, Direction::Herc20Hbit) => {
+                Step::HbitFunded
+            }
+            (Role::Alice, Direction::Herc20Hbit) | (Role::Bob, Direction::HbitHerc20) => {
+                Step::Herc20Funded
+            }
+        }
+    }
+
+    /// The step recorded once Alice has redeemed, revealing the
+    /// secret.
+    fn alice_redeem_step(&self) -> Step {
+        match self.direction {
+            Direction::HbitHerc20 => Step::Herc20Redeemed,
+            Direction::Herc20Hbit => Step::HbitRedeemed,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> hbit::Fund for Persisted<A, W>
+where
+    A: hbit::Fund + Send + Sync,
+    W: hbit::Fund + Send + Sync,
+{
+    async fn fund(&self, params: &hbit::Params) -> anyhow::Result<hbit::CorrectlyFunded> {
+        if self.has_step(Step::HbitFunded)? {
+            return self.watch_only.fund(params).await;
+        }
+
+        if let Some(event) = self
+            .look_up(Step::HbitFunded, self.watch_only.fund(params))
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.fund(params).await?;
+        self.record_step(Step::HbitFunded)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> hbit::RedeemAsBob for Persisted<A, W>
+where
+    A: hbit::RedeemAsBob + Send + Sync,
+    W: hbit::RedeemAsBob + Send + Sync,
+{
+    async fn redeem<SC>(
+        &self,
+        params: &hbit::Params,
+        fund_event: hbit::CorrectlyFunded,
+        secret: Secret,
+        secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<hbit::Redeemed>
+    where
+        SC: bitcoin::secp256k1::Signing,
+    {
+        if self.has_step(Step::HbitRedeemed)? {
+            return self
+                .watch_only
+                .redeem(params, fund_event, secret, secp)
+                .await;
+        }
+
+        if let Some(event) = self
+            .look_up(
+                Step::HbitRedeemed,
+                self.watch_only.redeem(params, fund_event, secret, secp),
+            )
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.redeem(params, fund_event, secret, secp).await?;
+        self.record_step(Step::HbitRedeemed)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> hbit::RedeemAsAlice for Persisted<A, W>
+where
+    A: hbit::RedeemAsAlice + Send + Sync,
+    W: hbit::RedeemAsAlice + Send + Sync,
+{
+    async fn redeem<SC>(
+        &self,
+        params: &hbit::Params,
+        fund_event: hbit::CorrectlyFunded,
+        secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<hbit::Redeemed>
+    where
+        SC: bitcoin::secp256k1::Signing,
+    {
+        if self.has_step(Step::HbitRedeemed)? {
+            return self.watch_only.redeem(params, fund_event, secp).await;
+        }
+
+        if let Some(event) = self
+            .look_up(
+                Step::HbitRedeemed,
+                self.watch_only.redeem(params, fund_event, secp),
+            )
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.redeem(params, fund_event, secp).await?;
+        self.record_step(Step::HbitRedeemed)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> hbit::Refund for Persisted<A, W>
+where
+    A: hbit::Refund + Send + Sync,
+    W: Send + Sync,
+{
+    async fn refund<SC>(
+        &self,
+        params: &hbit::Params,
+        fund_event: hbit::CorrectlyFunded,
+        secp: &bitcoin::secp256k1::Secp256k1<SC>,
+    ) -> anyhow::Result<Option<hbit::Refunded>>
+    where
+        SC: bitcoin::secp256k1::Signing,
+    {
+        if let Some(event) = self.db.hbit_refund(&self.swap_id)? {
+            return Ok(Some(event));
+        }
+
+        let event = self.actor.refund(params, fund_event, secp).await?;
+        if let Some(event) = &event {
+            tracing::info!("swap {}: {:?}", self.swap_id, Step::HbitRefunded);
+            self.db.record_hbit_refund(&self.swap_id, event)?;
+        }
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> herc20::Deploy for Persisted<A, W>
+where
+    A: herc20::Deploy + Send + Sync,
+    W: herc20::Deploy + Send + Sync,
+{
+    async fn deploy(&self, params: &herc20::Params) -> anyhow::Result<herc20::Deployed> {
+        if self.has_step(Step::Herc20Deployed)? {
+            return self.watch_only.deploy(params).await;
+        }
+
+        if let Some(event) = self
+            .look_up(Step::Herc20Deployed, self.watch_only.deploy(params))
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.deploy(params).await?;
+        self.record_step(Step::Herc20Deployed)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> herc20::Fund for Persisted<A, W>
+where
+    A: herc20::Fund + Send + Sync,
+    W: herc20::Fund + Send + Sync,
+{
+    async fn fund(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<herc20::CorrectlyFunded> {
+        if self.has_step(Step::Herc20Funded)? {
+            return self.watch_only.fund(params, deploy_event).await;
+        }
+
+        if let Some(event) = self
+            .look_up(
+                Step::Herc20Funded,
+                self.watch_only.fund(params, deploy_event.clone()),
+            )
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.fund(params, deploy_event).await?;
+        self.record_step(Step::Herc20Funded)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> herc20::RedeemAsAlice for Persisted<A, W>
+where
+    A: herc20::RedeemAsAlice + Send + Sync,
+    W: herc20::RedeemAsAlice + Send + Sync,
+{
+    async fn redeem(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        if self.has_step(Step::Herc20Redeemed)? {
+            return self.watch_only.redeem(params, deploy_event).await;
+        }
+
+        if let Some(event) = self
+            .look_up(
+                Step::Herc20Redeemed,
+                self.watch_only.redeem(params, deploy_event.clone()),
+            )
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.redeem(params, deploy_event).await?;
+        self.record_step(Step::Herc20Redeemed)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> herc20::RedeemAsBob for Persisted<A, W>
+where
+    A: herc20::RedeemAsBob + Send + Sync,
+    W: herc20::RedeemAsBob + Send + Sync,
+{
+    async fn redeem(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+        secret: Secret,
+    ) -> anyhow::Result<herc20::Redeemed> {
+        if self.has_step(Step::Herc20Redeemed)? {
+            return self.watch_only.redeem(params, deploy_event, secret).await;
+        }
+
+        if let Some(event) = self
+            .look_up(
+                Step::Herc20Redeemed,
+                self.watch_only.redeem(params, deploy_event.clone(), secret),
+            )
+            .await?
+        {
+            return Ok(event);
+        }
+
+        let event = self.actor.redeem(params, deploy_event, secret).await?;
+        self.record_step(Step::Herc20Redeemed)?;
+
+        Ok(event)
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> herc20::Refund for Persisted<A, W>
+where
+    A: herc20::Refund + Send + Sync,
+    W: Send + Sync,
+{
+    async fn refund(
+        &self,
+        params: &herc20::Params,
+        deploy_event: herc20::Deployed,
+    ) -> anyhow::Result<Option<herc20::Refunded>> {
+        if let Some(event) = self.db.herc20_refund(&self.swap_id)? {
+            return Ok(Some(event));
+        }
+
+        let event = self.actor.refund(params, deploy_event).await?;
+        if let Some(event) = &event {
+            tracing::info!("swap {}: {:?}", self.swap_id, Step::Herc20Refunded);
+            self.db.record_herc20_refund(&self.swap_id, event)?;
+        }
+
+        Ok(event)
+    }
+}
+
+/// Once the actor has funded, the check that guarded the funding
+/// must not abort the swap on resume: that would leave the funds
+/// locked in the HTLC without ever refunding them.
+#[async_trait::async_trait]
+impl<A, W> SafeToFund for Persisted<A, W>
+where
+    A: SafeToFund + Send + Sync,
+    W: Send + Sync,
+{
+    async fn is_safe_to_fund(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        if self.has_step(self.own_fund_step())? {
+            return Ok(true);
+        }
+
+        self.actor.is_safe_to_fund(expiries).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<A, W> SafeToRedeem for Persisted<A, W>
+where
+    A: SafeToRedeem + Send + Sync,
+    W: Send + Sync,
+{
+    async fn is_safe_to_redeem(&self, expiries: Expiries) -> anyhow::Result<bool> {
+        if self.role == Role::Alice && self.has_step(self.alice_redeem_step())? {
+            return Ok(true);
+        }
+
+        self.actor.is_safe_to_redeem(expiries).await
+    }
+}
+
+/// Where the wallets of a swap record the transactions they send for a
+/// step before broadcasting them, see `InFlight`.
+#[derive(Clone, Debug)]
+pub struct Journal {
+    db: Arc<Database>,
+    swap_id: SwapId,
+}
+
+impl Journal {
+    pub fn new(db: Arc<Database>, swap_id: SwapId) -> Self {
+        Journal { db, swap_id }
+    }
+
+    pub fn in_flight(&self, step: Step) -> anyhow::Result<Option<InFlight>> {
+        self.db.in_flight(&self.swap_id, step)
+    }
+
+    pub fn record_in_flight(&self, step: Step, in_flight: InFlight) -> anyhow::Result<()> {
+        self.db.record_in_flight(&self.swap_id, step, in_flight)
+    }
+
+    pub fn start_of_swap(&self) -> anyhow::Result<NaiveDateTime> {
+        self.db.start_of_swap(&self.swap_id)
+    }
+}
+
+/// The Ethereum transactions of a step, nothing is recorded without a
+/// journal.
+#[derive(Clone, Copy, Debug)]
+pub struct EthereumJournal<'a> {
+    journal: Option<&'a Journal>,
+    step: Step,
+}
+
+impl<'a> EthereumJournal<'a> {
+    pub fn new(journal: Option<&'a Journal>, step: Step) -> Self {
+        EthereumJournal { journal, step }
+    }
+}
+
+impl ethereum_wallet::Journal for EthereumJournal<'_> {
+    fn sent(&self) -> anyhow::Result<Option<Sent>> {
+        let journal = match self.journal {
+            Some(journal) => journal,
+            None => return Ok(None),
+        };
+
+        match journal.in_flight(self.step)? {
+            Some(InFlight::Ethereum(sent)) => Ok(Some(sent)),
+            Some(InFlight::Bitcoin(_)) => anyhow::bail!(
+                "swap {}: {:?} is not an Ethereum step",
+                journal.swap_id,
+                self.step
+            ),
+            None => Ok(None),
+        }
+    }
+
+    fn record(&self, sent: &Sent) -> anyhow::Result<()> {
+        match self.journal {
+            Some(journal) => journal.record_in_flight(self.step, InFlight::Ethereum(sent.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn forget(&self) -> anyhow::Result<()> {
+        match self.journal {
+            Some(journal) => journal.db.forget_in_flight(&journal.swap_id, self.step),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use crate::bitcoind;
+use crate::{bitcoind, geth};
+use ::bitcoin::Amount;
+use comit::ethereum::Address;
 use reqwest::Url;
+use std::time::Duration;
 use testcontainers::{
     clients,
     images::coblox_bitcoincore::BitcoinCore,
@@ -7,6 +10,14 @@ use testcontainers::{
     Container, Docker, Image,
 };
 
+/// The bitcoind wallet holding the mined bitcoin.
+const TEST_WALLET: &str = "testwallet";
+
+/// A minimal ERC20 token, hand-assembled: its deployer gets 10^30
+/// tokens and only `balanceOf` and `transfer` are implemented, the
+/// latter emitting the standard `Transfer` event.
+const ERC20_TOKEN: &str = "6f0000000c9f2c9cd04674edea400000003355610084806100206000396000f360003560e01c806370a0823114610020578063a9059cbb1461002d57600080fd5b6004355460005260206000f35b602435335481811061007f578190033355600435805482019055600052600435337fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef60206000a3600160005260206000f35b600080fd";
+
 #[derive(Debug)]
 pub struct BitcoinBlockchain<'c> {
     _container: Container<'c, clients::Cli, BitcoinCore>,
@@ -36,13 +47,12 @@ impl<'c> BitcoinBlockchain<'c> {
     pub async fn init(&self) -> anyhow::Result<()> {
         let bitcoind_client = bitcoind::Client::new(self.node_url.clone());
 
-        let test_wallet_name = String::from("testwallet");
         bitcoind_client
-            .create_wallet(&test_wallet_name, None, None, "".into(), None)
+            .create_wallet(TEST_WALLET, None, None, "".into(), None)
             .await?;
 
         let test_address = bitcoind_client
-            .get_new_address(&test_wallet_name, None, None)
+            .get_new_address(TEST_WALLET, None, None)
             .await?;
 
         bitcoind_client
@@ -51,6 +61,45 @@ impl<'c> BitcoinBlockchain<'c> {
 
         Ok(())
     }
+
+    /// Send `amount` from the test wallet to `address` and mine the
+    /// transaction.
+    pub async fn mint(&self, address: ::bitcoin::Address, amount: Amount) -> anyhow::Result<()> {
+        let bitcoind_client = bitcoind::Client::new(self.node_url.clone());
+
+        bitcoind_client
+            .send_to_address(TEST_WALLET, address, amount)
+            .await?;
+        let test_address = bitcoind_client
+            .get_new_address(TEST_WALLET, None, None)
+            .await?;
+        bitcoind_client
+            .generate_to_address(1, test_address, None)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Mine a block every second in the background, for transactions
+    /// to be confirmed and for the median time past to follow the
+    /// clock, as needed to refund an HTLC.
+    pub async fn start_mining(&self) -> anyhow::Result<()> {
+        let bitcoind_client = bitcoind::Client::new(self.node_url.clone());
+        let test_address = bitcoind_client
+            .get_new_address(TEST_WALLET, None, None)
+            .await?;
+
+        tokio::spawn(async move {
+            loop {
+                let _ = bitcoind_client
+                    .generate_to_address(1, test_address.clone(), None)
+                    .await;
+                tokio::time::delay_for(Duration::from_secs(1)).await;
+            }
+        });
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -70,6 +119,7 @@ impl<'c> EthereumBlockchain<'c> {
                 String::from("--dev"), // TODO: Most definitely missing arguments, see comit-rs geth_instance.ts
                 String::from("--dev.period=1"),
                 String::from("--rpc"),
+                String::from("--rpcaddr=0.0.0.0"),
             ]);
 
         let container = client.run(geth_image);
@@ -83,4 +133,85 @@ impl<'c> EthereumBlockchain<'c> {
             node_url: url,
         })
     }
+
+    /// Send ether from the pre-funded developer account to `to` and
+    /// wait for the transfer to be mined.
+    pub async fn mint_ether(&self, to: Address, wei: u128) -> anyhow::Result<()> {
+        self.send_from_dev_account(geth::CallRequest {
+            to: Some(to),
+            value: Some(wei.into()),
+            ..geth::CallRequest::default()
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deploy `ERC20_TOKEN`, all the tokens go to the developer
+    /// account.
+    pub async fn deploy_erc20_token(&self) -> anyhow::Result<Address> {
+        let receipt = self
+            .send_from_dev_account(geth::CallRequest {
+                data: hex::decode(ERC20_TOKEN)?,
+                ..geth::CallRequest::default()
+            })
+            .await?;
+
+        receipt
+            .contract_address
+            .ok_or_else(|| anyhow::anyhow!("token deployment created no contract"))
+    }
+
+    /// Transfer tokens from the developer account to `to`.
+    pub async fn mint_erc20(
+        &self,
+        token_contract: Address,
+        to: Address,
+        quantity: u128,
+    ) -> anyhow::Result<()> {
+        let mut data = hex::decode("a9059cbb")?;
+        data.extend_from_slice(&[0u8; 12]);
+        data.extend_from_slice(to.as_bytes());
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&quantity.to_be_bytes());
+
+        self.send_from_dev_account(geth::CallRequest {
+            to: Some(token_contract),
+            data,
+            ..geth::CallRequest::default()
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn send_from_dev_account(
+        &self,
+        transaction: geth::CallRequest,
+    ) -> anyhow::Result<geth::TransactionReceipt> {
+        let geth_client = geth::Client::new(self.node_url.clone());
+
+        let dev_account = *geth_client
+            .accounts()
+            .await?
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("geth has no developer account"))?;
+        let hash = geth_client
+            .send_transaction(&geth::CallRequest {
+                from: Some(dev_account),
+                ..transaction
+            })
+            .await?;
+
+        loop {
+            if let Some(receipt) = geth_client.get_transaction_receipt(hash).await? {
+                if !receipt.is_successful()? {
+                    anyhow::bail!("transaction {:?} was reverted", hash)
+                }
+
+                return Ok(receipt);
+            }
+            tokio::time::delay_for(Duration::from_millis(200)).await;
+        }
+    }
 }